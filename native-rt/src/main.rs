@@ -4,6 +4,7 @@ use clap::Parser;
 
 use std::fs::File;
 use std::io::Read;
+use std::path::Path;
 
 use std::sync::{Arc, Mutex};
 use std::thread;
@@ -17,10 +18,11 @@ use rhai::Engine;
 use rhai_rand::RandomPackage;
 
 use rt::camera::Camera;
-use rt::geometry::{BVHNode, Hittable, HittableList, Sphere};
+use rt::geometry::{BVHNode, Hittable, HittableList, LightList, Object};
+use rt::import;
 use rt::material::Material;
 use rt::math::Vec3;
-use rt::{cast_ray, output_buffer};
+use rt::{cast_ray, object_from_dynamic, output_buffer, register_types};
 
 use image_writer::{write_pfm, write_png};
 
@@ -44,7 +46,7 @@ fn output_image(
     height: u32,
     samples: u32,
     camera: &Camera,
-    scene: Vec<Sphere>,
+    scene: Vec<Object>,
     skybox_scale: f32,
     threads: u32,
     output_path: &str,
@@ -60,7 +62,7 @@ fn output_image(
         let handle = thread::spawn(move || {
             let box_list: Vec<Box<dyn Hittable>> = thrd_scene
                 .iter()
-                .map(|s| Box::new(s.clone()) as Box<dyn Hittable>)
+                .map(|o| Box::new(o.clone()) as Box<dyn Hittable>)
                 .collect();
             let world: Box<dyn Hittable> = if box_list.len() > 10 {
                 Box::new(BVHNode::new(box_list))
@@ -117,7 +119,7 @@ pub fn output_window(
     width: usize,
     height: usize,
     camera: &Camera,
-    scene: Vec<Sphere>,
+    scene: Vec<Object>,
     skybox_scale: f32,
     threads: u32,
 ) {
@@ -155,13 +157,14 @@ pub fn output_window(
         let handle = thread::spawn(move || {
             let box_list: Vec<Box<dyn Hittable>> = thrd_scene
                 .iter()
-                .map(|s| Box::new(s.clone()) as Box<dyn Hittable>)
+                .map(|o| Box::new(o.clone()) as Box<dyn Hittable>)
                 .collect();
             let world: Box<dyn Hittable> = if box_list.len() > 10 {
                 Box::new(BVHNode::new(box_list))
             } else {
                 Box::new(HittableList::from_vec(box_list))
             };
+            let lights = LightList::from_hittable(world.as_ref());
 
             let mut rng = rand::thread_rng();
 
@@ -180,8 +183,9 @@ pub fn output_window(
                         // Jitter the ray by a random amount
                         let u = (x as f32 + rng.gen::<f32>()) / width as f32;
                         let v = ((height - y) as f32 + rng.gen::<f32>()) / height as f32;
-                        let ray = thrd_camera.get_ray(u, v);
-                        color += cast_ray(ray, &world, skybox_scale, 0);
+                        // And send it at a random time while the shutter is open
+                        let ray = thrd_camera.get_ray(u, v, rng.gen::<f32>());
+                        color += cast_ray(ray, &world, &lights, skybox_scale, 0);
 
                         // Acculumate colors
                         data[i] += color.x;
@@ -253,45 +257,29 @@ fn run_script(
     threads: u32,
 ) -> Result<(), Box<rhai::EvalAltResult>> {
     let mut engine = Engine::new();
-    engine
-        .build_type::<Vec3>()
-        .build_type::<Camera>()
-        .build_type::<Material>()
-        .build_type::<Sphere>()
-        .register_fn(
-            "render",
-            move |w: i64,
-                  h: i64,
-                  s: i64,
-                  c: Camera,
-                  scene: rhai::Array,
-                  skybox_scale: f32,
-                  p: &str| {
-                let mut list: Vec<Sphere> = Vec::new();
-                for sphere in scene.iter() {
-                    match sphere.clone().try_cast::<Sphere>() {
-                        Some(s) => list.push(s),
-                        None => (),
-                    }
-                }
-
-                if window {
-                    output_window(w as usize, h as usize, &c, list, skybox_scale, threads);
-                } else {
-                    output_image(
-                        w as u32,
-                        h as u32,
-                        s as u32,
-                        &c,
-                        list,
-                        skybox_scale,
-                        threads,
-                        p,
-                        format,
-                    );
-                }
-            },
-        );
+    register_types(&mut engine);
+    engine.register_fn(
+        "render",
+        move |w: i64, h: i64, s: i64, c: Camera, scene: rhai::Array, skybox_scale: f32, p: &str| {
+            let list: Vec<Object> = scene.iter().filter_map(object_from_dynamic).collect();
+
+            if window {
+                output_window(w as usize, h as usize, &c, list, skybox_scale, threads);
+            } else {
+                output_image(
+                    w as u32,
+                    h as u32,
+                    s as u32,
+                    &c,
+                    list,
+                    skybox_scale,
+                    threads,
+                    p,
+                    format,
+                );
+            }
+        },
+    );
 
     // Add RNG support
     let random = RandomPackage::new();
@@ -300,13 +288,73 @@ fn run_script(
     engine.eval::<()>(&script)
 }
 
+// glTF files can be rendered directly, without a script, using these settings
+const GLTF_WIDTH: u32 = 1200;
+const GLTF_SAMPLES: u32 = 100;
+const GLTF_DEFAULT_ASPECT: f32 = 2.0;
+
+fn render_gltf(
+    path: &str,
+    format: ImageFormat,
+    window: bool,
+    threads: u32,
+) -> Result<(), import::ImportError> {
+    let default_material = Material::new_lambertian(Vec3::new_uniform(0.5));
+    let scene = import::gltf::load_gltf(path, default_material)?;
+
+    let (camera, aspect) = match &scene.camera {
+        Some(camera) => {
+            let aspect = camera.aspect.unwrap_or(GLTF_DEFAULT_ASPECT);
+            (camera.to_camera(aspect), aspect)
+        }
+        None => {
+            return Err(import::ImportError::parse(
+                0,
+                "file has no perspective camera",
+            ));
+        }
+    };
+    let height = (GLTF_WIDTH as f32 / aspect).round() as u32;
+    let objects = vec![import::meshes_to_object(scene.meshes)];
+
+    if window {
+        output_window(
+            GLTF_WIDTH as usize,
+            height as usize,
+            &camera,
+            objects,
+            1.0,
+            threads,
+        );
+    } else {
+        // Write next to the working directory using the file's name
+        let output_path = Path::new(path)
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("gltf");
+        output_image(
+            GLTF_WIDTH,
+            height,
+            GLTF_SAMPLES,
+            &camera,
+            objects,
+            1.0,
+            threads,
+            output_path,
+            format,
+        );
+    }
+
+    Ok(())
+}
+
 #[derive(Parser, Debug)]
 #[command(name = "rt_weekend")]
 #[command(version = "1.0")]
 #[command(author = "fanciful-marmot")]
 #[command(about = "A ray tracer written in Rust", long_about = None)]
 struct Args {
-    /// .rhai file describing the scene to render
+    /// .rhai file describing the scene to render, or a .gltf/.glb file to render directly
     #[arg(short, long)]
     scene: String,
 
@@ -328,6 +376,18 @@ fn main() {
 
     let file_path = args.scene;
 
+    let extension = Path::new(&file_path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("");
+    if extension == "gltf" || extension == "glb" {
+        match render_gltf(&file_path, args.format, args.window, args.threads) {
+            Ok(()) => println!("Done!"),
+            Err(e) => println!("Failed: {}", e),
+        }
+        return;
+    }
+
     // Read the script file
     let mut script = String::new();
     let mut script_file = File::open(&file_path).expect("could not open script");
@@ -1,39 +1,537 @@
 use crate::math::{Ray, Vec3};
+use rand::Rng;
 use std::f32::consts::PI;
+use std::sync::Arc;
 
-#[derive(Copy, Clone, Debug)]
+// Shape of the lens opening. Out of focus highlights (bokeh) take this shape
+#[derive(Clone, Debug)]
+pub enum Aperture {
+    Circle,
+    // Regular polygon, as made by the blades of an iris. rotation is in degrees
+    Polygon { blades: u32, rotation: f32 },
+    // Closed outline with points inside the unit circle, e.g. a star or heart
+    Custom(Arc<Vec<(f32, f32)>>),
+}
+
+// Even-odd rule
+fn inside_outline(points: &[(f32, f32)], p: (f32, f32)) -> bool {
+    let mut inside = false;
+    let mut j = points.len() - 1;
+    for i in 0..points.len() {
+        let (a, b) = (points[i], points[j]);
+        if (a.1 > p.1) != (b.1 > p.1) && p.0 < a.0 + (p.1 - a.1) * (b.0 - a.0) / (b.1 - a.1) {
+            inside = !inside;
+        }
+        j = i;
+    }
+
+    inside
+}
+
+impl Aperture {
+    // Uniformly random point on the aperture, within the unit circle
+    pub fn sample(&self) -> (f32, f32) {
+        let mut rng = rand::thread_rng();
+        let mut in_square = || (2.0 * rng.gen::<f32>() - 1.0, 2.0 * rng.gen::<f32>() - 1.0);
+
+        match self {
+            Aperture::Circle => loop {
+                let p = in_square();
+                if p.0 * p.0 + p.1 * p.1 < 1.0 {
+                    break p;
+                }
+            },
+            Aperture::Polygon { blades, rotation } => {
+                // Every blade's triangle from the centre is the same size so pick one
+                // at random, then a point in it
+                let blades = (*blades).max(3);
+                let step = 2.0 * PI / blades as f32;
+                let a0 = rotation.to_radians() + rng.gen_range(0..blades) as f32 * step;
+                let a1 = a0 + step;
+
+                let (mut s, mut t) = (rng.gen::<f32>(), rng.gen::<f32>());
+                if s + t > 1.0 {
+                    (s, t) = (1.0 - s, 1.0 - t);
+                }
+                (s * a0.cos() + t * a1.cos(), s * a0.sin() + t * a1.sin())
+            }
+            Aperture::Custom(points) => {
+                if points.len() < 3 {
+                    return (0.0, 0.0);
+                }
+                // Give up on outlines too thin to ever hit
+                for _ in 0..1000 {
+                    let p = in_square();
+                    if inside_outline(points, p) {
+                        return p;
+                    }
+                }
+                (0.0, 0.0)
+            }
+        }
+    }
+}
+
+// How directions from the camera map onto the image
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Projection {
+    // The image plane is at the focus distance, where rays through the lens meet
+    Perspective {
+        half_width: f32,
+        half_height: f32,
+        focus_distance: f32,
+    },
+    // Parallel rays from a rectangle facing the view direction
+    Orthographic {
+        half_width: f32,
+        half_height: f32,
+    },
+    // Angle from the view direction grows linearly with distance from the centre.
+    // half_fov is the angle at the top and bottom edges, in radians
+    Fisheye {
+        half_fov: f32,
+        aspect: f32,
+    },
+    // Longitude across and latitude up the image, covering every direction
+    Equirectangular,
+}
+
+// How the two eyes of a stereo pair share one image
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum StereoLayout {
+    // Left eye on the left half
+    SideBySide,
+    // Left eye on the top half
+    OverUnder,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Stereo {
+    // Distance between the eyes
+    pub interocular: f32,
+    // Distance at which both eyes see the same point, so it appears at screen depth
+    pub convergence: f32,
+    pub layout: StereoLayout,
+}
+
+#[derive(Clone, Debug)]
 pub struct Camera {
-    bottom_left: Vec3,
-    horizontal: Vec3,
-    vertical: Vec3,
     origin: Vec3,
+    // Unit vectors across the image, up the image and out through its centre
+    right: Vec3,
+    up: Vec3,
+    forward: Vec3,
+    projection: Projection,
+    // Only perspective projections have a lens
+    lens_radius: f32,
+    aperture: Aperture,
+    shutter_open: f32,
+    shutter_close: f32,
+    stereo: Option<Stereo>,
 }
 
 impl Camera {
+    fn with_projection(
+        look_from: Vec3,
+        look_at: Vec3,
+        v_up: Vec3,
+        projection: Projection,
+    ) -> Camera {
+        let forward = (look_at - look_from).make_unit();
+        let right = forward.cross(&v_up).make_unit();
+        let up = right.cross(&forward);
+
+        Camera {
+            origin: look_from,
+            right,
+            up,
+            forward,
+            projection,
+            lens_radius: 0.0,
+            aperture: Aperture::Circle,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+            stereo: None,
+        }
+    }
+
+    // A pinhole camera, everything is in focus
     pub fn new(look_from: Vec3, look_at: Vec3, v_up: Vec3, v_fov: f32, aspect: f32) -> Camera {
+        Camera::new_thin_lens(look_from, look_at, v_up, v_fov, aspect, 0.0, 1.0)
+    }
+
+    // A camera with a lens aperture wide, things focus_distance away are sharp
+    // and blur the further they are from it
+    pub fn new_thin_lens(
+        look_from: Vec3,
+        look_at: Vec3,
+        v_up: Vec3,
+        v_fov: f32,
+        aspect: f32,
+        aperture: f32,
+        focus_distance: f32,
+    ) -> Camera {
         let half_theta = v_fov * PI / 360.0;
         let half_height = (half_theta).tan();
         let half_width = aspect * half_height;
 
-        let w = (look_from - look_at).make_unit();
-        let u = v_up.cross(&w).make_unit();
-        let v = w.cross(&u);
+        let mut camera = Camera::with_projection(
+            look_from,
+            look_at,
+            v_up,
+            Projection::Perspective {
+                half_width,
+                half_height,
+                focus_distance,
+            },
+        );
+        camera.lens_radius = aperture / 2.0;
+        camera
+    }
 
-        let origin = look_from.clone();
+    // Parallel projection of a view height units tall, nothing shrinks with distance
+    pub fn new_orthographic(
+        look_from: Vec3,
+        look_at: Vec3,
+        v_up: Vec3,
+        height: f32,
+        aspect: f32,
+    ) -> Camera {
+        let half_height = height / 2.0;
+        Camera::with_projection(
+            look_from,
+            look_at,
+            v_up,
+            Projection::Orthographic {
+                half_width: aspect * half_height,
+                half_height,
+            },
+        )
+    }
 
-        Camera {
-            bottom_left: origin - half_width * u - half_height * v - w,
-            horizontal: 2.0 * half_width * u,
-            vertical: 2.0 * half_height * v,
-            origin,
+    // Equidistant fisheye seeing fov degrees from the bottom of the image to the
+    // top. It can be over 180
+    pub fn new_fisheye(
+        look_from: Vec3,
+        look_at: Vec3,
+        v_up: Vec3,
+        fov: f32,
+        aspect: f32,
+    ) -> Camera {
+        Camera::with_projection(
+            look_from,
+            look_at,
+            v_up,
+            Projection::Fisheye {
+                half_fov: fov.to_radians() / 2.0,
+                aspect,
+            },
+        )
+    }
+
+    // 360 degree panorama centred on look_at. Render it twice as wide as it is tall
+    pub fn new_equirectangular(look_from: Vec3, look_at: Vec3, v_up: Vec3) -> Camera {
+        Camera::with_projection(look_from, look_at, v_up, Projection::Equirectangular)
+    }
+
+    pub fn projection(&self) -> Projection {
+        self.projection
+    }
+
+    // Shape of the aperture, it's a circle by default
+    pub fn with_aperture(mut self, aperture: Aperture) -> Camera {
+        self.aperture = aperture;
+        self
+    }
+
+    // Times the shutter is open between. Moving objects blur over this interval
+    pub fn with_shutter(mut self, open: f32, close: f32) -> Camera {
+        self.shutter_open = open;
+        self.shutter_close = close;
+        self
+    }
+
+    // Renders a stereo pair into one image. Each eye gets half of it, so the aspect
+    // given to the constructor should be that of one eye
+    pub fn with_stereo(
+        mut self,
+        interocular: f32,
+        convergence: f32,
+        layout: StereoLayout,
+    ) -> Camera {
+        self.stereo = Some(Stereo {
+            interocular,
+            convergence,
+            layout,
+        });
+        self
+    }
+
+    pub fn stereo(&self) -> Option<Stereo> {
+        self.stereo
+    }
+
+    // u and v run from 0 to 1 across and up the image. With stereo on that's the
+    // whole image, holding both eyes.
+    // shutter is how far through the exposure the ray is sent, from 0 to 1
+    pub fn get_ray(&self, u: f32, v: f32, shutter: f32) -> Ray {
+        match self.stereo.map(|stereo| stereo.layout) {
+            None => self.view_ray(0.0, u, v, shutter),
+            Some(StereoLayout::SideBySide) if u < 0.5 => {
+                self.get_eye_ray(Eye::Left, 2.0 * u, v, shutter)
+            }
+            Some(StereoLayout::SideBySide) => {
+                self.get_eye_ray(Eye::Right, 2.0 * u - 1.0, v, shutter)
+            }
+            Some(StereoLayout::OverUnder) if v >= 0.5 => {
+                self.get_eye_ray(Eye::Left, u, 2.0 * v - 1.0, shutter)
+            }
+            Some(StereoLayout::OverUnder) => self.get_eye_ray(Eye::Right, u, 2.0 * v, shutter),
         }
     }
 
-    pub fn get_ray(&self, u: f32, v: f32) -> Ray {
+    // Ray for one eye, u and v are across that eye's view
+    pub fn get_eye_ray(&self, eye: Eye, u: f32, v: f32, shutter: f32) -> Ray {
+        let side = match eye {
+            Eye::Left => -1.0,
+            Eye::Right => 1.0,
+        };
+        self.view_ray(side, u, v, shutter)
+    }
+
+    // side is -1 for the left eye, 1 for the right and 0 for the camera's centre
+    fn view_ray(&self, side: f32, u: f32, v: f32, shutter: f32) -> Ray {
+        let (x, y) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+        let (right, up, forward) = (self.right, self.up, self.forward);
+
+        // Ray from the centre, and the direction the eyes are apart in. Planar
+        // projections have directions 1 unit along forward, the others are unit length
+        let (center, direction, eye_axis) = match self.projection {
+            Projection::Perspective {
+                half_width,
+                half_height,
+                ..
+            } => (
+                self.origin,
+                x * half_width * right + y * half_height * up + forward,
+                right,
+            ),
+            Projection::Orthographic {
+                half_width,
+                half_height,
+            } => (
+                self.origin + x * half_width * right + y * half_height * up,
+                forward,
+                right,
+            ),
+            Projection::Fisheye { half_fov, aspect } => {
+                let x = x * aspect;
+                let theta = ((x * x + y * y).sqrt() * half_fov).min(PI);
+                let phi = y.atan2(x);
+                let (sin_theta, cos_theta) = theta.sin_cos();
+                (
+                    self.origin,
+                    sin_theta * phi.cos() * right
+                        + sin_theta * phi.sin() * up
+                        + cos_theta * forward,
+                    right,
+                )
+            }
+            Projection::Equirectangular => {
+                let longitude = x * PI;
+                let latitude = y * PI / 2.0;
+                let (sin_lon, cos_lon) = longitude.sin_cos();
+                let (sin_lat, cos_lat) = latitude.sin_cos();
+                // Omni-directional stereo, the eyes turn with the longitude so every
+                // direction around the horizon gets a stereo pair
+                (
+                    self.origin,
+                    cos_lat * sin_lon * right + sin_lat * up + cos_lat * cos_lon * forward,
+                    cos_lon * right - sin_lon * forward,
+                )
+            }
+        };
+
+        // Both eyes look at the same point at the convergence distance
+        let (eye, direction) = match self.stereo {
+            Some(stereo) if side != 0.0 => {
+                let eye = center + (side * stereo.interocular / 2.0) * eye_axis;
+                let target = center + stereo.convergence * direction;
+                (eye, target - eye)
+            }
+            _ => (center, direction),
+        };
+
+        let (origin, direction) = match self.projection {
+            Projection::Perspective { focus_distance, .. } if self.lens_radius > 0.0 => {
+                // Rays from anywhere on the lens meet again on the focus plane
+                let focus = eye + (focus_distance / direction.dot(&forward)) * direction;
+                let (lens_x, lens_y) = self.aperture.sample();
+                let origin = eye + self.lens_radius * (lens_x * right + lens_y * up);
+                (origin, focus - origin)
+            }
+            _ => (eye, direction),
+        };
+
         Ray {
-            origin: self.origin.clone(),
-            direction: ((self.bottom_left + u * self.horizontal + v * self.vertical) - self.origin)
-                .make_unit(),
+            origin,
+            direction: direction.make_unit(),
+            time: self.shutter_open + shutter * (self.shutter_close - self.shutter_open),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::assert_near;
+
+    #[test]
+    fn get_ray() {
+        let look_from = Vec3::new(0.0, 0.0, 5.0);
+        let look_at = Vec3::new_zeroes();
+        let v_up = Vec3::new(0.0, 1.0, 0.0);
+
+        // Pinholes send every ray from the same point
+        let camera = Camera::new(look_from, look_at, v_up, 90.0, 2.0).with_shutter(1.0, 3.0);
+        let ray = camera.get_ray(0.5, 0.5, 0.25);
+        assert_eq!(ray.origin, look_from);
+        assert_near(ray.direction, Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(ray.time, 1.5);
+        // 90 degrees vertically, twice as wide
+        let ray = camera.get_ray(1.0, 1.0, 0.0);
+        assert_near(ray.direction, Vec3::new(2.0, 1.0, -1.0).make_unit());
+
+        // Rays through the same pixel spread over the lens and meet on the focus plane
+        let camera = Camera::new_thin_lens(look_from, look_at, v_up, 90.0, 2.0, 1.0, 4.0);
+        for _ in 0..100 {
+            let ray = camera.get_ray(0.75, 0.5, 0.0);
+            assert!((ray.origin - look_from).length() <= 0.5);
+            assert_eq!(ray.origin.z, 5.0);
+            let t = -4.0 / ray.direction.z;
+            assert_near(ray.point_at_parameter(t), Vec3::new(4.0, 0.0, 1.0));
+        }
+    }
+
+    #[test]
+    fn projections() {
+        let look_from = Vec3::new(0.0, 0.0, 5.0);
+        let look_at = Vec3::new_zeroes();
+        let v_up = Vec3::new(0.0, 1.0, 0.0);
+
+        // Parallel rays from across a 4x2 rectangle
+        let camera = Camera::new_orthographic(look_from, look_at, v_up, 2.0, 2.0);
+        let ray = camera.get_ray(1.0, 0.0, 0.0);
+        assert_near(ray.origin, Vec3::new(2.0, -1.0, 5.0));
+        assert_near(ray.direction, Vec3::new(0.0, 0.0, -1.0));
+        assert!(matches!(
+            camera.projection(),
+            Projection::Orthographic { .. }
+        ));
+
+        // 180 degree fisheye sees straight up at the top and sideways past the edge
+        let camera = Camera::new_fisheye(look_from, look_at, v_up, 180.0, 2.0);
+        assert_near(
+            camera.get_ray(0.5, 0.5, 0.0).direction,
+            Vec3::new(0.0, 0.0, -1.0),
+        );
+        assert_near(
+            camera.get_ray(0.5, 1.0, 0.0).direction,
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        assert_near(
+            camera.get_ray(0.75, 0.5, 0.0).direction,
+            Vec3::new(1.0, 0.0, 0.0),
+        );
+
+        // Panoramas wrap all the way around
+        let camera = Camera::new_equirectangular(look_from, look_at, v_up);
+        let directions = [
+            ((0.5, 0.5), Vec3::new(0.0, 0.0, -1.0)),
+            ((0.75, 0.5), Vec3::new(1.0, 0.0, 0.0)),
+            ((0.25, 0.5), Vec3::new(-1.0, 0.0, 0.0)),
+            ((0.0, 0.5), Vec3::new(0.0, 0.0, 1.0)),
+            ((0.3, 1.0), Vec3::new(0.0, 1.0, 0.0)),
+        ];
+        for ((u, v), direction) in directions {
+            let ray = camera.get_ray(u, v, 0.0);
+            assert_eq!(ray.origin, look_from);
+            assert_near(ray.direction, direction);
+        }
+    }
+
+    #[test]
+    fn stereo() {
+        let look_from = Vec3::new(0.0, 0.0, 5.0);
+        let look_at = Vec3::new_zeroes();
+        let v_up = Vec3::new(0.0, 1.0, 0.0);
+
+        // Eyes 0.5 apart converging on the origin
+        let camera = Camera::new(look_from, look_at, v_up, 90.0, 1.0).with_stereo(
+            0.5,
+            5.0,
+            StereoLayout::SideBySide,
+        );
+        let left = camera.get_ray(0.25, 0.5, 0.0);
+        let right = camera.get_ray(0.75, 0.5, 0.0);
+        assert_near(left.origin, Vec3::new(-0.25, 0.0, 5.0));
+        assert_near(right.origin, Vec3::new(0.25, 0.0, 5.0));
+        assert_near(left.point_at_parameter(5.0f32.hypot(0.25)), look_at);
+        assert_near(right.point_at_parameter(5.0f32.hypot(0.25)), look_at);
+
+        // The left eye is on top when over-under
+        let camera = camera.with_stereo(0.5, 5.0, StereoLayout::OverUnder);
+        assert_near(camera.get_ray(0.5, 0.75, 0.0).origin, left.origin);
+        assert_near(camera.get_ray(0.5, 0.25, 0.0).origin, right.origin);
+
+        // Panorama eyes stay either side of every direction they look in
+        let camera = Camera::new_equirectangular(look_from, look_at, v_up).with_stereo(
+            0.5,
+            1000.0,
+            StereoLayout::OverUnder,
+        );
+        for u in [0.1, 0.3, 0.5, 0.8] {
+            let left = camera.get_eye_ray(Eye::Left, u, 0.5, 0.0);
+            let right = camera.get_eye_ray(Eye::Right, u, 0.5, 0.0);
+            let between = right.origin - left.origin;
+            assert!((between.length() - 0.5).abs() < 1e-4);
+            assert!(between.dot(&left.direction).abs() < 1e-3);
+            // Right is to the right when looking along the ray
+            assert!(between.cross(&left.direction).y > 0.0);
+        }
+    }
+
+    #[test]
+    fn aperture() {
+        // Hexagon with a flat top and bottom, so nothing is above sin(60)
+        let hexagon = Aperture::Polygon {
+            blades: 6,
+            rotation: 0.0,
+        };
+        let flat = (PI / 3.0).sin();
+        for _ in 0..1000 {
+            let (x, y) = hexagon.sample();
+            assert!(x * x + y * y <= 1.0 + 1e-5);
+            assert!(y.abs() <= flat + 1e-5);
+        }
+
+        // Outlines can be concave, nothing lands in the notch of this arrowhead
+        let arrow = Aperture::Custom(Arc::new(vec![
+            (0.0, 0.9),
+            (0.6, -0.6),
+            (0.0, -0.1),
+            (-0.6, -0.6),
+        ]));
+        for _ in 0..1000 {
+            let (x, y) = arrow.sample();
+            assert!(y < 0.9 && y > -0.6);
+            assert!(!(x.abs() < 0.1 && y < -0.2));
         }
     }
 }
@@ -1,5 +1,8 @@
 use crate::math::{Ray, Vec3};
 
+// Padding added by new_padded so flat shapes don't end up with a zero thickness box
+const PADDING: f32 = 0.0001;
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub struct AABB {
     pub min: Vec3,
@@ -11,6 +14,14 @@ impl AABB {
         AABB { min, max }
     }
 
+    pub fn new_padded(min: Vec3, max: Vec3) -> AABB {
+        let padding = Vec3::new_uniform(PADDING);
+        AABB {
+            min: min - padding,
+            max: max + padding,
+        }
+    }
+
     pub fn merge(box1: &AABB, box2: &AABB) -> AABB {
         let mut aabb = box1.clone();
         aabb.expand(box2);
@@ -19,6 +30,11 @@ impl AABB {
     }
 
     pub fn hit(&self, r: &Ray, t_range: (f32, f32)) -> bool {
+        self.clip(r, t_range).is_some()
+    }
+
+    // The part of t_range where the ray is inside the box, if there is one
+    pub fn clip(&self, r: &Ray, t_range: (f32, f32)) -> Option<(f32, f32)> {
         let mut t_range = t_range;
         for a in 0..3 {
             let inv_d = 1.0 / r.direction[a];
@@ -35,11 +51,11 @@ impl AABB {
             }
 
             if t_range.1 <= t_range.0 {
-                return false;
+                return None;
             }
         }
 
-        true
+        Some(t_range)
     }
 
     // Expands this AABB to contain the other
@@ -69,6 +85,7 @@ mod tests {
         let ray = Ray {
             origin: Vec3::new(0.0, 0.0, -2.0),
             direction: Vec3::new(0.0, 0.0, 1.0),
+            time: 0.0,
         };
         assert_eq!(aabb.hit(&ray, (0.0, 100.0)), true);
 
@@ -76,6 +93,7 @@ mod tests {
         let ray = Ray {
             origin: Vec3::new(0.0, 0.0, -2.0),
             direction: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
         };
         assert_eq!(aabb.hit(&ray, (0.0, 100.0)), false);
 
@@ -83,6 +101,7 @@ mod tests {
         let ray = Ray {
             origin: Vec3::new(0.0, 0.0, 0.0),
             direction: Vec3::new(0.0, 0.0, 1.0),
+            time: 0.0,
         };
         assert_eq!(aabb.hit(&ray, (0.0, 100.0)), true);
     }
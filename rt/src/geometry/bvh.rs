@@ -1,7 +1,7 @@
 use rand::Rng;
 use std::cmp::Ordering;
 
-use crate::geometry::{Hit, Hittable, AABB};
+use crate::geometry::{Hit, Hittable, HittableList, Light, AABB};
 use crate::math::Ray;
 
 pub struct BVHNode {
@@ -11,7 +11,25 @@ pub struct BVHNode {
 
 impl BVHNode {
     pub fn new(objects: Vec<Box<dyn Hittable>>) -> BVHNode {
-        let mut objects = objects;
+        // Unbounded objects (e.g. planes) can't be sorted into the tree.
+        // Keep them in a list next to it instead so they're always tested
+        let (mut objects, unbounded): (Vec<_>, Vec<_>) = objects
+            .into_iter()
+            .partition(|o| o.bounding_box().is_some());
+        if !unbounded.is_empty() {
+            let unbounded: Box<dyn Hittable> = Box::new(HittableList::from_vec(unbounded));
+            let children: (Box<dyn Hittable>, Option<Box<dyn Hittable>>) = if objects.is_empty() {
+                (unbounded, None)
+            } else {
+                (Box::new(BVHNode::new(objects)), Some(unbounded))
+            };
+
+            return BVHNode {
+                aabb: None,
+                children,
+            };
+        }
+
         let axis = rand::thread_rng().gen_range(0..3);
 
         let comparator = |a: &Box<dyn Hittable>, b: &Box<dyn Hittable>| {
@@ -107,4 +125,58 @@ impl Hittable for BVHNode {
             None => None,
         }
     }
+
+    fn lights(&self) -> Vec<Light> {
+        let mut lights = self.children.0.lights();
+        if let Some(right) = &self.children.1 {
+            lights.extend(right.lights());
+        }
+        lights
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::{Plane, Sphere};
+    use crate::material::grey;
+    use crate::math::Vec3;
+
+    #[test]
+    fn unbounded_children() {
+        let mat = grey();
+        let mut objects: Vec<Box<dyn Hittable>> = Vec::new();
+        for i in 0..4 {
+            objects.push(Box::new(Sphere::new(
+                Vec3::new(i as f32 * 3.0, 0.0, 0.0),
+                1.0,
+                mat.clone(),
+            )));
+        }
+        objects.push(Box::new(Plane::new(
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            mat,
+        )));
+
+        // Shouldn't panic and the tree as a whole is unbounded
+        let bvh = BVHNode::new(objects);
+        assert!(bvh.bounding_box().is_none());
+
+        // Plane is hit far away from the spheres
+        let ray = Ray {
+            origin: Vec3::new(100.0, 1.0, 100.0),
+            direction: Vec3::new(0.0, -1.0, 0.0),
+            time: 0.0,
+        };
+        assert_eq!(bvh.intersects_ray(&ray, (0.0, 100.0)).unwrap().t, 2.0);
+
+        // Sphere is hit in front of the plane
+        let ray = Ray {
+            origin: Vec3::new(3.0, 5.0, 0.0),
+            direction: Vec3::new(0.0, -1.0, 0.0),
+            time: 0.0,
+        };
+        assert_eq!(bvh.intersects_ray(&ray, (0.0, 100.0)).unwrap().t, 4.0);
+    }
 }
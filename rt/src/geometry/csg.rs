@@ -0,0 +1,271 @@
+use crate::geometry::{Hit, Hittable, Span, AABB};
+use crate::math::{Ray, Vec3};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CsgOperation {
+    Union,
+    Intersection,
+    // Everything in a that isn't in b
+    Difference,
+}
+
+impl CsgOperation {
+    fn inside(&self, in_a: bool, in_b: bool) -> bool {
+        match self {
+            CsgOperation::Union => in_a || in_b,
+            CsgOperation::Intersection => in_a && in_b,
+            CsgOperation::Difference => in_a && !in_b,
+        }
+    }
+}
+
+// A solid made by combining two others. Both should be closed surfaces so that the
+// ray's spans inside them are well defined
+#[derive(Clone)]
+pub struct Csg<A: Hittable, B: Hittable> {
+    a: A,
+    b: B,
+    operation: CsgOperation,
+    aabb: Option<AABB>,
+}
+
+impl<A: Hittable, B: Hittable> Csg<A, B> {
+    pub fn new(a: A, b: B, operation: CsgOperation) -> Csg<A, B> {
+        let aabb = match (operation, a.bounding_box(), b.bounding_box()) {
+            (CsgOperation::Union, Some(a), Some(b)) => Some(AABB::merge(a, b)),
+            (CsgOperation::Union, _, _) => None,
+            // Only the overlap of the two can be inside. Unbounded solids don't narrow it
+            (CsgOperation::Intersection, Some(a), Some(b)) => {
+                let min = Vec3::new(
+                    a.min.x.max(b.min.x),
+                    a.min.y.max(b.min.y),
+                    a.min.z.max(b.min.z),
+                );
+                let max = Vec3::new(
+                    a.max.x.min(b.max.x),
+                    a.max.y.min(b.max.y),
+                    a.max.z.min(b.max.z),
+                );
+                // Disjoint boxes leave nothing to hit, keep an empty box at min then
+                let max = Vec3::new(max.x.max(min.x), max.y.max(min.y), max.z.max(min.z));
+                Some(AABB::new(min, max))
+            }
+            (CsgOperation::Intersection, Some(bbox), None)
+            | (CsgOperation::Intersection, None, Some(bbox)) => Some(*bbox),
+            (CsgOperation::Intersection, None, None) => None,
+            (CsgOperation::Difference, a, _) => a.copied(),
+        };
+
+        Csg {
+            a,
+            b,
+            operation,
+            aabb,
+        }
+    }
+}
+
+// A point where the ray crosses the boundary of one of the operands
+struct Event<'a> {
+    t: f32,
+    from_a: bool,
+    entering: bool,
+    hit: Hit<'a>,
+}
+
+fn push_events<'a>(events: &mut Vec<Event<'a>>, spans: Vec<Span<'a>>, from_a: bool) {
+    for span in spans {
+        if let Some(hit) = span.enter {
+            events.push(Event {
+                t: hit.t,
+                from_a,
+                entering: true,
+                hit,
+            });
+        }
+        if let Some(hit) = span.exit {
+            events.push(Event {
+                t: hit.t,
+                from_a,
+                entering: false,
+                hit,
+            });
+        }
+    }
+}
+
+impl<A: Hittable, B: Hittable> Hittable for Csg<A, B> {
+    fn intersects_ray(&self, ray: &Ray, t_range: (f32, f32)) -> Option<Hit<'_>> {
+        // The first boundary is an exit if the ray starts inside
+        let span = self.spans(ray, t_range).into_iter().next()?;
+        span.enter.or(span.exit)
+    }
+
+    fn bounding_box(&self) -> Option<&AABB> {
+        self.aabb.as_ref()
+    }
+
+    fn spans(&self, ray: &Ray, t_range: (f32, f32)) -> Vec<Span<'_>> {
+        if let Some(aabb) = &self.aabb {
+            if !aabb.hit(ray, t_range) {
+                return Vec::new();
+            }
+        }
+
+        let a_spans = self.a.spans(ray, t_range);
+        let b_spans = self.b.spans(ray, t_range);
+
+        // Spans that start without an entry mean the ray starts inside
+        let starts_inside = |spans: &[Span]| spans.first().is_some_and(|s| s.enter.is_none());
+        let mut in_a = starts_inside(&a_spans);
+        let mut in_b = starts_inside(&b_spans);
+        let mut inside = self.operation.inside(in_a, in_b);
+
+        let mut events = Vec::new();
+        push_events(&mut events, a_spans, true);
+        push_events(&mut events, b_spans, false);
+        events.sort_by(|e1, e2| e1.t.total_cmp(&e2.t));
+
+        let mut spans = Vec::new();
+        let mut enter = None;
+        for event in events {
+            if event.from_a {
+                in_a = event.entering;
+            } else {
+                in_b = event.entering;
+            }
+
+            let now_inside = self.operation.inside(in_a, in_b);
+            if now_inside == inside {
+                continue;
+            }
+            inside = now_inside;
+
+            // Surfaces of b bound the result from the other side in a difference
+            let mut hit = event.hit;
+            if self.operation == CsgOperation::Difference && !event.from_a {
+                hit.normal = -hit.normal;
+                hit.shading_normal = -hit.shading_normal;
+            }
+
+            if inside {
+                enter = Some(hit);
+            } else {
+                spans.push(Span {
+                    enter: enter.take(),
+                    exit: Some(hit),
+                });
+            }
+        }
+
+        if inside {
+            spans.push(Span { enter, exit: None });
+        }
+
+        spans
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::{Cuboid, Sphere};
+    use crate::material::grey;
+    use crate::math::assert_near;
+
+    // Two unit spheres overlapping between x = -0.5 and 0.5
+    fn spheres() -> (Sphere, Sphere) {
+        let mat = grey();
+        (
+            Sphere::new(Vec3::new(-0.5, 0.0, 0.0), 1.0, mat.clone()),
+            Sphere::new(Vec3::new(0.5, 0.0, 0.0), 1.0, mat),
+        )
+    }
+
+    // Along the x axis from the left
+    fn ray() -> Ray {
+        Ray {
+            origin: Vec3::new(-5.0, 0.0, 0.0),
+            direction: Vec3::new(1.0, 0.0, 0.0),
+            time: 0.0,
+        }
+    }
+
+    #[test]
+    fn union() {
+        let (a, b) = spheres();
+        let union = Csg::new(a, b, CsgOperation::Union);
+
+        // One span from the far side of a to the far side of b
+        let spans = union.spans(&ray(), (0.0, 100.0));
+        assert_eq!(spans.len(), 1);
+        assert!((spans[0].enter.as_ref().unwrap().t - 3.5).abs() < 1e-4);
+        assert!((spans[0].exit.as_ref().unwrap().t - 6.5).abs() < 1e-4);
+
+        // Inner surfaces are skipped from inside
+        let hit = union.intersects_ray(&ray(), (4.0, 100.0)).unwrap();
+        assert!((hit.t - 6.5).abs() < 1e-4);
+
+        let aabb = union.bounding_box().unwrap();
+        assert_near(aabb.min, Vec3::new(-1.5, -1.0, -1.0));
+        assert_near(aabb.max, Vec3::new(1.5, 1.0, 1.0));
+    }
+
+    #[test]
+    fn intersection() {
+        // A lens
+        let (a, b) = spheres();
+        let lens = Csg::new(a, b, CsgOperation::Intersection);
+
+        // Enters through b's surface and leaves through a's
+        let hit = lens.intersects_ray(&ray(), (0.0, 100.0)).unwrap();
+        assert!((hit.t - 4.5).abs() < 1e-4);
+        assert_near(hit.normal, Vec3::new(-1.0, 0.0, 0.0));
+        let hit = lens.intersects_ray(&ray(), (5.0, 100.0)).unwrap();
+        assert!((hit.t - 5.5).abs() < 1e-4);
+        assert_near(hit.normal, Vec3::new(1.0, 0.0, 0.0));
+
+        // Misses outside the overlap
+        let ray = Ray {
+            origin: Vec3::new(-1.0, 0.0, 5.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        assert!(lens.intersects_ray(&ray, (0.0, 100.0)).is_none());
+
+        let aabb = lens.bounding_box().unwrap();
+        assert_near(aabb.min, Vec3::new(-0.5, -1.0, -1.0));
+        assert_near(aabb.max, Vec3::new(0.5, 1.0, 1.0));
+    }
+
+    #[test]
+    fn difference() {
+        // A unit cube with a ball taken out of the middle
+        let mat = grey();
+        let cube = Cuboid::new(Vec3::new_uniform(-1.0), Vec3::new_uniform(1.0), mat.clone());
+        let ball = Sphere::new(Vec3::new_zeroes(), 0.5, mat);
+        let hollow = Csg::new(cube, ball, CsgOperation::Difference);
+
+        // Two spans either side of the hole
+        let spans = hollow.spans(&ray(), (0.0, 100.0));
+        assert_eq!(spans.len(), 2);
+
+        // Leaving the cube into the hole, the normal points into the hole
+        let hit = hollow.intersects_ray(&ray(), (4.0, 100.0)).unwrap();
+        assert!((hit.t - 4.5).abs() < 1e-4);
+        assert_near(hit.normal, Vec3::new(1.0, 0.0, 0.0));
+
+        // Entering the cube again on the far side of the hole faces back into the hole
+        let hit = hollow.intersects_ray(&ray(), (5.0, 100.0)).unwrap();
+        assert!((hit.t - 5.5).abs() < 1e-4);
+        assert_near(hit.normal, Vec3::new(-1.0, 0.0, 0.0));
+
+        // Through a corner the hole isn't seen
+        let ray = Ray {
+            origin: Vec3::new(-5.0, 0.9, 0.9),
+            direction: Vec3::new(1.0, 0.0, 0.0),
+            time: 0.0,
+        };
+        assert_eq!(hollow.spans(&ray, (0.0, 100.0)).len(), 1);
+    }
+}
@@ -0,0 +1,384 @@
+use crate::geometry::triangle::{interpolate, intersect_triangle};
+use crate::geometry::{Hit, Hittable, AABB};
+use crate::material::Material;
+use crate::math::{Ray, Vec3};
+
+// A grid of height samples rendered as a surface of triangles, two per grid cell.
+// Rays are intersected by walking a min-max mip hierarchy over the cells, so only
+// cells whose height range the ray actually passes through get tested
+#[derive(Clone)]
+pub struct Heightfield {
+    // Row major, width samples along x by depth samples along z
+    heights: Vec<f32>,
+    normals: Vec<Vec3>,
+    width: usize,
+    depth: usize,
+    corner: Vec3,
+    // Size of a cell along x and z
+    cell_size: (f32, f32),
+    // Height range of the cells at each level. Level 0 is one entry per cell and
+    // each level above halves both dimensions
+    levels: Vec<MipLevel>,
+    pub material: Material,
+    aabb: AABB,
+}
+
+#[derive(Clone)]
+struct MipLevel {
+    width: usize,
+    depth: usize,
+    ranges: Vec<(f32, f32)>,
+}
+
+impl Heightfield {
+    // heights is row major with width samples per row and at least 2 rows.
+    // The grid covers size.x by size.z from corner, and heights are scaled by size.y
+    // and added to corner.y
+    pub fn new(
+        heights: &[f32],
+        width: usize,
+        corner: Vec3,
+        size: Vec3,
+        material: Material,
+    ) -> Heightfield {
+        let width = width.max(2);
+        let depth = (heights.len() / width).max(2);
+        let mut scaled = vec![corner.y; width * depth];
+        for (scaled, h) in scaled.iter_mut().zip(heights) {
+            *scaled += h * size.y;
+        }
+        let heights = scaled;
+
+        let cell_size = (size.x / (width - 1) as f32, size.z / (depth - 1) as f32);
+
+        // Smooth normals from the slope between neighbouring samples
+        let mut normals = Vec::with_capacity(width * depth);
+        for z in 0..depth {
+            for x in 0..width {
+                let h = |x: usize, z: usize| heights[z * width + x];
+                let (x0, x1) = (x.saturating_sub(1), (x + 1).min(width - 1));
+                let (z0, z1) = (z.saturating_sub(1), (z + 1).min(depth - 1));
+                let dx = (h(x1, z) - h(x0, z)) / ((x1 - x0) as f32 * cell_size.0);
+                let dz = (h(x, z1) - h(x, z0)) / ((z1 - z0) as f32 * cell_size.1);
+                normals.push(Vec3::new(-dx, 1.0, -dz).make_unit());
+            }
+        }
+
+        // Level 0 holds the range of each cell's 4 corners
+        let (cells_x, cells_z) = (width - 1, depth - 1);
+        let mut ranges = Vec::with_capacity(cells_x * cells_z);
+        for z in 0..cells_z {
+            for x in 0..cells_x {
+                let corners = [
+                    heights[z * width + x],
+                    heights[z * width + x + 1],
+                    heights[(z + 1) * width + x],
+                    heights[(z + 1) * width + x + 1],
+                ];
+                let min = corners.iter().fold(f32::MAX, |a, &b| a.min(b));
+                let max = corners.iter().fold(f32::MIN, |a, &b| a.max(b));
+                ranges.push((min, max));
+            }
+        }
+        let mut levels = vec![MipLevel {
+            width: cells_x,
+            depth: cells_z,
+            ranges,
+        }];
+
+        // Merge 2x2 blocks until there's a single node covering everything
+        while levels.last().is_some_and(|l| l.width > 1 || l.depth > 1) {
+            let below = levels.last().unwrap();
+            let (w, d) = (below.width.div_ceil(2), below.depth.div_ceil(2));
+            let mut ranges = vec![(f32::MAX, f32::MIN); w * d];
+            for z in 0..below.depth {
+                for x in 0..below.width {
+                    let (min, max) = below.ranges[z * below.width + x];
+                    let range = &mut ranges[(z / 2) * w + x / 2];
+                    *range = (range.0.min(min), range.1.max(max));
+                }
+            }
+            levels.push(MipLevel {
+                width: w,
+                depth: d,
+                ranges,
+            });
+        }
+
+        let (min_y, max_y) = levels.last().unwrap().ranges[0];
+        let aabb = AABB::new_padded(
+            Vec3::new(corner.x, min_y, corner.z),
+            Vec3::new(corner.x + size.x, max_y, corner.z + size.z),
+        );
+
+        Heightfield {
+            heights,
+            normals,
+            width,
+            depth,
+            corner,
+            cell_size,
+            levels,
+            material,
+            aabb,
+        }
+    }
+
+    fn position(&self, x: usize, z: usize) -> Vec3 {
+        Vec3::new(
+            self.corner.x + x as f32 * self.cell_size.0,
+            self.heights[z * self.width + x],
+            self.corner.z + z as f32 * self.cell_size.1,
+        )
+    }
+
+    // Bounds of node (x, z) at a mip level
+    fn node_bounds(&self, level: usize, x: usize, z: usize) -> AABB {
+        let mip = &self.levels[level];
+        let (min_y, max_y) = mip.ranges[z * mip.width + x];
+        let span = 1 << level;
+        let cells = (self.width - 1, self.depth - 1);
+
+        let x0 = x * span;
+        let z0 = z * span;
+        let x1 = ((x + 1) * span).min(cells.0);
+        let z1 = ((z + 1) * span).min(cells.1);
+        AABB::new_padded(
+            Vec3::new(
+                self.corner.x + x0 as f32 * self.cell_size.0,
+                min_y,
+                self.corner.z + z0 as f32 * self.cell_size.1,
+            ),
+            Vec3::new(
+                self.corner.x + x1 as f32 * self.cell_size.0,
+                max_y,
+                self.corner.z + z1 as f32 * self.cell_size.1,
+            ),
+        )
+    }
+
+    // Closest hit in a cell as t and the smooth normal, shrinking t_range to it
+    fn intersect_cell(
+        &self,
+        ray: &Ray,
+        x: usize,
+        z: usize,
+        t_range: &mut (f32, f32),
+    ) -> Option<(f32, Vec3)> {
+        let corners = [(x, z), (x, z + 1), (x + 1, z), (x + 1, z + 1)];
+        let p = corners.map(|(x, z)| self.position(x, z));
+        let n = corners.map(|(x, z)| self.normals[z * self.width + x]);
+
+        // Both triangles are wound so their geometric normals face up
+        let mut closest = None;
+        for [i0, i1, i2] in [[0, 1, 2], [2, 1, 3]] {
+            if let Some((t, b1, b2)) = intersect_triangle(ray, &p[i0], &p[i1], &p[i2], *t_range) {
+                t_range.1 = t;
+                closest = Some((t, interpolate(n[i0], n[i1], n[i2], b1, b2)));
+            }
+        }
+
+        closest
+    }
+
+    fn traverse(
+        &self,
+        ray: &Ray,
+        level: usize,
+        x: usize,
+        z: usize,
+        t_range: &mut (f32, f32),
+        closest: &mut Option<(f32, Vec3)>,
+    ) {
+        if level == 0 {
+            if let Some(hit) = self.intersect_cell(ray, x, z, t_range) {
+                *closest = Some(hit);
+            }
+            return;
+        }
+
+        // Visit the children the ray passes through, nearest first, so later ones
+        // can be skipped once something closer has been hit
+        let below = &self.levels[level - 1];
+        let mut children = Vec::with_capacity(4);
+        for (cx, cz) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let (cx, cz) = (x * 2 + cx, z * 2 + cz);
+            if cx >= below.width || cz >= below.depth {
+                continue;
+            }
+            if let Some((t_enter, _)) = self.node_bounds(level - 1, cx, cz).clip(ray, *t_range) {
+                children.push((t_enter, cx, cz));
+            }
+        }
+        children.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        for (t_enter, cx, cz) in children {
+            if t_enter < t_range.1 {
+                self.traverse(ray, level - 1, cx, cz, t_range, closest);
+            }
+        }
+    }
+}
+
+impl Hittable for Heightfield {
+    fn intersects_ray(&self, ray: &Ray, t_range: (f32, f32)) -> Option<Hit<'_>> {
+        if !self.aabb.hit(ray, t_range) {
+            return None;
+        }
+
+        let mut range = t_range;
+        let mut closest = None;
+        self.traverse(ray, self.levels.len() - 1, 0, 0, &mut range, &mut closest);
+        let (t, normal) = closest?;
+
+        // uv runs across the whole grid
+        let point = ray.point_at_parameter(t);
+        let size = (
+            self.cell_size.0 * (self.width - 1) as f32,
+            self.cell_size.1 * (self.depth - 1) as f32,
+        );
+        let uv = (
+            (point.x - self.corner.x) / size.0,
+            (point.z - self.corner.z) / size.1,
+        );
+
+        // Tangents follow u along x and v along z
+        let normal = normal.make_unit();
+        let along = |axis: Vec3| (axis - axis.dot(&normal) * normal).make_unit();
+
+        Some(Hit {
+            t,
+            point,
+            normal,
+            shading_normal: normal,
+            tangent: along(Vec3::new(1.0, 0.0, 0.0)),
+            bitangent: along(Vec3::new(0.0, 0.0, 1.0)),
+            uv,
+            vertex_color: None,
+            material: &self.material,
+        })
+    }
+
+    fn bounding_box(&self) -> Option<&AABB> {
+        Some(&self.aabb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::grey;
+    use crate::math::assert_near;
+
+    // 5x5 samples over [0, 4] on x and z, flat at 0 except a spike of height 2 in the middle
+    fn spike() -> Heightfield {
+        let mat = grey();
+        let mut heights = vec![0.0; 25];
+        heights[12] = 1.0;
+        Heightfield::new(
+            &heights,
+            5,
+            Vec3::new_zeroes(),
+            Vec3::new(4.0, 2.0, 4.0),
+            mat,
+        )
+    }
+
+    #[test]
+    fn intersects_ray() {
+        let field = spike();
+
+        // Straight down onto the flat part
+        let ray = Ray {
+            origin: Vec3::new(0.5, 5.0, 3.5),
+            direction: Vec3::new(0.0, -1.0, 0.0),
+            time: 0.0,
+        };
+        let hit = field.intersects_ray(&ray, (0.0, 100.0)).unwrap();
+        assert!((hit.t - 5.0).abs() < 1e-4);
+        assert_near(hit.normal, Vec3::new(0.0, 1.0, 0.0));
+        assert!((hit.uv.0 - 0.125).abs() < 1e-4 && (hit.uv.1 - 0.875).abs() < 1e-4);
+
+        // Straight down onto the tip of the spike
+        let ray = Ray {
+            origin: Vec3::new(2.0, 5.0, 2.0),
+            direction: Vec3::new(0.0, -1.0, 0.0),
+            time: 0.0,
+        };
+        let hit = field.intersects_ray(&ray, (0.0, 100.0)).unwrap();
+        assert!((hit.t - 3.0).abs() < 1e-4);
+
+        // Skimming along x at half the spike's height hits its side, not the ground
+        let ray = Ray {
+            origin: Vec3::new(-1.0, 1.0, 2.0),
+            direction: Vec3::new(1.0, 0.0, 0.0),
+            time: 0.0,
+        };
+        let hit = field.intersects_ray(&ray, (0.0, 100.0)).unwrap();
+        assert!((hit.t - 2.5).abs() < 1e-4);
+        assert!(hit.normal.x < 0.0 && hit.normal.y > 0.0);
+
+        // Above everything
+        let ray = Ray {
+            origin: Vec3::new(-1.0, 2.5, 2.0),
+            direction: Vec3::new(1.0, 0.0, 0.0),
+            time: 0.0,
+        };
+        assert!(field.intersects_ray(&ray, (0.0, 100.0)).is_none());
+
+        // Outside the grid
+        let ray = Ray {
+            origin: Vec3::new(5.0, 5.0, 2.0),
+            direction: Vec3::new(0.0, -1.0, 0.0),
+            time: 0.0,
+        };
+        assert!(field.intersects_ray(&ray, (0.0, 100.0)).is_none());
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        // Bumpy grid with odd dimensions so the mip levels have partial nodes
+        let mat = grey();
+        let (width, depth) = (7, 6);
+        let heights: Vec<f32> = (0..width * depth)
+            .map(|i| ((i * 37 % 11) as f32) / 10.0)
+            .collect();
+        let field = Heightfield::new(
+            &heights,
+            width,
+            Vec3::new(-1.0, 0.0, -1.0),
+            Vec3::new(3.0, 1.0, 2.0),
+            mat,
+        );
+
+        for i in 0..50 {
+            let f = i as f32 / 50.0;
+            let ray = Ray {
+                origin: Vec3::new(-2.0 + f, 2.0, -2.0 + 0.5 * f),
+                direction: Vec3::new(1.0, -0.4 - f * 0.3, 0.8),
+                time: 0.0,
+            };
+
+            let mut range = (0.0, 100.0);
+            let mut expected = None;
+            for z in 0..depth - 1 {
+                for x in 0..width - 1 {
+                    if let Some((t, _)) = field.intersect_cell(&ray, x, z, &mut range) {
+                        expected = Some(t);
+                    }
+                }
+            }
+
+            let t = field.intersects_ray(&ray, (0.0, 100.0)).map(|hit| hit.t);
+            assert_eq!(t, expected);
+        }
+    }
+
+    #[test]
+    fn bounding_box() {
+        let aabb = *spike().bounding_box().unwrap();
+        assert!(aabb.min.y < 0.0 && aabb.max.y > 2.0);
+        assert!(aabb.min.x <= 0.0 && aabb.max.x >= 4.0);
+        assert!(aabb.min.z <= 0.0 && aabb.max.z >= 4.0);
+    }
+}
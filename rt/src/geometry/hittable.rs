@@ -1,24 +1,107 @@
 use std::ops::Index;
 
-use crate::geometry::AABB;
+use crate::geometry::{Light, AABB};
 use crate::material::Material;
 use crate::math::{Ray, Vec3};
 
 pub struct Hit<'a> {
     pub t: f32, // t stands for time?
     pub point: Vec3,
+    // The true surface normal, pointing out of solids. Decides which side was hit
     pub normal: Vec3,
+    // The normal used for lighting, e.g. smoothed across a mesh or from a normal map
+    pub shading_normal: Vec3,
+    // Unit directions along which u and v increase, for normal and bump maps
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
+    pub uv: (f32, f32),
+    // Interpolated vertex colour for meshes that have them. Tints the material
+    pub vertex_color: Option<Vec3>,
     pub material: &'a Material,
 }
 
-pub trait Hittable {
+impl Hit<'_> {
+    // The shading normal flipped to the side of the surface the ray came from.
+    // Needed for single sided surfaces like triangles which can be hit from behind
+    pub fn facing_normal(&self, ray: &Ray) -> Vec3 {
+        if ray.direction.dot(&self.normal) > 0.0 {
+            -self.shading_normal
+        } else {
+            self.shading_normal
+        }
+    }
+}
+
+// Where a ray is inside a solid, from the hit where it enters to the hit where it leaves.
+// An end is None if the ray is already inside at the start of the t range, or still
+// inside at the end of it
+pub struct Span<'a> {
+    pub enter: Option<Hit<'a>>,
+    pub exit: Option<Hit<'a>>,
+}
+
+// Distance to step past a hit before looking for the next one along the same ray
+const SPAN_EPSILON: f32 = 0.0001;
+// Stops runaway loops on surfaces that keep reporting hits
+const MAX_SPAN_HITS: usize = 64;
+
+// Send + Sync so scenes can be shared between render threads
+pub trait Hittable: Send + Sync {
     fn intersects_ray(&self, ray: &Ray, t_range: (f32, f32)) -> Option<Hit>;
     fn bounding_box(&self) -> Option<&AABB>;
+
+    // All the spans of the ray inside this solid within t_range, nearest first.
+    // Used by CSG. By default the hits along the ray are found one at a time and the
+    // outward normal tells whether the ray is entering or leaving, so this only makes
+    // sense for closed surfaces (or planes, which bound a half space)
+    fn spans(&self, ray: &Ray, t_range: (f32, f32)) -> Vec<Span<'_>> {
+        let mut spans = Vec::new();
+        let mut enter = None;
+        let mut inside = false;
+        let mut range = t_range;
+
+        for _ in 0..MAX_SPAN_HITS {
+            let hit = match self.intersects_ray(ray, range) {
+                Some(hit) => hit,
+                None => break,
+            };
+            range.0 = hit.t + SPAN_EPSILON;
+
+            if hit.normal.dot(&ray.direction) < 0.0 {
+                // Entering. Keep the first of repeated entries, e.g. from grazing hits
+                if !inside {
+                    enter = Some(hit);
+                    inside = true;
+                }
+            } else if inside || spans.is_empty() {
+                // Leaving. An exit with no entry means the ray started inside
+                spans.push(Span {
+                    enter: enter.take(),
+                    exit: Some(hit),
+                });
+                inside = false;
+            }
+        }
+
+        if inside {
+            spans.push(Span { enter, exit: None });
+        }
+
+        spans
+    }
+
+    // Emissive shapes inside this hittable that can be sampled directly for lighting,
+    // in world space. Anything else is only lit when rays find it by chance
+    fn lights(&self) -> Vec<Light> {
+        Vec::new()
+    }
 }
 
 pub struct HittableList {
     list: Vec<Box<dyn Hittable>>,
     aabb: Option<AABB>,
+    // Set once an unbounded hittable is added. The list is then unbounded as well
+    unbounded: bool,
 }
 
 impl HittableList {
@@ -26,28 +109,24 @@ impl HittableList {
         HittableList {
             list: Vec::new(),
             aabb: None,
+            unbounded: false,
         }
     }
 
     pub fn from_vec(list: Vec<Box<dyn Hittable>>) -> HittableList {
-        let mut aabb: Option<AABB> = None;
-        for hittable in &list {
-            match hittable.bounding_box() {
-                Some(bbox) => match &mut aabb {
-                    Some(self_aabb) => self_aabb.expand(bbox),
-                    None => aabb = Some(bbox.clone()),
-                },
-                None => {}
-            }
+        let mut hittable_list = HittableList::with_capacity(list.len());
+        for hittable in list {
+            hittable_list.push(hittable);
         }
 
-        HittableList { list, aabb }
+        hittable_list
     }
 
     pub fn with_capacity(n: usize) -> HittableList {
         HittableList {
             list: Vec::with_capacity(n),
             aabb: None,
+            unbounded: false,
         }
     }
 
@@ -57,7 +136,7 @@ impl HittableList {
                 Some(self_aabb) => self_aabb.expand(aabb),
                 None => self.aabb = Some(aabb.clone()),
             },
-            None => {}
+            None => self.unbounded = true,
         }
         self.list.push(hittable);
     }
@@ -93,18 +172,29 @@ impl Hittable for HittableList {
     }
 
     fn bounding_box(&self) -> Option<&AABB> {
+        if self.unbounded {
+            return None;
+        }
+
         match &self.aabb {
             Some(aabb) => Some(&aabb),
             None => None,
         }
     }
+
+    fn lights(&self) -> Vec<Light> {
+        self.list
+            .iter()
+            .flat_map(|hittable| hittable.lights())
+            .collect()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     struct TestHittable {
-        aabb: AABB,
+        aabb: Option<AABB>,
     }
 
     impl Hittable for TestHittable {
@@ -113,7 +203,7 @@ mod tests {
         }
 
         fn bounding_box(&self) -> Option<&AABB> {
-            Some(&self.aabb)
+            self.aabb.as_ref()
         }
     }
 
@@ -122,10 +212,13 @@ mod tests {
         // Setup
         let bounds1 = AABB::new(Vec3::new_zeroes(), Vec3::new(1.0, 1.0, 1.0));
         let hittable1 = TestHittable {
-            aabb: bounds1.clone(),
+            aabb: Some(bounds1.clone()),
         };
         let hittable2 = TestHittable {
-            aabb: AABB::new(Vec3::new(-1.0, 0.0, -1.0), Vec3::new(1.0, 2.0, 1.0)),
+            aabb: Some(AABB::new(
+                Vec3::new(-1.0, 0.0, -1.0),
+                Vec3::new(1.0, 2.0, 1.0),
+            )),
         };
         let mut combined_aabb = hittable1.bounding_box().unwrap().clone();
         combined_aabb.expand(hittable2.bounding_box().unwrap());
@@ -141,5 +234,9 @@ mod tests {
         // Bounds should be the combination of both
         hit_list.push(Box::new(hittable2));
         assert_eq!(hit_list.bounding_box().unwrap(), &combined_aabb);
+
+        // Any unbounded object makes the whole list unbounded
+        hit_list.push(Box::new(TestHittable { aabb: None }));
+        assert!(hit_list.bounding_box().is_none());
     }
 }
@@ -0,0 +1,610 @@
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::geometry::mesh::SharedMesh;
+use crate::geometry::triangle::triangle_bounds;
+use crate::geometry::{Hit, Hittable, Quad, Sphere, Triangle, AABB};
+use crate::material::Material;
+use crate::math::{orthonormal_basis, Transform, Vec3};
+
+// Where an emissive shape is in world space. Only the shape matters here, the light given
+// off comes from the scene itself when a shadow ray hits it
+#[derive(Clone)]
+enum Shape {
+    Sphere {
+        center: Vec3,
+        radius: f32,
+    },
+    Quad {
+        q: Vec3,
+        u: Vec3,
+        v: Vec3,
+    },
+    Triangle([Vec3; 3]),
+    // Every face of a mesh, picked in proportion to its area through the running total in
+    // cdf. The vertices stay shared with the mesh and are moved into world space by
+    // transform as they're needed
+    Mesh {
+        mesh: Arc<SharedMesh>,
+        transform: Transform,
+        cdf: Vec<f32>,
+    },
+}
+
+// An emissive shape in world space that can be sampled directly from a point in the scene
+#[derive(Clone)]
+pub struct Light {
+    shape: Shape,
+    area: f32,
+    aabb: AABB,
+    // Address of the material the shape glows with. Hits only carry a reference to their
+    // material, so this is how a hit is matched back to its light
+    material: usize,
+}
+
+// Closer than this, a light is too small to sample reliably
+const MIN_DISTANCE: f32 = 1e-4;
+
+fn address(material: &Material) -> usize {
+    material as *const Material as usize
+}
+
+fn triangle_area([p0, p1, p2]: &[Vec3; 3]) -> f32 {
+    0.5 * (p1 - p0).cross(&(p2 - p0)).length()
+}
+
+impl Light {
+    pub fn sphere(sphere: &Sphere) -> Light {
+        Light::new(
+            Shape::Sphere {
+                center: sphere.center,
+                radius: sphere.radius,
+            },
+            address(&sphere.material),
+        )
+    }
+
+    pub fn quad(quad: &Quad) -> Light {
+        Light::new(
+            Shape::Quad {
+                q: quad.q,
+                u: quad.u,
+                v: quad.v,
+            },
+            address(&quad.material),
+        )
+    }
+
+    pub fn triangle(triangle: &Triangle) -> Light {
+        Light::new(
+            Shape::Triangle(triangle.vertices),
+            address(&triangle.material),
+        )
+    }
+
+    // All the faces of a mesh as one light
+    pub(crate) fn mesh(mesh: &Arc<SharedMesh>) -> Light {
+        Light::new(
+            Shape::Mesh {
+                mesh: Arc::clone(mesh),
+                transform: Transform::identity(),
+                cdf: Vec::new(),
+            },
+            address(&mesh.material),
+        )
+    }
+
+    // Works out the area and bounds of the shape, and the face areas of meshes
+    fn new(mut shape: Shape, material: usize) -> Light {
+        let (area, aabb) = match &mut shape {
+            Shape::Sphere { center, radius } => {
+                let extent = Vec3::new_uniform(*radius);
+                (
+                    4.0 * PI * *radius * *radius,
+                    AABB::new(*center - extent, *center + extent),
+                )
+            }
+            Shape::Quad { q, u, v } => {
+                let (q, u, v) = (*q, *u, *v);
+                let aabb = AABB::merge(
+                    &triangle_bounds(&q, &(q + u), &(q + v)),
+                    &triangle_bounds(&(q + u), &(q + v), &(q + u + v)),
+                );
+                (u.cross(&v).length(), aabb)
+            }
+            Shape::Triangle(vertices) => {
+                let [p0, p1, p2] = &*vertices;
+                (triangle_area(vertices), triangle_bounds(p0, p1, p2))
+            }
+            Shape::Mesh {
+                mesh,
+                transform,
+                cdf,
+            } => {
+                let mut total = 0.0;
+                let mut aabb: Option<AABB> = None;
+                *cdf = (0..mesh.data.indices.len())
+                    .map(|face| {
+                        let vertices = mesh_face(mesh, transform, face);
+                        let [p0, p1, p2] = &vertices;
+                        let bounds = triangle_bounds(p0, p1, p2);
+                        aabb = Some(match aabb {
+                            Some(aabb) => AABB::merge(&aabb, &bounds),
+                            None => bounds,
+                        });
+                        total += triangle_area(&vertices);
+                        total
+                    })
+                    .collect();
+                let empty = AABB::new(Vec3::new_zeroes(), Vec3::new_zeroes());
+                (total, aabb.unwrap_or(empty))
+            }
+        };
+
+        Light {
+            shape,
+            area,
+            aabb,
+            material,
+        }
+    }
+
+    // Surface area of the shape, which decides how often it's picked
+    pub fn area(&self) -> f32 {
+        self.area
+    }
+
+    // A unit direction from origin towards the light and the distance to the point it
+    // aims at, chosen with the density given by pdf. Spheres are sampled by the cone of
+    // directions they cover, everything else by area
+    pub fn sample(&self, origin: &Vec3, u1: f32, u2: f32) -> Option<(Vec3, f32)> {
+        let point = match &self.shape {
+            Shape::Sphere { center, radius } => {
+                let (axis, sin2_max, cos_max) = sphere_cone(center, *radius, origin)?;
+                let cos_theta = 1.0 - u1 * sin2_max / (1.0 + cos_max);
+                let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+                let phi = 2.0 * PI * u2;
+                let (t, b) = orthonormal_basis(&axis);
+                let direction =
+                    sin_theta * phi.cos() * t + sin_theta * phi.sin() * b + cos_theta * axis;
+
+                // The near side of the sphere along the direction
+                let oc = origin - center;
+                let half_b = oc.dot(&direction);
+                let c = oc.length_sq() - radius * radius;
+                let distance = -half_b - (half_b * half_b - c).max(0.0).sqrt();
+                return Some((direction, distance));
+            }
+            Shape::Quad { q, u, v } => *q + u1 * *u + u2 * *v,
+            Shape::Triangle(vertices) => triangle_point(vertices, u1, u2),
+            Shape::Mesh {
+                mesh,
+                transform,
+                cdf,
+            } => {
+                // u1 picks the face, then is stretched back over [0, 1] to pick the point
+                let target = u1 * self.area;
+                let face = cdf
+                    .partition_point(|&total| total <= target)
+                    .min(cdf.len().checked_sub(1)?);
+                let start = if face == 0 { 0.0 } else { cdf[face - 1] };
+                let face_area = cdf[face] - start;
+                let u1 = if face_area > 0.0 {
+                    ((target - start) / face_area).clamp(0.0, 1.0)
+                } else {
+                    0.5
+                };
+                triangle_point(&mesh_face(mesh, transform, face), u1, u2)
+            }
+        };
+
+        let to_light = point - origin;
+        let distance = to_light.length();
+        if distance < MIN_DISTANCE {
+            return None;
+        }
+        Some((to_light / distance, distance))
+    }
+
+    // Density over solid angle of sample choosing the direction from origin to hit,
+    // which has to be on this light
+    pub fn pdf(&self, origin: &Vec3, hit: &Hit) -> f32 {
+        if let Shape::Sphere { center, radius } = &self.shape {
+            return match sphere_cone(center, *radius, origin) {
+                Some((_, sin2_max, cos_max)) => (1.0 + cos_max) / (2.0 * PI * sin2_max),
+                None => 0.0,
+            };
+        }
+
+        // Area density turned into solid angle
+        let to_light = hit.point - origin;
+        let distance_sq = to_light.length_sq();
+        let cosine = hit.normal.dot(&to_light).abs() / distance_sq.sqrt();
+        if self.area > 0.0 && cosine > 0.0 {
+            distance_sq / (self.area * cosine)
+        } else {
+            0.0
+        }
+    }
+
+    // Whether hit is on this light
+    fn is_hit(&self, hit: &Hit) -> bool {
+        if address(hit.material) != self.material {
+            return false;
+        }
+
+        // The same material glows in several places when an object is instanced, so the
+        // hit also has to be inside the bounds of this copy
+        let slack = 1e-3 * (self.aabb.max - self.aabb.min).length() + 1e-4;
+        (0..3).all(|a| {
+            hit.point[a] >= self.aabb.min[a] - slack && hit.point[a] <= self.aabb.max[a] + slack
+        })
+    }
+
+    // The light moved into world space by transform. Spheres only stay spheres when the
+    // transform keeps angles, otherwise they're left to be found by chance
+    pub fn transformed(&self, transform: &Transform) -> Option<Light> {
+        let shape = match &self.shape {
+            Shape::Sphere { center, radius } => {
+                let axes = [
+                    Vec3::new(1.0, 0.0, 0.0),
+                    Vec3::new(0.0, 1.0, 0.0),
+                    Vec3::new(0.0, 0.0, 1.0),
+                ]
+                .map(|axis| transform.transform_vector(&axis));
+                let scale = axes[0].length();
+                let similar = axes
+                    .iter()
+                    .all(|a| (a.length() - scale).abs() < 1e-4 * scale)
+                    && axes[0].dot(&axes[1]).abs() < 1e-4 * scale * scale
+                    && axes[1].dot(&axes[2]).abs() < 1e-4 * scale * scale
+                    && axes[2].dot(&axes[0]).abs() < 1e-4 * scale * scale;
+                if !similar {
+                    return None;
+                }
+                Shape::Sphere {
+                    center: transform.transform_point(center),
+                    radius: scale * radius,
+                }
+            }
+            Shape::Quad { q, u, v } => Shape::Quad {
+                q: transform.transform_point(q),
+                u: transform.transform_vector(u),
+                v: transform.transform_vector(v),
+            },
+            Shape::Triangle(vertices) => {
+                Shape::Triangle(vertices.map(|p| transform.transform_point(&p)))
+            }
+            // The face areas change with the transform, so they're worked out again
+            Shape::Mesh {
+                mesh,
+                transform: inner,
+                ..
+            } => Shape::Mesh {
+                mesh: Arc::clone(mesh),
+                transform: *transform * *inner,
+                cdf: Vec::new(),
+            },
+        };
+
+        Some(Light::new(shape, self.material))
+    }
+}
+
+// A face of a mesh in world space
+fn mesh_face(mesh: &SharedMesh, transform: &Transform, face: usize) -> [Vec3; 3] {
+    mesh.data.indices[face].map(|i| transform.transform_point(&mesh.data.positions[i]))
+}
+
+// A point on a triangle, uniform over its area for uniform u1 and u2
+fn triangle_point([p0, p1, p2]: &[Vec3; 3], u1: f32, u2: f32) -> Vec3 {
+    // Folding the unit square keeps the points uniform over the triangle
+    let (b1, b2) = if u1 + u2 > 1.0 {
+        (1.0 - u1, 1.0 - u2)
+    } else {
+        (u1, u2)
+    };
+    *p0 + b1 * (p1 - p0) + b2 * (p2 - p0)
+}
+
+// The unit axis of the cone of directions from origin that hit the sphere, with the
+// squared sine and the cosine of its half angle. None from inside the sphere
+fn sphere_cone(center: &Vec3, radius: f32, origin: &Vec3) -> Option<(Vec3, f32, f32)> {
+    let to_center = center - origin;
+    let distance_sq = to_center.length_sq();
+    let radius_sq = radius * radius;
+    if distance_sq <= radius_sq {
+        return None;
+    }
+
+    let sin2_max = radius_sq / distance_sq;
+    let cos_max = (1.0 - sin2_max).max(0.0).sqrt();
+    Some((to_center / distance_sq.sqrt(), sin2_max, cos_max))
+}
+
+// A unit direction towards the light at index light, and the distance to the point on
+// it the direction aims at
+pub struct LightSample {
+    pub light: usize,
+    pub direction: Vec3,
+    pub distance: f32,
+}
+
+// All the lights in a scene. One is picked for each shadow ray, in proportion to its area
+pub struct LightList {
+    lights: Vec<Light>,
+    // Running total of the light areas
+    cdf: Vec<f32>,
+    // Indices of the lights glowing with each material, by its address
+    by_material: HashMap<usize, Vec<usize>>,
+}
+
+impl LightList {
+    // Lights without any area could never be hit, so they're left out
+    pub fn new(lights: Vec<Light>) -> LightList {
+        let lights: Vec<Light> = lights.into_iter().filter(|l| l.area > 0.0).collect();
+
+        let mut total = 0.0;
+        let cdf = lights
+            .iter()
+            .map(|light| {
+                total += light.area;
+                total
+            })
+            .collect();
+        let mut by_material: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (i, light) in lights.iter().enumerate() {
+            by_material.entry(light.material).or_default().push(i);
+        }
+
+        LightList {
+            lights,
+            cdf,
+            by_material,
+        }
+    }
+
+    // Collects the lights from every emissive shape in the scene
+    pub fn from_hittable(world: &dyn Hittable) -> LightList {
+        LightList::new(world.lights())
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    // A unit direction from origin towards one of the lights
+    pub fn sample(&self, origin: &Vec3) -> Option<LightSample> {
+        let total = *self.cdf.last()?;
+        let mut rng = rand::thread_rng();
+        let target = rng.gen::<f32>() * total;
+        let light = self
+            .cdf
+            .partition_point(|&t| t <= target)
+            .min(self.lights.len() - 1);
+
+        let (direction, distance) = self.lights[light].sample(origin, rng.gen(), rng.gen())?;
+        Some(LightSample {
+            light,
+            direction,
+            distance,
+        })
+    }
+
+    // Density of sample picking the light at index light and then the direction from
+    // origin to hit. 0 if hit isn't on that light
+    pub fn light_pdf(&self, light: usize, origin: &Vec3, hit: &Hit) -> f32 {
+        let total = self.cdf[self.cdf.len() - 1];
+        let light = &self.lights[light];
+        if light.is_hit(hit) {
+            light.area / total * light.pdf(origin, hit)
+        } else {
+            0.0
+        }
+    }
+
+    // Density of sample choosing the direction from origin to hit, whichever light
+    // it's on. Only the lights with the hit's material are looked at
+    pub fn pdf(&self, origin: &Vec3, hit: &Hit) -> f32 {
+        match self.by_material.get(&address(hit.material)) {
+            Some(lights) => lights
+                .iter()
+                .map(|&light| self.light_pdf(light, origin, hit))
+                .sum(),
+            None => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::{HittableList, MeshData, Transformed, TriangleMesh};
+    use crate::math::Ray;
+
+    fn emissive() -> Material {
+        Material::new_emissive(Vec3::new_uniform(1.0))
+    }
+
+    fn ray(origin: Vec3, direction: Vec3) -> Ray {
+        Ray {
+            origin,
+            direction,
+            time: 0.0,
+        }
+    }
+
+    // The pdf integrates to 1 over the directions that reach the light, and samples
+    // land on it at the distance they give
+    fn check_light(light: &Light, shape: &dyn Hittable, origin: Vec3) {
+        let mut rng = rand::thread_rng();
+        let n = 200000;
+        let mut total = 0.0;
+        for _ in 0..n {
+            let z = 1.0 - 2.0 * rng.gen::<f32>();
+            let phi = 2.0 * PI * rng.gen::<f32>();
+            let r = (1.0 - z * z).max(0.0).sqrt();
+            let direction = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+            if let Some(hit) = shape.intersects_ray(&ray(origin, direction), (0.0, f32::MAX)) {
+                assert!(light.is_hit(&hit));
+                total += light.pdf(&origin, &hit) * 4.0 * PI;
+            }
+        }
+        let integral = total / n as f32;
+        assert!((integral - 1.0).abs() < 0.05, "{}", integral);
+
+        for _ in 0..1000 {
+            let (direction, distance) = light.sample(&origin, rng.gen(), rng.gen()).unwrap();
+            assert!((direction.length() - 1.0).abs() < 1e-4);
+            let hit = shape
+                .intersects_ray(&ray(origin, direction), (0.0, f32::MAX))
+                .unwrap();
+            assert!((hit.t - distance).abs() < 1e-3 * distance);
+            assert!(light.pdf(&origin, &hit) > 0.0);
+        }
+    }
+
+    #[test]
+    fn sphere() {
+        let sphere = Sphere::new(Vec3::new(0.0, 3.0, 0.0), 1.0, emissive());
+        let light = Light::sphere(&sphere);
+        check_light(&light, &sphere, Vec3::new_zeroes());
+
+        // A sphere covering a quarter of the sky in cosine terms
+        let up = ray(Vec3::new_zeroes(), Vec3::new(0.0, 1.0, 0.0));
+        let hit = sphere.intersects_ray(&up, (0.0, f32::MAX)).unwrap();
+        let pdf = light.pdf(&Vec3::new_zeroes(), &hit);
+        let cos_max = (8.0f32 / 9.0).sqrt();
+        assert!((pdf - 1.0 / (2.0 * PI * (1.0 - cos_max))).abs() < 1e-2 * pdf);
+
+        // Nothing to sample from inside
+        assert!(light.sample(&Vec3::new(0.0, 3.0, 0.0), 0.5, 0.5).is_none());
+    }
+
+    #[test]
+    fn quad() {
+        let quad = Quad::xz_rect(-1.0, 1.0, -1.0, 1.0, 2.0, emissive());
+        let light = Light::quad(&quad);
+        check_light(&light, &quad, Vec3::new_zeroes());
+
+        // Straight up at the middle, the area density scaled by the squared distance
+        let up = ray(Vec3::new_zeroes(), Vec3::new(0.0, 1.0, 0.0));
+        let hit = quad.intersects_ray(&up, (0.0, f32::MAX)).unwrap();
+        assert!((light.pdf(&Vec3::new_zeroes(), &hit) - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn triangle() {
+        let triangle = Triangle::new(
+            Vec3::new(-1.0, 2.0, -1.0),
+            Vec3::new(1.0, 2.0, -1.0),
+            Vec3::new(0.0, 2.0, 1.0),
+            emissive(),
+        );
+        check_light(&Light::triangle(&triangle), &triangle, Vec3::new_zeroes());
+    }
+
+    #[test]
+    fn mesh() {
+        // A 2x2 square split into three triangles of different sizes, stretched to
+        // twice its depth
+        let mesh = Transformed::new(
+            TriangleMesh::new(
+                MeshData {
+                    positions: vec![
+                        Vec3::new(-1.0, 2.0, -1.0),
+                        Vec3::new(1.0, 2.0, -1.0),
+                        Vec3::new(1.0, 2.0, 1.0),
+                        Vec3::new(-1.0, 2.0, 1.0),
+                        Vec3::new(-0.5, 2.0, 1.0),
+                    ],
+                    indices: vec![[0, 1, 4], [1, 2, 4], [0, 4, 3]],
+                    ..MeshData::default()
+                },
+                emissive(),
+            ),
+            Transform::scale(Vec3::new(1.0, 1.0, 2.0)),
+        );
+        let lights = mesh.lights();
+        assert_eq!(lights.len(), 1);
+        assert!((lights[0].area() - 8.0).abs() < 1e-4);
+        check_light(&lights[0], &mesh, Vec3::new_zeroes());
+    }
+
+    #[test]
+    fn transformed() {
+        let light = Light::sphere(&Sphere::new(Vec3::new_zeroes(), 1.0, emissive()));
+        let moved = light
+            .transformed(
+                &(Transform::translate(Vec3::new(0.0, 3.0, 0.0))
+                    * Transform::scale(Vec3::new_uniform(0.5))),
+            )
+            .unwrap();
+        match &moved.shape {
+            Shape::Sphere { center, radius } => {
+                assert!((*center - Vec3::new(0.0, 3.0, 0.0)).length() < 1e-5);
+                assert!((radius - 0.5).abs() < 1e-5);
+            }
+            _ => panic!("expected a sphere"),
+        }
+
+        // Stretched spheres aren't spheres any more
+        assert!(light
+            .transformed(&Transform::scale(Vec3::new(2.0, 1.0, 1.0)))
+            .is_none());
+    }
+
+    #[test]
+    fn light_list() {
+        // Only emissive shapes become lights
+        let mut world = HittableList::new();
+        world.push(Box::new(Sphere::new(
+            Vec3::new(0.0, 3.0, 0.0),
+            1.0,
+            emissive(),
+        )));
+        world.push(Box::new(Sphere::new(
+            Vec3::new(0.0, -3.0, 0.0),
+            1.0,
+            Material::new_lambertian(Vec3::new_uniform(0.5)),
+        )));
+        world.push(Box::new(Transformed::new(
+            Quad::xy_rect(-1.0, 1.0, -1.0, 1.0, 0.0, emissive()),
+            Transform::translate(Vec3::new(0.0, 0.0, 4.0)),
+        )));
+        let lights = LightList::from_hittable(&world);
+        assert_eq!(lights.len(), 2);
+
+        // Lights are picked by area, so the sphere gets 4 pi / (4 pi + 4) of the samples
+        let origin = Vec3::new_zeroes();
+        let hit_along = |direction| {
+            world
+                .intersects_ray(&ray(origin, direction), (0.0, f32::MAX))
+                .unwrap()
+        };
+        let share = 4.0 * PI / (4.0 * PI + 4.0);
+        let up = hit_along(Vec3::new(0.0, 1.0, 0.0));
+        let sphere = Light::sphere(&Sphere::new(Vec3::new(0.0, 3.0, 0.0), 1.0, emissive()));
+        assert!((lights.pdf(&origin, &up) - share * sphere.pdf(&origin, &up)).abs() < 1e-4);
+        assert_eq!(lights.light_pdf(1, &origin, &up), 0.0);
+        assert!(lights.pdf(&origin, &hit_along(Vec3::new(0.0, 0.0, 1.0))) > 0.0);
+        assert_eq!(
+            lights.pdf(&origin, &hit_along(Vec3::new(0.0, -1.0, 0.0))),
+            0.0
+        );
+
+        let n = 10000;
+        let picks = (0..n)
+            .filter(|_| lights.sample(&origin).unwrap().light == 0)
+            .count();
+        assert!((picks as f32 / n as f32 - share).abs() < 0.03);
+        assert!(LightList::new(Vec::new()).sample(&origin).is_none());
+    }
+}
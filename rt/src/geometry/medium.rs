@@ -0,0 +1,129 @@
+use rand::Rng;
+
+use crate::geometry::{Hit, Hittable, AABB};
+use crate::material::Material;
+use crate::math::{Ray, Vec3};
+
+// A volume of uniform density filling a closed boundary, e.g. smoke or fog.
+// Rays scatter at a random distance inside it, more likely the denser it is.
+// The phase material decides where they go from there, usually isotropic
+#[derive(Clone)]
+pub struct ConstantMedium<H: Hittable> {
+    boundary: H,
+    neg_inv_density: f32,
+    pub phase: Material,
+}
+
+impl<H: Hittable> ConstantMedium<H> {
+    pub fn new(boundary: H, density: f32, phase: Material) -> ConstantMedium<H> {
+        ConstantMedium {
+            boundary,
+            neg_inv_density: -1.0 / density,
+            phase,
+        }
+    }
+}
+
+impl<H: Hittable> Hittable for ConstantMedium<H> {
+    fn intersects_ray(&self, ray: &Ray, t_range: (f32, f32)) -> Option<Hit<'_>> {
+        let length = ray.direction.length();
+        let mut rng = rand::thread_rng();
+
+        // Spans handle rays starting inside, as scattered ones do, and boundaries
+        // the ray passes in and out of more than once
+        for span in self.boundary.spans(ray, t_range) {
+            let t_enter = span.enter.map_or(t_range.0, |hit| hit.t);
+            let t_exit = span.exit.map_or(t_range.1, |hit| hit.t);
+
+            // Distance to the next scatter is exponentially distributed
+            let distance_inside = (t_exit - t_enter) * length;
+            let scatter_distance = self.neg_inv_density * (1.0 - rng.gen::<f32>()).ln();
+            if scatter_distance < distance_inside {
+                let t = t_enter + scatter_distance / length;
+                return Some(Hit {
+                    t,
+                    point: ray.point_at_parameter(t),
+                    // Arbitrary, there's no surface inside a volume
+                    normal: Vec3::new(1.0, 0.0, 0.0),
+                    shading_normal: Vec3::new(1.0, 0.0, 0.0),
+                    tangent: Vec3::new(0.0, 1.0, 0.0),
+                    bitangent: Vec3::new(0.0, 0.0, 1.0),
+                    uv: (0.0, 0.0),
+                    vertex_color: None,
+                    material: &self.phase,
+                });
+            }
+        }
+
+        None
+    }
+
+    fn bounding_box(&self) -> Option<&AABB> {
+        self.boundary.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::{Cuboid, Sphere};
+    use crate::material::grey;
+
+    fn fog(density: f32) -> ConstantMedium<Sphere> {
+        let boundary = Sphere::new(Vec3::new_zeroes(), 1.0, grey());
+        ConstantMedium::new(
+            boundary,
+            density,
+            Material::new_isotropic(Vec3::new_uniform(1.0)),
+        )
+    }
+
+    #[test]
+    fn intersects_ray() {
+        let ray = Ray {
+            origin: Vec3::new(-5.0, 0.0, 0.0),
+            direction: Vec3::new(2.0, 0.0, 0.0),
+            time: 0.0,
+        };
+
+        // Very dense media scatter right at the boundary
+        let dense = fog(1e6);
+        let hit = dense.intersects_ray(&ray, (0.0, 100.0)).unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-3);
+        assert!(matches!(hit.material, Material::Isotropic(_)));
+
+        // Very thin media are passed straight through
+        assert!(fog(1e-9).intersects_ray(&ray, (0.0, 100.0)).is_none());
+
+        // Every scatter is somewhere inside
+        let medium = fog(0.5);
+        for _ in 0..100 {
+            if let Some(hit) = medium.intersects_ray(&ray, (0.0, 100.0)) {
+                assert!(hit.t > 2.0 && hit.t < 3.0);
+            }
+        }
+
+        // Rays starting inside scatter before they leave
+        let ray = Ray {
+            origin: Vec3::new_zeroes(),
+            direction: Vec3::new(0.0, 1.0, 0.0),
+            time: 0.0,
+        };
+        let hit = dense.intersects_ray(&ray, (0.001, 100.0)).unwrap();
+        assert!(hit.t < 0.01);
+    }
+
+    #[test]
+    fn bounding_box() {
+        let mat = Material::new_isotropic(Vec3::new_uniform(1.0));
+        let boundary = Cuboid::new(
+            Vec3::new_uniform(-1.0),
+            Vec3::new(1.0, 2.0, 3.0),
+            mat.clone(),
+        );
+        let medium = ConstantMedium::new(boundary.clone(), 0.1, mat);
+        let aabb = medium.bounding_box().unwrap();
+        assert_eq!(aabb.min, boundary.bounding_box().unwrap().min);
+        assert_eq!(aabb.max, boundary.bounding_box().unwrap().max);
+    }
+}
@@ -0,0 +1,275 @@
+use std::sync::Arc;
+
+use crate::geometry::triangle::{
+    interpolate, intersect_triangle, tangent_frame, triangle_bounds, BARYCENTRIC_UVS,
+};
+use crate::geometry::{BVHNode, Hit, Hittable, HittableList, Light, AABB};
+use crate::material::Material;
+use crate::math::{Ray, Vec3};
+
+// Vertex data for an indexed triangle mesh.
+// normals, uvs and colors, when present, are indexed the same way as positions
+#[derive(Clone, Default)]
+pub struct MeshData {
+    pub positions: Vec<Vec3>,
+    pub normals: Option<Vec<Vec3>>,
+    pub uvs: Option<Vec<(f32, f32)>>,
+    pub colors: Option<Vec<Vec3>>,
+    pub indices: Vec<[usize; 3]>,
+}
+
+pub(crate) struct SharedMesh {
+    pub(crate) data: MeshData,
+    pub(crate) material: Material,
+}
+
+// A single face of a mesh. Only stores its index so the vertex data can be shared
+struct MeshTriangle {
+    mesh: Arc<SharedMesh>,
+    face: usize,
+    aabb: AABB,
+}
+
+impl Hittable for MeshTriangle {
+    fn intersects_ray(&self, ray: &Ray, t_range: (f32, f32)) -> Option<Hit<'_>> {
+        let data = &self.mesh.data;
+        let [i0, i1, i2] = data.indices[self.face];
+        let (p0, p1, p2) = (
+            &data.positions[i0],
+            &data.positions[i1],
+            &data.positions[i2],
+        );
+        let (t, b1, b2) = intersect_triangle(ray, p0, p1, p2, t_range)?;
+
+        // The geometric normal is turned to the same side as smoothed vertex normals
+        let mut normal = (p1 - p0).cross(&(p2 - p0)).make_unit();
+        let shading_normal = match &data.normals {
+            Some(normals) => interpolate(normals[i0], normals[i1], normals[i2], b1, b2).make_unit(),
+            None => normal,
+        };
+        if normal.dot(&shading_normal) < 0.0 {
+            normal = -normal;
+        }
+        let uvs = match &data.uvs {
+            Some(uvs) => [uvs[i0], uvs[i1], uvs[i2]],
+            None => BARYCENTRIC_UVS,
+        };
+        let (tangent, bitangent) = tangent_frame(p0, p1, p2, &uvs, &shading_normal);
+        let uv = match &data.uvs {
+            Some(uvs) => {
+                let uv = interpolate(
+                    Vec3::new(uvs[i0].0, uvs[i0].1, 0.0),
+                    Vec3::new(uvs[i1].0, uvs[i1].1, 0.0),
+                    Vec3::new(uvs[i2].0, uvs[i2].1, 0.0),
+                    b1,
+                    b2,
+                );
+                (uv.x, uv.y)
+            }
+            None => (b1, b2),
+        };
+        let vertex_color = data
+            .colors
+            .as_ref()
+            .map(|colors| interpolate(colors[i0], colors[i1], colors[i2], b1, b2));
+
+        Some(Hit {
+            t,
+            point: ray.point_at_parameter(t),
+            normal,
+            shading_normal,
+            tangent,
+            bitangent,
+            uv,
+            vertex_color,
+            material: &self.mesh.material,
+        })
+    }
+
+    fn bounding_box(&self) -> Option<&AABB> {
+        Some(&self.aabb)
+    }
+}
+
+// An indexed triangle mesh with a single material.
+// Faces are stored in their own BVH so the mesh can be placed in a scene as one object
+pub struct TriangleMesh {
+    root: Box<dyn Hittable>,
+    mesh: Arc<SharedMesh>,
+    face_count: usize,
+}
+
+impl TriangleMesh {
+    pub fn new(data: MeshData, material: Material) -> TriangleMesh {
+        let face_count = data.indices.len();
+        let mesh = Arc::new(SharedMesh { data, material });
+
+        let faces: Vec<Box<dyn Hittable>> = mesh
+            .data
+            .indices
+            .iter()
+            .enumerate()
+            .map(|(face, [i0, i1, i2])| {
+                let positions = &mesh.data.positions;
+                Box::new(MeshTriangle {
+                    mesh: Arc::clone(&mesh),
+                    face,
+                    aabb: triangle_bounds(&positions[*i0], &positions[*i1], &positions[*i2]),
+                }) as Box<dyn Hittable>
+            })
+            .collect();
+
+        let root: Box<dyn Hittable> = if faces.is_empty() {
+            Box::new(HittableList::new())
+        } else {
+            Box::new(BVHNode::new(faces))
+        };
+
+        TriangleMesh {
+            root,
+            mesh,
+            face_count,
+        }
+    }
+
+    pub fn face_count(&self) -> usize {
+        self.face_count
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn intersects_ray(&self, ray: &Ray, t_range: (f32, f32)) -> Option<Hit<'_>> {
+        self.root.intersects_ray(ray, t_range)
+    }
+
+    fn bounding_box(&self) -> Option<&AABB> {
+        self.root.bounding_box()
+    }
+
+    // An emissive mesh is a single light that shares the mesh data
+    fn lights(&self) -> Vec<Light> {
+        if self.mesh.material.is_emissive() {
+            vec![Light::mesh(&self.mesh)]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::LightList;
+    use crate::material::grey;
+
+    // Unit square in the xy plane made of two triangles
+    fn square(normals: Option<Vec<Vec3>>) -> TriangleMesh {
+        let mat = grey();
+        let data = MeshData {
+            positions: vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(1.0, 1.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            ],
+            normals,
+            uvs: Some(vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)]),
+            colors: None,
+            indices: vec![[0, 1, 2], [0, 2, 3]],
+        };
+
+        TriangleMesh::new(data, mat)
+    }
+
+    #[test]
+    fn intersects_ray() {
+        let mesh = square(None);
+        assert_eq!(mesh.face_count(), 2);
+
+        // Hit each of the two faces
+        for (x, y) in [(0.75, 0.25), (0.25, 0.75)] {
+            let ray = Ray {
+                origin: Vec3::new(x, y, 1.0),
+                direction: Vec3::new(0.0, 0.0, -1.0),
+                time: 0.0,
+            };
+            let hit = mesh.intersects_ray(&ray, (0.0, 100.0)).unwrap();
+            assert_eq!(hit.t, 1.0);
+            assert_eq!(hit.normal, Vec3::new(0.0, 0.0, 1.0));
+            assert!((hit.uv.0 - x).abs() < 1e-6);
+            assert!((hit.uv.1 - y).abs() < 1e-6);
+        }
+
+        // Miss outside the square
+        let ray = Ray {
+            origin: Vec3::new(1.5, 0.5, 1.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        assert!(mesh.intersects_ray(&ray, (0.0, 100.0)).is_none());
+    }
+
+    #[test]
+    fn vertex_normals() {
+        let n = Vec3::new(1.0, 0.0, 1.0).make_unit();
+        let mesh = square(Some(vec![n; 4]));
+
+        let ray = Ray {
+            origin: Vec3::new(0.5, 0.25, 1.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        let hit = mesh.intersects_ray(&ray, (0.0, 100.0)).unwrap();
+        assert!((hit.shading_normal - n).length() < 1e-6);
+        assert_eq!(hit.normal, Vec3::new(0.0, 0.0, 1.0));
+
+        // Tangents follow the uvs but lie flat against the shading normal
+        let tangent = Vec3::new(1.0, 0.0, -1.0).make_unit();
+        assert!((hit.tangent - tangent).length() < 1e-6);
+        assert!((hit.bitangent - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-6);
+    }
+
+    #[test]
+    fn bounding_box() {
+        let mesh = square(None);
+        let aabb = mesh.bounding_box().unwrap();
+        assert!(aabb.min.x < 0.0 && aabb.max.x > 1.0);
+        assert!(aabb.min.y < 0.0 && aabb.max.y > 1.0);
+        assert!(aabb.min.z < 0.0 && aabb.max.z > 0.0);
+
+        // Empty mesh has no bounds
+        let mat = grey();
+        let empty = TriangleMesh::new(MeshData::default(), mat);
+        assert!(empty.bounding_box().is_none());
+    }
+
+    #[test]
+    fn lights() {
+        assert!(square(None).lights().is_empty());
+
+        // A glowing strip of 1000 faces is a single light, sharing the mesh rather than
+        // copying it
+        let columns = 500;
+        let positions = (0..=columns)
+            .flat_map(|i| [Vec3::new(i as f32, 0.0, 0.0), Vec3::new(i as f32, 1.0, 0.0)])
+            .collect();
+        let indices = (0..columns)
+            .flat_map(|i| [[2 * i, 2 * i + 2, 2 * i + 3], [2 * i, 2 * i + 3, 2 * i + 1]])
+            .collect();
+        let glowing = TriangleMesh::new(
+            MeshData {
+                positions,
+                indices,
+                ..MeshData::default()
+            },
+            Material::new_emissive(Vec3::new_uniform(1.0)),
+        );
+        assert_eq!(glowing.face_count(), 1000);
+        assert_eq!(LightList::from_hittable(&glowing).len(), 1);
+
+        let lights = glowing.lights();
+        assert!((lights[0].area() - columns as f32).abs() < 1e-2);
+        // One reference for the mesh, one for each face and one for the light
+        assert_eq!(Arc::strong_count(&glowing.mesh), 1002);
+    }
+}
@@ -1,11 +1,40 @@
 mod aabb;
 mod bvh;
+mod csg;
+mod heightfield;
 mod hittable;
+mod light;
+mod medium;
+mod mesh;
+mod object;
+mod plane;
+mod quad;
+mod quadric;
+mod sdf;
 mod sphere;
+mod torus;
+mod transformed;
+mod triangle;
+mod volume;
 
 pub use aabb::AABB;
 pub use bvh::BVHNode;
+pub use csg::{Csg, CsgOperation};
+pub use heightfield::Heightfield;
 pub use hittable::Hit;
 pub use hittable::Hittable;
 pub use hittable::HittableList;
-pub use sphere::Sphere;
+pub use hittable::Span;
+pub use light::{Light, LightList, LightSample};
+pub use medium::ConstantMedium;
+pub use mesh::{MeshData, TriangleMesh};
+pub use object::Object;
+pub use plane::{Disk, Plane};
+pub use quad::{Cuboid, Quad};
+pub use quadric::{Capsule, Cone, Cylinder};
+pub use sdf::{Sdf, SdfObject};
+pub use sphere::{MovingSphere, Sphere};
+pub use torus::Torus;
+pub use transformed::Transformed;
+pub use triangle::Triangle;
+pub use volume::{VoxelGrid, VoxelVolume};
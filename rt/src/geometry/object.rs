@@ -0,0 +1,38 @@
+use std::sync::Arc;
+
+use crate::geometry::{Hit, Hittable, Light, Span, AABB};
+use crate::math::Ray;
+
+// A cheaply clonable handle to any hittable.
+// Used for objects built outside of scripts (e.g. loaded models) so they can be
+// passed around by scripts and shared between render threads
+#[derive(Clone)]
+pub struct Object {
+    hittable: Arc<dyn Hittable>,
+}
+
+impl Object {
+    pub fn new(hittable: impl Hittable + 'static) -> Object {
+        Object {
+            hittable: Arc::new(hittable),
+        }
+    }
+}
+
+impl Hittable for Object {
+    fn intersects_ray(&self, ray: &Ray, t_range: (f32, f32)) -> Option<Hit<'_>> {
+        self.hittable.intersects_ray(ray, t_range)
+    }
+
+    fn bounding_box(&self) -> Option<&AABB> {
+        self.hittable.bounding_box()
+    }
+
+    fn spans(&self, ray: &Ray, t_range: (f32, f32)) -> Vec<Span<'_>> {
+        self.hittable.spans(ray, t_range)
+    }
+
+    fn lights(&self) -> Vec<Light> {
+        self.hittable.lights()
+    }
+}
@@ -0,0 +1,220 @@
+use std::f32::consts::PI;
+
+use crate::geometry::quadric::disk_extent;
+use crate::geometry::{Hit, Hittable, AABB};
+use crate::material::Material;
+use crate::math::{orthonormal_basis, Ray, Vec3};
+
+// Intersects the plane through point with unit normal, returning t
+fn intersect_plane(ray: &Ray, point: &Vec3, normal: &Vec3, t_range: (f32, f32)) -> Option<f32> {
+    let denom = normal.dot(&ray.direction);
+    // Ray is parallel to the plane
+    if denom.abs() < 1e-8 {
+        return None;
+    }
+
+    let t = (point - ray.origin).dot(normal) / denom;
+    if t > t_range.0 && t < t_range.1 {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+// An infinite plane. It has no bounding box so it's never culled by a BVH
+#[derive(Clone)]
+pub struct Plane {
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub material: Material,
+    tangent: Vec3,
+    bitangent: Vec3,
+}
+
+impl Plane {
+    pub fn new(point: Vec3, normal: Vec3, material: Material) -> Plane {
+        let normal = normal.make_unit();
+        let (tangent, bitangent) = orthonormal_basis(&normal);
+        Plane {
+            point,
+            normal,
+            material,
+            tangent,
+            bitangent,
+        }
+    }
+}
+
+impl Hittable for Plane {
+    fn intersects_ray(&self, ray: &Ray, t_range: (f32, f32)) -> Option<Hit<'_>> {
+        let t = intersect_plane(ray, &self.point, &self.normal, t_range)?;
+        let point = ray.point_at_parameter(t);
+
+        // uv is the distance along the plane from point, so textures tile every unit
+        let planar = point - self.point;
+
+        Some(Hit {
+            t,
+            point,
+            normal: self.normal,
+            shading_normal: self.normal,
+            tangent: self.tangent,
+            bitangent: self.bitangent,
+            uv: (planar.dot(&self.tangent), planar.dot(&self.bitangent)),
+            vertex_color: None,
+            material: &self.material,
+        })
+    }
+
+    fn bounding_box(&self) -> Option<&AABB> {
+        None
+    }
+}
+
+// A flat circle facing along normal
+#[derive(Clone)]
+pub struct Disk {
+    pub center: Vec3,
+    pub normal: Vec3,
+    pub radius: f32,
+    pub material: Material,
+    tangent: Vec3,
+    bitangent: Vec3,
+    aabb: AABB,
+}
+
+impl Disk {
+    pub fn new(center: Vec3, normal: Vec3, radius: f32, material: Material) -> Disk {
+        let normal = normal.make_unit();
+        let (tangent, bitangent) = orthonormal_basis(&normal);
+
+        let half = disk_extent(&normal, radius);
+
+        Disk {
+            center,
+            normal,
+            radius,
+            material,
+            tangent,
+            bitangent,
+            aabb: AABB::new_padded(center - half, center + half),
+        }
+    }
+}
+
+impl Hittable for Disk {
+    fn intersects_ray(&self, ray: &Ray, t_range: (f32, f32)) -> Option<Hit<'_>> {
+        let t = intersect_plane(ray, &self.center, &self.normal, t_range)?;
+        let point = ray.point_at_parameter(t);
+
+        let planar = point - self.center;
+        if planar.length_sq() > self.radius * self.radius {
+            return None;
+        }
+
+        // Polar coordinates, u is the distance from the center and v goes around
+        let x = planar.dot(&self.tangent);
+        let y = planar.dot(&self.bitangent);
+        let uv = (
+            planar.length() / self.radius,
+            (y.atan2(x) + PI) / (2.0 * PI),
+        );
+
+        Some(Hit {
+            t,
+            point,
+            normal: self.normal,
+            shading_normal: self.normal,
+            tangent: self.tangent,
+            bitangent: self.bitangent,
+            uv,
+            vertex_color: None,
+            material: &self.material,
+        })
+    }
+
+    fn bounding_box(&self) -> Option<&AABB> {
+        Some(&self.aabb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::grey;
+
+    #[test]
+    fn plane_intersects_ray() {
+        let mat = grey();
+        let plane = Plane::new(Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 2.0, 0.0), mat);
+
+        // Hit far away from point
+        let ray = Ray {
+            origin: Vec3::new(1000.0, 1.0, -1000.0),
+            direction: Vec3::new(0.0, -1.0, 0.0),
+            time: 0.0,
+        };
+        let hit = plane.intersects_ray(&ray, (0.0, 100.0)).unwrap();
+        assert_eq!(hit.t, 2.0);
+        assert_eq!(hit.point, Vec3::new(1000.0, -1.0, -1000.0));
+        assert_eq!(hit.normal, Vec3::new(0.0, 1.0, 0.0));
+
+        // Miss pointing away from the plane
+        let ray = Ray {
+            origin: Vec3::new(0.0, 1.0, 0.0),
+            direction: Vec3::new(0.0, 1.0, 0.0),
+            time: 0.0,
+        };
+        assert!(plane.intersects_ray(&ray, (0.0, 100.0)).is_none());
+
+        // Miss parallel to the plane
+        let ray = Ray {
+            origin: Vec3::new(0.0, 1.0, 0.0),
+            direction: Vec3::new(1.0, 0.0, 0.0),
+            time: 0.0,
+        };
+        assert!(plane.intersects_ray(&ray, (0.0, 100.0)).is_none());
+
+        assert!(plane.bounding_box().is_none());
+    }
+
+    #[test]
+    fn disk_intersects_ray() {
+        let mat = grey();
+        let disk = Disk::new(
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            1.0,
+            mat,
+        );
+
+        // Hit inside the radius
+        let ray = Ray {
+            origin: Vec3::new(0.5, 0.5, 0.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        let hit = disk.intersects_ray(&ray, (0.0, 100.0)).unwrap();
+        assert_eq!(hit.t, 1.0);
+        assert_eq!(hit.normal, Vec3::new(0.0, 0.0, 1.0));
+
+        // Miss in the corner of the bounding square
+        let ray = Ray {
+            origin: Vec3::new(0.8, 0.8, 0.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        assert!(disk.intersects_ray(&ray, (0.0, 100.0)).is_none());
+    }
+
+    #[test]
+    fn disk_bounding_box() {
+        let mat = grey();
+        let disk = Disk::new(Vec3::new(1.0, 2.0, 3.0), Vec3::new(0.0, 1.0, 0.0), 2.0, mat);
+
+        let aabb = disk.bounding_box().unwrap();
+        assert!(aabb.min.y < 2.0 && aabb.max.y > 2.0);
+        assert!((aabb.min.x - -1.0).abs() < 0.001 && (aabb.max.x - 3.0).abs() < 0.001);
+        assert!((aabb.min.z - 1.0).abs() < 0.001 && (aabb.max.z - 5.0).abs() < 0.001);
+    }
+}
@@ -0,0 +1,264 @@
+use crate::geometry::{Hit, Hittable, Light, AABB};
+use crate::material::Material;
+use crate::math::{Ray, Vec3};
+
+// A parallelogram with a corner at q and edges u and v.
+// The front face is on the side u x v points to
+#[derive(Clone)]
+pub struct Quad {
+    pub q: Vec3,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub material: Material,
+    normal: Vec3,
+    d: f32,
+    w: Vec3,
+    aabb: AABB,
+}
+
+impl Quad {
+    pub fn new(q: Vec3, u: Vec3, v: Vec3, material: Material) -> Quad {
+        let n = u.cross(&v);
+        let normal = n.make_unit();
+
+        // Bounds of all 4 corners
+        let mut aabb = AABB::new_padded(q, q);
+        for corner in [q + u, q + v, q + u + v] {
+            aabb.expand(&AABB::new_padded(corner, corner));
+        }
+
+        Quad {
+            q,
+            u,
+            v,
+            material,
+            normal,
+            d: normal.dot(&q),
+            w: n / n.dot(&n),
+            aabb,
+        }
+    }
+
+    // Rectangle on the plane z = k, facing +z
+    pub fn xy_rect(x0: f32, x1: f32, y0: f32, y1: f32, k: f32, material: Material) -> Quad {
+        Quad::new(
+            Vec3::new(x0, y0, k),
+            Vec3::new(x1 - x0, 0.0, 0.0),
+            Vec3::new(0.0, y1 - y0, 0.0),
+            material,
+        )
+    }
+
+    // Rectangle on the plane y = k, facing +y
+    pub fn xz_rect(x0: f32, x1: f32, z0: f32, z1: f32, k: f32, material: Material) -> Quad {
+        Quad::new(
+            Vec3::new(x0, k, z0),
+            Vec3::new(0.0, 0.0, z1 - z0),
+            Vec3::new(x1 - x0, 0.0, 0.0),
+            material,
+        )
+    }
+
+    // Rectangle on the plane x = k, facing +x
+    pub fn yz_rect(y0: f32, y1: f32, z0: f32, z1: f32, k: f32, material: Material) -> Quad {
+        Quad::new(
+            Vec3::new(k, y0, z0),
+            Vec3::new(0.0, y1 - y0, 0.0),
+            Vec3::new(0.0, 0.0, z1 - z0),
+            material,
+        )
+    }
+}
+
+impl Hittable for Quad {
+    fn intersects_ray(&self, ray: &Ray, t_range: (f32, f32)) -> Option<Hit<'_>> {
+        let denom = self.normal.dot(&ray.direction);
+        // Ray is parallel to the plane
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = (self.d - self.normal.dot(&ray.origin)) / denom;
+        if t <= t_range.0 || t >= t_range.1 {
+            return None;
+        }
+
+        // Express the hit point in terms of u and v to check it's inside
+        let point = ray.point_at_parameter(t);
+        let planar = point - self.q;
+        let alpha = self.w.dot(&planar.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+
+        Some(Hit {
+            t,
+            point,
+            normal: self.normal,
+            shading_normal: self.normal,
+            tangent: self.u.make_unit(),
+            bitangent: self.v.make_unit(),
+            uv: (alpha, beta),
+            vertex_color: None,
+            material: &self.material,
+        })
+    }
+
+    fn bounding_box(&self) -> Option<&AABB> {
+        Some(&self.aabb)
+    }
+
+    fn lights(&self) -> Vec<Light> {
+        if self.material.is_emissive() {
+            vec![Light::quad(self)]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+// An axis aligned box made of 6 outward facing quads
+#[derive(Clone)]
+pub struct Cuboid {
+    sides: [Quad; 6],
+    aabb: AABB,
+}
+
+impl Cuboid {
+    // min and max are opposite corners of the box
+    pub fn new(min: Vec3, max: Vec3, material: Material) -> Cuboid {
+        let (min, max) = (
+            Vec3::new(min.x.min(max.x), min.y.min(max.y), min.z.min(max.z)),
+            Vec3::new(min.x.max(max.x), min.y.max(max.y), min.z.max(max.z)),
+        );
+        let dx = Vec3::new(max.x - min.x, 0.0, 0.0);
+        let dy = Vec3::new(0.0, max.y - min.y, 0.0);
+        let dz = Vec3::new(0.0, 0.0, max.z - min.z);
+
+        let sides = [
+            // Front, right, back, left, top, bottom
+            Quad::new(Vec3::new(min.x, min.y, max.z), dx, dy, material.clone()),
+            Quad::new(Vec3::new(max.x, min.y, max.z), -dz, dy, material.clone()),
+            Quad::new(Vec3::new(max.x, min.y, min.z), -dx, dy, material.clone()),
+            Quad::new(min, dz, dy, material.clone()),
+            Quad::new(Vec3::new(min.x, max.y, max.z), dx, -dz, material.clone()),
+            Quad::new(min, dx, dz, material),
+        ];
+
+        let mut aabb = sides[0].aabb;
+        for side in &sides[1..] {
+            aabb.expand(&side.aabb);
+        }
+
+        Cuboid { sides, aabb }
+    }
+}
+
+impl Hittable for Cuboid {
+    fn intersects_ray(&self, ray: &Ray, t_range: (f32, f32)) -> Option<Hit<'_>> {
+        let mut hit = None;
+        let mut range = t_range;
+        for side in &self.sides {
+            if let Some(new_hit) = side.intersects_ray(ray, range) {
+                range.1 = new_hit.t;
+                hit = Some(new_hit);
+            }
+        }
+
+        hit
+    }
+
+    fn bounding_box(&self) -> Option<&AABB> {
+        Some(&self.aabb)
+    }
+
+    fn lights(&self) -> Vec<Light> {
+        self.sides.iter().flat_map(|side| side.lights()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::grey;
+
+    #[test]
+    fn intersects_ray() {
+        let mat = grey();
+        let quad = Quad::xy_rect(0.0, 2.0, 0.0, 1.0, -1.0, mat);
+
+        let ray = Ray {
+            origin: Vec3::new(1.5, 0.5, 0.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+
+        // Ray should hit the front
+        let hit = quad.intersects_ray(&ray, (0.0, 100.0)).unwrap();
+        assert_eq!(hit.t, 1.0);
+        assert_eq!(hit.point, Vec3::new(1.5, 0.5, -1.0));
+        assert_eq!(hit.normal, Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(hit.uv, (0.75, 0.5));
+
+        // Ray should miss in t range [1.5, 100]
+        assert!(quad.intersects_ray(&ray, (1.5, 100.0)).is_none());
+
+        // Ray should miss outside the quad
+        let ray = Ray {
+            origin: Vec3::new(2.5, 0.5, 0.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        assert!(quad.intersects_ray(&ray, (0.0, 100.0)).is_none());
+
+        // Ray parallel to the quad should miss
+        let ray = Ray {
+            origin: Vec3::new(-1.0, 0.5, -1.0),
+            direction: Vec3::new(1.0, 0.0, 0.0),
+            time: 0.0,
+        };
+        assert!(quad.intersects_ray(&ray, (0.0, 100.0)).is_none());
+    }
+
+    #[test]
+    fn bounding_box() {
+        let mat = grey();
+        let quad = Quad::xz_rect(-1.0, 1.0, -2.0, 2.0, 3.0, mat);
+
+        // Flat quad should still have some thickness
+        let aabb = quad.bounding_box().unwrap();
+        assert!(aabb.min.y < 3.0 && aabb.max.y > 3.0);
+        assert!(aabb.min.x <= -1.0 && aabb.max.x >= 1.0);
+        assert!(aabb.min.z <= -2.0 && aabb.max.z >= 2.0);
+    }
+
+    #[test]
+    fn cuboid() {
+        let mat = grey();
+        let cuboid = Cuboid::new(Vec3::new(1.0, 1.0, 1.0), Vec3::new(-1.0, -1.0, -1.0), mat);
+
+        // Every face should be hit from outside with an outward normal
+        let axes = [
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        ];
+        for axis in axes {
+            for sign in [-1.0, 1.0] {
+                let outward = sign * axis;
+                let ray = Ray {
+                    origin: 3.0 * outward,
+                    direction: -outward,
+                    time: 0.0,
+                };
+                let hit = cuboid.intersects_ray(&ray, (0.0, 100.0)).unwrap();
+                assert_eq!(hit.t, 2.0);
+                assert_eq!(hit.normal, outward);
+            }
+        }
+
+        let aabb = cuboid.bounding_box().unwrap();
+        assert!(aabb.min.x <= -1.0 && aabb.max.x >= 1.0);
+    }
+}
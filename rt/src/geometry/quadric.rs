@@ -0,0 +1,542 @@
+use std::f32::consts::PI;
+
+use crate::geometry::{Hit, Hittable, AABB};
+use crate::material::Material;
+use crate::math::{orthonormal_basis, Ray, Vec3};
+
+// Orthonormal frame with y along a shape's axis. Shapes are intersected in this local
+// space where they're axis aligned, then normals are brought back to world space.
+// The frame is a rotation so t is the same in both spaces
+#[derive(Clone, Copy)]
+pub(crate) struct Frame {
+    origin: Vec3,
+    u: Vec3,
+    axis: Vec3,
+    w: Vec3,
+}
+
+impl Frame {
+    pub(crate) fn new(origin: Vec3, axis: Vec3) -> Frame {
+        let (w, u) = orthonormal_basis(&axis);
+        Frame { origin, u, axis, w }
+    }
+
+    fn local_vector(&self, v: &Vec3) -> Vec3 {
+        Vec3::new(v.dot(&self.u), v.dot(&self.axis), v.dot(&self.w))
+    }
+
+    pub(crate) fn local_ray(&self, ray: &Ray) -> Ray {
+        Ray {
+            origin: self.local_vector(&(ray.origin - self.origin)),
+            direction: self.local_vector(&ray.direction),
+            time: ray.time,
+        }
+    }
+
+    pub(crate) fn world_vector(&self, v: &Vec3) -> Vec3 {
+        v.x * self.u + v.y * self.axis + v.z * self.w
+    }
+}
+
+// Angle around the local y axis mapped to [0, 1]
+pub(crate) fn azimuth(p: &Vec3) -> f32 {
+    ((-p.z).atan2(p.x) + PI) / (2.0 * PI)
+}
+
+// Extent of a disk with unit normal n along each world axis.
+// It shrinks as the normal lines up with the axis
+pub(crate) fn disk_extent(n: &Vec3, radius: f32) -> Vec3 {
+    let extent = |n: f32| radius * (1.0 - n * n).max(0.0).sqrt();
+    Vec3::new(extent(n.x), extent(n.y), extent(n.z))
+}
+
+fn min_vec(a: &Vec3, b: &Vec3) -> Vec3 {
+    Vec3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z))
+}
+
+fn max_vec(a: &Vec3, b: &Vec3) -> Vec3 {
+    Vec3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z))
+}
+
+// Both roots of at^2 + 2bt + c = 0 in increasing order, if there are any
+fn solve_half_b(a: f32, b: f32, c: f32) -> Option<(f32, f32)> {
+    if a.abs() < 1e-12 {
+        // Degenerates to 2bt + c = 0
+        if b.abs() < 1e-12 {
+            return None;
+        }
+        let t = -c / (2.0 * b);
+        return Some((t, t));
+    }
+
+    let discriminant = b * b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let sqrt_d = discriminant.sqrt();
+    let (t0, t1) = ((-b - sqrt_d) / a, (-b + sqrt_d) / a);
+    Some((t0.min(t1), t0.max(t1)))
+}
+
+// Keeps the closest local space hit found so far while testing each surface of a shape
+struct Closest {
+    t_range: (f32, f32),
+    hit: Option<(f32, Vec3, (f32, f32))>,
+}
+
+impl Closest {
+    fn new(t_range: (f32, f32)) -> Closest {
+        Closest { t_range, hit: None }
+    }
+
+    fn in_range(&self, t: f32) -> bool {
+        t > self.t_range.0 && t < self.t_range.1
+    }
+
+    fn push(&mut self, t: f32, normal: Vec3, uv: (f32, f32)) {
+        if self.in_range(t) {
+            self.t_range.1 = t;
+            self.hit = Some((t, normal, uv));
+        }
+    }
+
+    fn into_hit<'a>(self, ray: &Ray, frame: &Frame, material: &'a Material) -> Option<Hit<'a>> {
+        let (t, normal, uv) = self.hit?;
+        let normal = frame.world_vector(&normal).make_unit();
+        let (tangent, bitangent) = orthonormal_basis(&normal);
+        Some(Hit {
+            t,
+            point: ray.point_at_parameter(t),
+            normal,
+            shading_normal: normal,
+            tangent,
+            bitangent,
+            uv,
+            vertex_color: None,
+            material,
+        })
+    }
+}
+
+// A cylinder between the centers of its two caps
+#[derive(Clone)]
+pub struct Cylinder {
+    pub base: Vec3,
+    pub top: Vec3,
+    pub radius: f32,
+    pub material: Material,
+    height: f32,
+    frame: Frame,
+    aabb: AABB,
+}
+
+impl Cylinder {
+    pub fn new(base: Vec3, top: Vec3, radius: f32, material: Material) -> Cylinder {
+        let axis = top - base;
+        let height = axis.length();
+        // A flat cylinder is just its caps, any axis will do
+        let axis = if height > 0.0 {
+            axis.make_unit()
+        } else {
+            Vec3::new(0.0, 1.0, 0.0)
+        };
+
+        let extent = disk_extent(&axis, radius);
+        Cylinder {
+            base,
+            top,
+            radius,
+            material,
+            height,
+            frame: Frame::new(base, axis),
+            aabb: AABB::new(min_vec(&base, &top) - extent, max_vec(&base, &top) + extent),
+        }
+    }
+}
+
+impl Hittable for Cylinder {
+    fn intersects_ray(&self, ray: &Ray, t_range: (f32, f32)) -> Option<Hit<'_>> {
+        let local = self.frame.local_ray(ray);
+        let (o, d) = (local.origin, local.direction);
+        let r2 = self.radius * self.radius;
+        let mut closest = Closest::new(t_range);
+
+        // Side, x^2 + z^2 = r^2 between the caps
+        let a = d.x * d.x + d.z * d.z;
+        let b = o.x * d.x + o.z * d.z;
+        let c = o.x * o.x + o.z * o.z - r2;
+        if let Some((t0, t1)) = solve_half_b(a, b, c).filter(|_| self.height > 0.0) {
+            for t in [t0, t1] {
+                let p = local.point_at_parameter(t);
+                if p.y >= 0.0 && p.y <= self.height {
+                    closest.push(
+                        t,
+                        Vec3::new(p.x, 0.0, p.z),
+                        (azimuth(&p), p.y / self.height),
+                    );
+                }
+            }
+        }
+
+        // Caps, uv is a planar mapping across each one
+        if d.y.abs() > 1e-8 {
+            for (y, normal_y) in [(0.0, -1.0), (self.height, 1.0)] {
+                let t = (y - o.y) / d.y;
+                let p = local.point_at_parameter(t);
+                if p.x * p.x + p.z * p.z <= r2 {
+                    let uv = (
+                        0.5 * (p.x / self.radius + 1.0),
+                        0.5 * (p.z / self.radius + 1.0),
+                    );
+                    closest.push(t, Vec3::new(0.0, normal_y, 0.0), uv);
+                }
+            }
+        }
+
+        closest.into_hit(ray, &self.frame, &self.material)
+    }
+
+    fn bounding_box(&self) -> Option<&AABB> {
+        Some(&self.aabb)
+    }
+}
+
+// A cone with a capped circular base and a point at apex
+#[derive(Clone)]
+pub struct Cone {
+    pub base: Vec3,
+    pub apex: Vec3,
+    pub radius: f32,
+    pub material: Material,
+    height: f32,
+    frame: Frame,
+    aabb: AABB,
+}
+
+impl Cone {
+    pub fn new(base: Vec3, apex: Vec3, radius: f32, material: Material) -> Cone {
+        let axis = apex - base;
+        let height = axis.length();
+        // A flat cone is just its base, any axis will do
+        let axis = if height > 0.0 {
+            axis.make_unit()
+        } else {
+            Vec3::new(0.0, 1.0, 0.0)
+        };
+
+        let extent = disk_extent(&axis, radius);
+        Cone {
+            base,
+            apex,
+            radius,
+            material,
+            height,
+            frame: Frame::new(base, axis),
+            aabb: AABB::new(
+                min_vec(&(base - extent), &apex),
+                max_vec(&(base + extent), &apex),
+            ),
+        }
+    }
+}
+
+impl Hittable for Cone {
+    fn intersects_ray(&self, ray: &Ray, t_range: (f32, f32)) -> Option<Hit<'_>> {
+        let local = self.frame.local_ray(ray);
+        let (o, d) = (local.origin, local.direction);
+        let mut closest = Closest::new(t_range);
+
+        // Side, x^2 + z^2 = (k(h - y))^2 where k is the slope of the side
+        let k = self.radius / self.height;
+        let k2 = k * k;
+        let h = self.height - o.y;
+        let a = d.x * d.x + d.z * d.z - k2 * d.y * d.y;
+        let b = o.x * d.x + o.z * d.z + k2 * h * d.y;
+        let c = o.x * o.x + o.z * o.z - k2 * h * h;
+        if let Some((t0, t1)) = solve_half_b(a, b, c).filter(|_| self.height > 0.0) {
+            for t in [t0, t1] {
+                let p = local.point_at_parameter(t);
+                if p.y >= 0.0 && p.y <= self.height {
+                    // Gradient of the implicit surface
+                    let normal = Vec3::new(p.x, k2 * (self.height - p.y), p.z);
+                    closest.push(t, normal, (azimuth(&p), p.y / self.height));
+                }
+            }
+        }
+
+        // Base cap
+        if d.y.abs() > 1e-8 {
+            let t = -o.y / d.y;
+            let p = local.point_at_parameter(t);
+            if p.x * p.x + p.z * p.z <= self.radius * self.radius {
+                let uv = (
+                    0.5 * (p.x / self.radius + 1.0),
+                    0.5 * (p.z / self.radius + 1.0),
+                );
+                closest.push(t, Vec3::new(0.0, -1.0, 0.0), uv);
+            }
+        }
+
+        closest.into_hit(ray, &self.frame, &self.material)
+    }
+
+    fn bounding_box(&self) -> Option<&AABB> {
+        Some(&self.aabb)
+    }
+}
+
+// A cylinder with hemispherical ends, i.e. every point within radius of the segment a to b
+#[derive(Clone)]
+pub struct Capsule {
+    pub a: Vec3,
+    pub b: Vec3,
+    pub radius: f32,
+    pub material: Material,
+    height: f32,
+    frame: Frame,
+    aabb: AABB,
+}
+
+impl Capsule {
+    pub fn new(a: Vec3, b: Vec3, radius: f32, material: Material) -> Capsule {
+        let axis = b - a;
+        let height = axis.length();
+        // Any axis will do for a sphere
+        let axis = if height > 0.0 {
+            axis.make_unit()
+        } else {
+            Vec3::new(0.0, 1.0, 0.0)
+        };
+
+        let r3 = Vec3::new_uniform(radius);
+        Capsule {
+            a,
+            b,
+            radius,
+            material,
+            height,
+            frame: Frame::new(a, axis),
+            aabb: AABB::new(min_vec(&a, &b) - r3, max_vec(&a, &b) + r3),
+        }
+    }
+
+    // v runs along the profile from the bottom pole to the top one by arc length
+    fn v(&self, p: &Vec3) -> f32 {
+        let quarter = 0.5 * PI * self.radius;
+        let s = if p.y < 0.0 {
+            quarter - self.radius * (-p.y / self.radius).clamp(-1.0, 1.0).asin()
+        } else if p.y > self.height {
+            quarter
+                + self.height
+                + self.radius * ((p.y - self.height) / self.radius).clamp(-1.0, 1.0).asin()
+        } else {
+            quarter + p.y
+        };
+
+        s / (2.0 * quarter + self.height)
+    }
+}
+
+impl Hittable for Capsule {
+    fn intersects_ray(&self, ray: &Ray, t_range: (f32, f32)) -> Option<Hit<'_>> {
+        let local = self.frame.local_ray(ray);
+        let (o, d) = (local.origin, local.direction);
+        let r2 = self.radius * self.radius;
+        let mut closest = Closest::new(t_range);
+
+        // Each surface only counts on its own part of the boundary so that hits from
+        // inside the capsule are right too
+        let a = d.x * d.x + d.z * d.z;
+        let b = o.x * d.x + o.z * d.z;
+        let c = o.x * o.x + o.z * o.z - r2;
+        if let Some((t0, t1)) = solve_half_b(a, b, c) {
+            for t in [t0, t1] {
+                let p = local.point_at_parameter(t);
+                if p.y >= 0.0 && p.y <= self.height {
+                    closest.push(t, Vec3::new(p.x, 0.0, p.z), (azimuth(&p), self.v(&p)));
+                }
+            }
+        }
+
+        // Hemispheres, bottom one below y = 0 and the top one above y = height
+        for (center_y, sign) in [(0.0, -1.0), (self.height, 1.0)] {
+            let oc = Vec3::new(o.x, o.y - center_y, o.z);
+            if let Some((t0, t1)) = solve_half_b(d.dot(&d), oc.dot(&d), oc.dot(&oc) - r2) {
+                for t in [t0, t1] {
+                    let p = local.point_at_parameter(t);
+                    if sign * (p.y - center_y) >= 0.0 {
+                        let normal = Vec3::new(p.x, p.y - center_y, p.z);
+                        closest.push(t, normal, (azimuth(&p), self.v(&p)));
+                    }
+                }
+            }
+        }
+
+        closest.into_hit(ray, &self.frame, &self.material)
+    }
+
+    fn bounding_box(&self) -> Option<&AABB> {
+        Some(&self.aabb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::grey;
+    use crate::math::assert_near;
+
+    #[test]
+    fn cylinder_intersects_ray() {
+        let mat = grey();
+        // Lying along x from -1 to 1
+        let cylinder = Cylinder::new(
+            Vec3::new(-1.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            0.5,
+            mat,
+        );
+
+        // Side from the front
+        let ray = Ray {
+            origin: Vec3::new(0.5, 0.0, 2.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        let hit = cylinder.intersects_ray(&ray, (0.0, 100.0)).unwrap();
+        assert!((hit.t - 1.5).abs() < 1e-5);
+        assert_near(hit.normal, Vec3::new(0.0, 0.0, 1.0));
+        assert!((hit.uv.1 - 0.75).abs() < 1e-5);
+
+        // Back of the side from inside
+        let hit = cylinder.intersects_ray(&ray, (2.0, 100.0)).unwrap();
+        assert!((hit.t - 2.5).abs() < 1e-5);
+        assert_near(hit.normal, Vec3::new(0.0, 0.0, -1.0));
+
+        // Top cap end on
+        let ray = Ray {
+            origin: Vec3::new(3.0, 0.25, 0.0),
+            direction: Vec3::new(-1.0, 0.0, 0.0),
+            time: 0.0,
+        };
+        let hit = cylinder.intersects_ray(&ray, (0.0, 100.0)).unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-5);
+        assert_near(hit.normal, Vec3::new(1.0, 0.0, 0.0));
+
+        // Miss past the end of the cylinder
+        let ray = Ray {
+            origin: Vec3::new(1.5, 0.0, 2.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        assert!(cylinder.intersects_ray(&ray, (0.0, 100.0)).is_none());
+    }
+
+    #[test]
+    fn cylinder_bounding_box() {
+        let mat = grey();
+        let cylinder = Cylinder::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 3.0, 0.0), 1.0, mat);
+
+        let aabb = cylinder.bounding_box().unwrap();
+        assert_near(aabb.min, Vec3::new(-1.0, 1.0, -1.0));
+        assert_near(aabb.max, Vec3::new(1.0, 3.0, 1.0));
+    }
+
+    #[test]
+    fn cone_intersects_ray() {
+        let mat = grey();
+        // Base radius 1 on y = 0 with apex at y = 1, so sides slope at 45 degrees
+        let cone = Cone::new(Vec3::new_zeroes(), Vec3::new(0.0, 1.0, 0.0), 1.0, mat);
+
+        // Side half way up
+        let ray = Ray {
+            origin: Vec3::new(0.0, 0.5, 2.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        let hit = cone.intersects_ray(&ray, (0.0, 100.0)).unwrap();
+        assert!((hit.t - 1.5).abs() < 1e-5);
+        let diagonal = 1.0 / 2.0f32.sqrt();
+        assert_near(hit.normal, Vec3::new(0.0, diagonal, diagonal));
+
+        // Base from below
+        let ray = Ray {
+            origin: Vec3::new(0.25, -1.0, 0.0),
+            direction: Vec3::new(0.0, 1.0, 0.0),
+            time: 0.0,
+        };
+        let hit = cone.intersects_ray(&ray, (0.0, 100.0)).unwrap();
+        assert!((hit.t - 1.0).abs() < 1e-5);
+        assert_near(hit.normal, Vec3::new(0.0, -1.0, 0.0));
+
+        // Miss the other nappe of the double cone above the apex
+        let ray = Ray {
+            origin: Vec3::new(0.0, 1.5, 2.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        assert!(cone.intersects_ray(&ray, (0.0, 100.0)).is_none());
+
+        let aabb = cone.bounding_box().unwrap();
+        assert_near(aabb.min, Vec3::new(-1.0, 0.0, -1.0));
+        assert_near(aabb.max, Vec3::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn flat_quadrics() {
+        // Zero height leaves a disk rather than a NaN axis
+        let mat = grey();
+        let cylinder = Cylinder::new(Vec3::new_zeroes(), Vec3::new_zeroes(), 1.0, mat.clone());
+        let cone = Cone::new(Vec3::new_zeroes(), Vec3::new_zeroes(), 1.0, mat);
+        let ray = Ray {
+            origin: Vec3::new(0.25, 1.0, 0.0),
+            direction: Vec3::new(0.0, -1.0, 0.0),
+            time: 0.0,
+        };
+        for shape in [&cylinder as &dyn Hittable, &cone] {
+            let aabb = shape.bounding_box().unwrap();
+            assert!(aabb.min.x.is_finite() && aabb.max.y.is_finite());
+            let hit = shape.intersects_ray(&ray, (0.0, 100.0)).unwrap();
+            assert!((hit.t - 1.0).abs() < 1e-5);
+            assert!(hit.normal.length().is_finite());
+        }
+    }
+
+    #[test]
+    fn capsule_intersects_ray() {
+        let mat = grey();
+        let capsule = Capsule::new(Vec3::new_zeroes(), Vec3::new(0.0, 2.0, 0.0), 0.5, mat);
+
+        // Top hemisphere end on
+        let ray = Ray {
+            origin: Vec3::new(0.0, 5.0, 0.0),
+            direction: Vec3::new(0.0, -1.0, 0.0),
+            time: 0.0,
+        };
+        let hit = capsule.intersects_ray(&ray, (0.0, 100.0)).unwrap();
+        assert!((hit.t - 2.5).abs() < 1e-5);
+        assert_near(hit.normal, Vec3::new(0.0, 1.0, 0.0));
+        assert!((hit.uv.1 - 1.0).abs() < 1e-5);
+
+        // Exit through the bottom from inside, not through the inner half of a hemisphere
+        let hit = capsule.intersects_ray(&ray, (3.0, 100.0)).unwrap();
+        assert!((hit.t - 5.5).abs() < 1e-5);
+        assert_near(hit.normal, Vec3::new(0.0, -1.0, 0.0));
+
+        // Side
+        let ray = Ray {
+            origin: Vec3::new(2.0, 1.0, 0.0),
+            direction: Vec3::new(-1.0, 0.0, 0.0),
+            time: 0.0,
+        };
+        let hit = capsule.intersects_ray(&ray, (0.0, 100.0)).unwrap();
+        assert!((hit.t - 1.5).abs() < 1e-5);
+        assert_near(hit.normal, Vec3::new(1.0, 0.0, 0.0));
+        assert!((hit.uv.1 - 0.5).abs() < 1e-5);
+
+        let aabb = capsule.bounding_box().unwrap();
+        assert_near(aabb.min, Vec3::new(-0.5, -0.5, -0.5));
+        assert_near(aabb.max, Vec3::new(0.5, 2.5, 0.5));
+    }
+}
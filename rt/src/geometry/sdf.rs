@@ -0,0 +1,332 @@
+use std::sync::Arc;
+
+use crate::geometry::{Hit, Hittable, AABB};
+use crate::material::Material;
+use crate::math::{orthonormal_basis, Ray, Transform, Vec3};
+
+const MAX_STEPS: u32 = 512;
+// Close enough to the surface to count as a hit
+const HIT_EPSILON: f32 = 0.0001;
+// Offset used to estimate normals from the distance gradient
+const NORMAL_EPSILON: f32 = 0.0001;
+
+fn length_2d(x: f32, y: f32) -> f32 {
+    (x * x + y * y).sqrt()
+}
+
+fn max_zero(v: &Vec3) -> Vec3 {
+    Vec3::new(v.x.max(0.0), v.y.max(0.0), v.z.max(0.0))
+}
+
+fn mix(a: f32, b: f32, h: f32) -> f32 {
+    a + (b - a) * h
+}
+
+// A signed distance function, negative inside the surface. Built up as a tree so
+// scripts can compose them. Children are shared so cloning is cheap.
+// Distances mostly follow Inigo Quilez's articles on SDFs
+#[derive(Clone, Debug)]
+pub enum Sdf {
+    Sphere(f32),
+    // Half the size along each axis
+    Box(Vec3),
+    // Box with edges rounded by radius, keeping the same overall size
+    RoundBox(Vec3, f32),
+    // Major and minor radius, around the y axis
+    Torus(f32, f32),
+    // Radius and half height, along the y axis
+    Cylinder(f32, f32),
+    // Segment end points and radius
+    Capsule(Vec3, Vec3, f32),
+    // Unit normal and distance from the origin
+    Plane(Vec3, f32),
+    // Power and iteration count
+    Mandelbulb(f32, u32),
+    Union(Arc<Sdf>, Arc<Sdf>),
+    Intersection(Arc<Sdf>, Arc<Sdf>),
+    // Everything in the first that isn't in the second
+    Difference(Arc<Sdf>, Arc<Sdf>),
+    // Blend the two over a distance of k
+    SmoothUnion(Arc<Sdf>, Arc<Sdf>, f32),
+    SmoothIntersection(Arc<Sdf>, Arc<Sdf>, f32),
+    SmoothDifference(Arc<Sdf>, Arc<Sdf>, f32),
+    Translate(Arc<Sdf>, Vec3),
+    // Should be rigid, anything else distorts the distance
+    Transform(Arc<Sdf>, Transform),
+    // Uniform so the distance stays exact
+    Scale(Arc<Sdf>, f32),
+    // Grows the surface by radius, rounding its edges
+    Round(Arc<Sdf>, f32),
+    // Hollows the surface into a shell of thickness
+    Onion(Arc<Sdf>, f32),
+    // Repeats space with the period along each axis, 0 doesn't repeat that axis
+    Repeat(Arc<Sdf>, Vec3),
+}
+
+impl Sdf {
+    pub fn distance(&self, p: &Vec3) -> f32 {
+        match self {
+            Sdf::Sphere(radius) => p.length() - radius,
+            Sdf::Box(half) => box_distance(p, half),
+            Sdf::RoundBox(half, radius) => {
+                box_distance(p, &(*half - Vec3::new_uniform(*radius))) - radius
+            }
+            Sdf::Torus(major, minor) => length_2d(length_2d(p.x, p.z) - major, p.y) - minor,
+            Sdf::Cylinder(radius, half_height) => {
+                let d = (length_2d(p.x, p.z) - radius, p.y.abs() - half_height);
+                d.0.max(d.1).min(0.0) + length_2d(d.0.max(0.0), d.1.max(0.0))
+            }
+            Sdf::Capsule(a, b, radius) => {
+                let pa = p - a;
+                let ba = *b - *a;
+                let h = (pa.dot(&ba) / ba.dot(&ba)).clamp(0.0, 1.0);
+                (pa - h * ba).length() - radius
+            }
+            Sdf::Plane(normal, offset) => p.dot(normal) - offset,
+            Sdf::Mandelbulb(power, iterations) => mandelbulb_distance(p, *power, *iterations),
+            Sdf::Union(a, b) => a.distance(p).min(b.distance(p)),
+            Sdf::Intersection(a, b) => a.distance(p).max(b.distance(p)),
+            Sdf::Difference(a, b) => a.distance(p).max(-b.distance(p)),
+            Sdf::SmoothUnion(a, b, k) => {
+                let (d1, d2) = (a.distance(p), b.distance(p));
+                let h = (0.5 + 0.5 * (d2 - d1) / k).clamp(0.0, 1.0);
+                mix(d2, d1, h) - k * h * (1.0 - h)
+            }
+            Sdf::SmoothIntersection(a, b, k) => {
+                let (d1, d2) = (a.distance(p), b.distance(p));
+                let h = (0.5 - 0.5 * (d2 - d1) / k).clamp(0.0, 1.0);
+                mix(d2, d1, h) + k * h * (1.0 - h)
+            }
+            Sdf::SmoothDifference(a, b, k) => {
+                let (d1, d2) = (a.distance(p), b.distance(p));
+                let h = (0.5 - 0.5 * (d1 + d2) / k).clamp(0.0, 1.0);
+                mix(d1, -d2, h) + k * h * (1.0 - h)
+            }
+            Sdf::Translate(sdf, offset) => sdf.distance(&(p - offset)),
+            Sdf::Transform(sdf, transform) => sdf.distance(&transform.inverse().transform_point(p)),
+            Sdf::Scale(sdf, factor) => sdf.distance(&(*p / *factor)) * factor,
+            Sdf::Round(sdf, radius) => sdf.distance(p) - radius,
+            Sdf::Onion(sdf, thickness) => sdf.distance(p).abs() - thickness,
+            Sdf::Repeat(sdf, period) => {
+                let wrap = |x: f32, period: f32| {
+                    if period > 0.0 {
+                        x - period * (x / period).round()
+                    } else {
+                        x
+                    }
+                };
+                sdf.distance(&Vec3::new(
+                    wrap(p.x, period.x),
+                    wrap(p.y, period.y),
+                    wrap(p.z, period.z),
+                ))
+            }
+        }
+    }
+
+    // Gradient of the distance by the tetrahedron technique, 4 samples instead of 6
+    pub fn normal(&self, p: &Vec3) -> Vec3 {
+        let e = NORMAL_EPSILON;
+        let k = [
+            Vec3::new(1.0, -1.0, -1.0),
+            Vec3::new(-1.0, -1.0, 1.0),
+            Vec3::new(-1.0, 1.0, -1.0),
+            Vec3::new(1.0, 1.0, 1.0),
+        ];
+
+        let mut normal = Vec3::new_zeroes();
+        for k in k {
+            normal += self.distance(&(*p + e * k)) * k;
+        }
+
+        normal.make_unit()
+    }
+}
+
+fn box_distance(p: &Vec3, half: &Vec3) -> f32 {
+    let q = Vec3::new(p.x.abs(), p.y.abs(), p.z.abs()) - *half;
+    max_zero(&q).length() + q.x.max(q.y.max(q.z)).min(0.0)
+}
+
+// Distance estimate for the power n Mandelbulb
+fn mandelbulb_distance(p: &Vec3, power: f32, iterations: u32) -> f32 {
+    let mut z = *p;
+    let mut dr = 1.0;
+    let mut r = 0.0;
+
+    for _ in 0..iterations {
+        r = z.length();
+        if r > 2.0 || r == 0.0 {
+            break;
+        }
+
+        // Raise to the power in spherical coordinates
+        let theta = (z.z / r).acos() * power;
+        let phi = z.y.atan2(z.x) * power;
+        dr = r.powf(power - 1.0) * power * dr + 1.0;
+        let zr = r.powf(power);
+        z =
+            zr * Vec3::new(
+                theta.sin() * phi.cos(),
+                phi.sin() * theta.sin(),
+                theta.cos(),
+            ) + *p;
+    }
+
+    if r == 0.0 {
+        return 0.0;
+    }
+
+    0.5 * r.ln() * r / dr
+}
+
+// A surface found by sphere tracing a signed distance function. The function can't
+// be bounded automatically so the bounds come from the user, and marching is clipped
+// to them
+#[derive(Clone)]
+pub struct SdfObject {
+    pub sdf: Sdf,
+    pub material: Material,
+    aabb: AABB,
+}
+
+impl SdfObject {
+    pub fn new(sdf: Sdf, min: Vec3, max: Vec3, material: Material) -> SdfObject {
+        SdfObject {
+            sdf,
+            material,
+            aabb: AABB::new_padded(min, max),
+        }
+    }
+}
+
+impl Hittable for SdfObject {
+    fn intersects_ray(&self, ray: &Ray, t_range: (f32, f32)) -> Option<Hit<'_>> {
+        let (t_start, t_end) = self.aabb.clip(ray, t_range)?;
+        let length = ray.direction.length();
+
+        // Rays that start inside (e.g. refracted ones) march on the negated distance
+        let sign = self.sdf.distance(&ray.point_at_parameter(t_start)).signum();
+
+        let mut t = t_start;
+        for _ in 0..MAX_STEPS {
+            let point = ray.point_at_parameter(t);
+            let distance = sign * self.sdf.distance(&point);
+
+            // Don't count the start, rays that bounced off the surface begin right on it
+            if distance < HIT_EPSILON && t > t_start {
+                let normal = self.sdf.normal(&point);
+                let (tangent, bitangent) = orthonormal_basis(&normal);
+                return Some(Hit {
+                    t,
+                    point,
+                    normal,
+                    shading_normal: normal,
+                    tangent,
+                    bitangent,
+                    uv: (0.0, 0.0),
+                    vertex_color: None,
+                    material: &self.material,
+                });
+            }
+
+            // Nothing can be closer than the distance so it's safe to step that far
+            t += distance.max(HIT_EPSILON) / length;
+            if t > t_end {
+                break;
+            }
+        }
+
+        None
+    }
+
+    fn bounding_box(&self) -> Option<&AABB> {
+        Some(&self.aabb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::grey;
+    use crate::math::assert_near;
+
+    #[test]
+    fn distance() {
+        let p = Vec3::new(2.0, 0.0, 0.0);
+        assert_eq!(Sdf::Sphere(1.0).distance(&p), 1.0);
+        assert_eq!(Sdf::Box(Vec3::new_uniform(0.5)).distance(&p), 1.5);
+        assert_eq!(Sdf::Torus(1.5, 0.25).distance(&p), 0.25);
+        assert_eq!(Sdf::Cylinder(1.0, 1.0).distance(&p), 1.0);
+        assert_eq!(Sdf::Plane(Vec3::new(1.0, 0.0, 0.0), 0.5).distance(&p), 1.5);
+
+        let sphere = Arc::new(Sdf::Sphere(1.0));
+        let moved = Arc::new(Sdf::Translate(sphere.clone(), Vec3::new(2.0, 0.0, 0.0)));
+        assert_eq!(moved.distance(&p), -1.0);
+        assert_eq!(Sdf::Union(sphere.clone(), moved.clone()).distance(&p), -1.0);
+        assert_eq!(
+            Sdf::Intersection(sphere.clone(), moved.clone()).distance(&p),
+            1.0
+        );
+        assert_eq!(Sdf::Difference(moved, sphere.clone()).distance(&p), -1.0);
+        assert_eq!(Sdf::Scale(sphere.clone(), 2.0).distance(&p), 0.0);
+
+        // Repeating every 4 units puts a copy of the sphere at x = 4
+        let repeated = Sdf::Repeat(sphere, Vec3::new(4.0, 0.0, 0.0));
+        assert_eq!(repeated.distance(&Vec3::new(4.5, 0.0, 0.0)), -0.5);
+    }
+
+    #[test]
+    fn smooth_union() {
+        let a = Arc::new(Sdf::Translate(
+            Arc::new(Sdf::Sphere(1.0)),
+            Vec3::new(-1.0, 0.0, 0.0),
+        ));
+        let b = Arc::new(Sdf::Translate(
+            Arc::new(Sdf::Sphere(1.0)),
+            Vec3::new(1.0, 0.0, 0.0),
+        ));
+
+        // Blending fills in the crease between the spheres
+        let p = Vec3::new(0.0, 0.5, 0.0);
+        let hard = Sdf::Union(a.clone(), b.clone()).distance(&p);
+        let smooth = Sdf::SmoothUnion(a, b, 0.5).distance(&p);
+        assert!(smooth < hard);
+    }
+
+    #[test]
+    fn intersects_ray() {
+        let mat = grey();
+        let sdf = SdfObject::new(
+            Sdf::Sphere(1.0),
+            Vec3::new_uniform(-1.0),
+            Vec3::new_uniform(1.0),
+            mat,
+        );
+
+        let ray = Ray {
+            origin: Vec3::new(0.0, 0.0, -2.0),
+            direction: Vec3::new(0.0, 0.0, 2.0),
+            time: 0.0,
+        };
+
+        // Front of the sphere, t is in terms of the unnormalised direction
+        let hit = sdf.intersects_ray(&ray, (0.0, 100.0)).unwrap();
+        assert!((hit.t - 0.5).abs() < 1e-3);
+        assert_near(hit.point, Vec3::new(0.0, 0.0, -1.0));
+        assert_near(hit.normal, Vec3::new(0.0, 0.0, -1.0));
+
+        // Back of the sphere from inside
+        let hit = sdf.intersects_ray(&ray, (0.75, 100.0)).unwrap();
+        assert!((hit.t - 1.5).abs() < 1e-3);
+        assert_near(hit.normal, Vec3::new(0.0, 0.0, 1.0));
+
+        // Misses past the side of the sphere but inside the bounds
+        let ray = Ray {
+            origin: Vec3::new(0.9, 0.9, -2.0),
+            direction: Vec3::new(0.0, 0.0, 1.0),
+            time: 0.0,
+        };
+        assert!(sdf.intersects_ray(&ray, (0.0, 100.0)).is_none());
+    }
+}
@@ -1,6 +1,7 @@
-use crate::geometry::{Hit, Hittable, AABB};
+use crate::geometry::{Hit, Hittable, Light, AABB};
 use crate::material::Material;
-use crate::math::{Ray, Vec3};
+use crate::math::{orthonormal_basis, Ray, Vec3};
+use std::f32::consts::PI;
 
 #[derive(Clone)]
 pub struct Sphere {
@@ -25,36 +26,137 @@ impl Sphere {
     }
 }
 
+// Closest hit on the sphere at center within t_range
+fn intersect_sphere<'a>(
+    ray: &Ray,
+    center: Vec3,
+    radius: f32,
+    material: &'a Material,
+    t_range: (f32, f32),
+) -> Option<Hit<'a>> {
+    let oc = ray.origin - center;
+
+    let a = ray.direction.dot(&ray.direction);
+    let b = oc.dot(&ray.direction);
+    let c = oc.dot(&oc) - radius * radius;
+    let discriminant = b * b - a * c;
+
+    if discriminant > 0.0 {
+        let mut t = (-b - discriminant.sqrt()) / a;
+        if t < t_range.0 || t > t_range.1 {
+            // t was out of range, try the other t
+            t = (-b + discriminant.sqrt()) / a;
+        }
+        if t > t_range.0 && t < t_range.1 {
+            // t was in range
+            let point = ray.point_at_parameter(t);
+            let normal = (point - center).make_unit();
+            // Spherical mapping with v running from the bottom pole to the top
+            let uv = (
+                ((-normal.z).atan2(normal.x) + PI) / (2.0 * PI),
+                (-normal.y).acos() / PI,
+            );
+            // u runs around the y axis, so there's no tangent at the poles
+            let around = Vec3::new(normal.z, 0.0, -normal.x);
+            let tangent = if around.length_sq() > 1e-12 {
+                around.make_unit()
+            } else {
+                orthonormal_basis(&normal).0
+            };
+
+            return Some(Hit {
+                t,
+                point,
+                normal,
+                shading_normal: normal,
+                tangent,
+                bitangent: normal.cross(&tangent),
+                uv,
+                vertex_color: None,
+                material,
+            });
+        }
+    }
+
+    None
+}
+
 impl Hittable for Sphere {
     fn intersects_ray(&self, ray: &Ray, t_range: (f32, f32)) -> Option<Hit> {
-        let oc = ray.origin - self.center;
-
-        let a = ray.direction.dot(&ray.direction);
-        let b = oc.dot(&ray.direction);
-        let c = oc.dot(&oc) - self.radius * self.radius;
-        let discriminant = b * b - a * c;
-
-        if discriminant > 0.0 {
-            let mut t = (-b - discriminant.sqrt()) / a;
-            if t < t_range.0 || t > t_range.1 {
-                // t was out of range, try the other t
-                t = (-b + discriminant.sqrt()) / a;
-            }
-            if t > t_range.0 && t < t_range.1 {
-                // t was in range
-                let point = ray.point_at_parameter(t);
-                let normal = (point - self.center).make_unit();
-
-                return Some(Hit {
-                    t,
-                    point,
-                    normal,
-                    material: &self.material,
-                });
-            }
+        intersect_sphere(ray, self.center, self.radius, &self.material, t_range)
+    }
+
+    fn bounding_box(&self) -> Option<&AABB> {
+        Some(&self.aabb)
+    }
+
+    fn lights(&self) -> Vec<Light> {
+        if self.material.is_emissive() {
+            vec![Light::sphere(self)]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+// A sphere moving in a straight line from center0 at time0 to center1 at time1.
+// It keeps going at the same speed outside those times
+#[derive(Clone)]
+pub struct MovingSphere {
+    pub center0: Vec3,
+    pub center1: Vec3,
+    pub time0: f32,
+    pub time1: f32,
+    pub material: Material,
+    pub radius: f32,
+    // Covers the sphere between time0 and time1
+    aabb: AABB,
+}
+
+impl MovingSphere {
+    pub fn new(
+        center0: Vec3,
+        center1: Vec3,
+        time0: f32,
+        time1: f32,
+        radius: f32,
+        material: Material,
+    ) -> MovingSphere {
+        let r3 = Vec3::new(radius, radius, radius);
+        let aabb = AABB::merge(
+            &AABB::new(center0 - r3, center0 + r3),
+            &AABB::new(center1 - r3, center1 + r3),
+        );
+
+        MovingSphere {
+            center0,
+            center1,
+            time0,
+            time1,
+            material,
+            radius,
+            aabb,
         }
+    }
+
+    pub fn center(&self, time: f32) -> Vec3 {
+        if self.time1 == self.time0 {
+            return self.center0;
+        }
+        let f = (time - self.time0) / (self.time1 - self.time0);
+        self.center0 + f * (self.center1 - self.center0)
+    }
+}
 
-        None
+impl Hittable for MovingSphere {
+    fn intersects_ray(&self, ray: &Ray, t_range: (f32, f32)) -> Option<Hit<'_>> {
+        intersect_sphere(
+            ray,
+            self.center(ray.time),
+            self.radius,
+            &self.material,
+            t_range,
+        )
     }
 
     fn bounding_box(&self) -> Option<&AABB> {
@@ -65,16 +167,18 @@ impl Hittable for Sphere {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::material::grey;
 
     #[test]
     fn intersects_ray() {
         // unit sphere
-        let mat = Material::new_lambertian(Vec3::new(0.8, 0.8, 0.8));
+        let mat = grey();
         let sphere = Sphere::new(Vec3::new_zeroes(), 1.0, mat);
 
         let ray = Ray {
             origin: Vec3::new(0.0, 0.0, -2.0),
             direction: Vec3::new(0.0, 0.0, 1.0),
+            time: 0.0,
         };
 
         // Ray should hit front of sphere in t range [0, 100]
@@ -82,6 +186,9 @@ mod tests {
         assert_eq!(hit.point, Vec3::new(0.0, 0.0, -1.0));
         assert_eq!(hit.normal, Vec3::new(0.0, 0.0, -1.0));
         assert_eq!(hit.t, 1.0);
+        // u runs around the equator and v up towards the top pole
+        assert_eq!(hit.tangent, Vec3::new(-1.0, 0.0, 0.0));
+        assert_eq!(hit.bitangent, Vec3::new(0.0, 1.0, 0.0));
 
         // Ray should hit back of sphere in t range [1.5, 100]
         let hit = sphere.intersects_ray(&ray, (1.5, 100.0)).unwrap();
@@ -99,6 +206,7 @@ mod tests {
         let ray = Ray {
             origin: Vec3::new(0.0, 0.0, -2.0),
             direction: Vec3::new(0.0, 1.0, 0.0),
+            time: 0.0,
         };
         assert!(sphere.intersects_ray(&ray, (-100.0, 100.0)).is_none());
     }
@@ -106,11 +214,43 @@ mod tests {
     #[test]
     fn bounding_box() {
         // shifted unit sphere
-        let mat = Material::new_lambertian(Vec3::new(0.8, 0.8, 0.8));
+        let mat = grey();
         let sphere = Sphere::new(Vec3::new(1.0, 2.0, 3.0), 1.0, mat);
 
         let aabb = sphere.bounding_box().unwrap();
         assert_eq!(aabb.min, Vec3::new(0.0, 1.0, 2.0));
         assert_eq!(aabb.max, Vec3::new(2.0, 3.0, 4.0));
     }
+
+    #[test]
+    fn moving_sphere() {
+        let mat = grey();
+        // From x = 0 at time 0 to x = 2 at time 1
+        let sphere = MovingSphere::new(
+            Vec3::new_zeroes(),
+            Vec3::new(2.0, 0.0, 0.0),
+            0.0,
+            1.0,
+            0.5,
+            mat,
+        );
+
+        let ray = |time: f32| Ray {
+            origin: Vec3::new(1.0, 0.0, -2.0),
+            direction: Vec3::new(0.0, 0.0, 1.0),
+            time,
+        };
+
+        // Only passes through the middle half way through
+        let hit = sphere.intersects_ray(&ray(0.5), (0.0, 100.0)).unwrap();
+        assert_eq!(hit.t, 1.5);
+        assert_eq!(hit.normal, Vec3::new(0.0, 0.0, -1.0));
+        assert!(sphere.intersects_ray(&ray(0.0), (0.0, 100.0)).is_none());
+        assert!(sphere.intersects_ray(&ray(1.0), (0.0, 100.0)).is_none());
+
+        // Bounds cover the whole path
+        let aabb = sphere.bounding_box().unwrap();
+        assert_eq!(aabb.min, Vec3::new(-0.5, -0.5, -0.5));
+        assert_eq!(aabb.max, Vec3::new(2.5, 0.5, 0.5));
+    }
 }
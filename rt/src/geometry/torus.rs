@@ -0,0 +1,214 @@
+use std::f32::consts::PI;
+
+use crate::geometry::quadric::{azimuth, disk_extent, Frame};
+use crate::geometry::{Hit, Hittable, AABB};
+use crate::material::Material;
+use crate::math::poly::solve_quartic;
+use crate::math::{orthonormal_basis, Ray, Vec3};
+
+// A ring around axis through center. major_radius is the distance from the center to the
+// middle of the tube and minor_radius is the radius of the tube
+#[derive(Clone)]
+pub struct Torus {
+    pub center: Vec3,
+    pub axis: Vec3,
+    pub major_radius: f32,
+    pub minor_radius: f32,
+    pub material: Material,
+    frame: Frame,
+    aabb: AABB,
+}
+
+impl Torus {
+    pub fn new(
+        center: Vec3,
+        axis: Vec3,
+        major_radius: f32,
+        minor_radius: f32,
+        material: Material,
+    ) -> Torus {
+        let axis = axis.make_unit();
+
+        // Bounds of the ring's outer edge, thickened by the tube along the axis
+        let ring = disk_extent(&axis, major_radius + minor_radius);
+        let tube = minor_radius * Vec3::new(axis.x.abs(), axis.y.abs(), axis.z.abs());
+        let extent = ring + tube;
+
+        Torus {
+            center,
+            axis,
+            major_radius,
+            minor_radius,
+            material,
+            frame: Frame::new(center, axis),
+            aabb: AABB::new(center - extent, center + extent),
+        }
+    }
+}
+
+impl Hittable for Torus {
+    fn intersects_ray(&self, ray: &Ray, t_range: (f32, f32)) -> Option<Hit<'_>> {
+        let local = self.frame.local_ray(ray);
+        let length = local.direction.length();
+        let d = local.direction / length;
+
+        // Start from the point on the ray closest to the center. The quartic is badly
+        // conditioned for far away origins, and it lets rays that miss the bounding
+        // sphere be skipped
+        let t_closest = -local.origin.dot(&d);
+        let o = local.origin + t_closest * d;
+        let outer = self.major_radius + self.minor_radius;
+        if o.length_sq() > outer * outer {
+            return None;
+        }
+
+        // (|p|^2 + R^2 - r^2)^2 = 4R^2(x^2 + z^2) with p = o + td
+        let (ox, oy, oz) = (o.x as f64, o.y as f64, o.z as f64);
+        let (dx, dy, dz) = (d.x as f64, d.y as f64, d.z as f64);
+        let r2 = (self.major_radius as f64).powi(2);
+        let four_r2 = 4.0 * r2;
+        let f = ox * dx + oy * dy + oz * dz;
+        let g = ox * ox + oy * oy + oz * oz + r2 - (self.minor_radius as f64).powi(2);
+        let roots = solve_quartic([
+            g * g - four_r2 * (ox * ox + oz * oz),
+            4.0 * f * g - 2.0 * four_r2 * (ox * dx + oz * dz),
+            4.0 * f * f + 2.0 * g - four_r2 * (dx * dx + dz * dz),
+            4.0 * f,
+            1.0,
+        ]);
+
+        // Roots are sorted so the first one in range is the closest
+        let t = roots
+            .iter()
+            .map(|root| (*root as f32 + t_closest) / length)
+            .find(|t| *t > t_range.0 && *t < t_range.1)?;
+
+        let p = local.point_at_parameter(t);
+        // Away from the nearest point on the ring through the middle of the tube
+        let radial = (p.x * p.x + p.z * p.z).sqrt();
+        let ring = if radial > 0.0 {
+            (self.major_radius / radial) * Vec3::new(p.x, 0.0, p.z)
+        } else {
+            Vec3::new_zeroes()
+        };
+        let normal = p - ring;
+
+        // u goes around the axis and v around the tube
+        let uv = (
+            azimuth(&p),
+            (p.y.atan2(radial - self.major_radius) + PI) / (2.0 * PI),
+        );
+
+        let normal = self.frame.world_vector(&normal).make_unit();
+        let (tangent, bitangent) = orthonormal_basis(&normal);
+
+        Some(Hit {
+            t,
+            point: ray.point_at_parameter(t),
+            normal,
+            shading_normal: normal,
+            tangent,
+            bitangent,
+            uv,
+            vertex_color: None,
+            material: &self.material,
+        })
+    }
+
+    fn bounding_box(&self) -> Option<&AABB> {
+        Some(&self.aabb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::grey;
+    use crate::math::assert_near;
+
+    #[test]
+    fn intersects_ray() {
+        let mat = grey();
+        // Lying flat in the xz plane
+        let torus = Torus::new(Vec3::new_zeroes(), Vec3::new(0.0, 1.0, 0.0), 2.0, 0.5, mat);
+
+        // Through the tube along x, hits the outside of the ring first
+        let ray = Ray {
+            origin: Vec3::new(-5.0, 0.0, 0.0),
+            direction: Vec3::new(1.0, 0.0, 0.0),
+            time: 0.0,
+        };
+        let hit = torus.intersects_ray(&ray, (0.0, 100.0)).unwrap();
+        assert!((hit.t - 2.5).abs() < 1e-3);
+        assert_near(hit.normal, Vec3::new(-1.0, 0.0, 0.0));
+
+        // Then the inside of the ring on the same side
+        let hit = torus.intersects_ray(&ray, (3.0, 100.0)).unwrap();
+        assert!((hit.t - 3.5).abs() < 1e-3);
+        assert_near(hit.normal, Vec3::new(1.0, 0.0, 0.0));
+
+        // Top of the tube from above
+        let ray = Ray {
+            origin: Vec3::new(0.0, 10.0, 2.0),
+            direction: Vec3::new(0.0, -2.0, 0.0),
+            time: 0.0,
+        };
+        let hit = torus.intersects_ray(&ray, (0.0, 100.0)).unwrap();
+        assert!((hit.t - 4.75).abs() < 1e-3);
+        assert_near(hit.normal, Vec3::new(0.0, 1.0, 0.0));
+
+        // Straight down the hole
+        let ray = Ray {
+            origin: Vec3::new(0.0, 10.0, 0.0),
+            direction: Vec3::new(0.0, -1.0, 0.0),
+            time: 0.0,
+        };
+        assert!(torus.intersects_ray(&ray, (0.0, 100.0)).is_none());
+
+        // Outside the bounding sphere
+        let ray = Ray {
+            origin: Vec3::new(-5.0, 3.0, 0.0),
+            direction: Vec3::new(1.0, 0.0, 0.0),
+            time: 0.0,
+        };
+        assert!(torus.intersects_ray(&ray, (0.0, 100.0)).is_none());
+    }
+
+    #[test]
+    fn tilted() {
+        let mat = grey();
+        // Standing up facing z
+        let torus = Torus::new(
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            1.0,
+            0.25,
+            mat,
+        );
+
+        let ray = Ray {
+            origin: Vec3::new(1.0, 1.0, 5.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        let hit = torus.intersects_ray(&ray, (0.0, 100.0)).unwrap();
+        assert!((hit.t - 4.75).abs() < 1e-3);
+        assert_near(hit.normal, Vec3::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn bounding_box() {
+        let mat = grey();
+        let torus = Torus::new(
+            Vec3::new(1.0, 2.0, 3.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            2.0,
+            0.5,
+            mat,
+        );
+
+        let aabb = torus.bounding_box().unwrap();
+        assert_near(aabb.min, Vec3::new(-1.5, 1.5, 0.5));
+        assert_near(aabb.max, Vec3::new(3.5, 2.5, 5.5));
+    }
+}
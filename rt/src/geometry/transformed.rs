@@ -0,0 +1,167 @@
+use crate::geometry::{Hit, Hittable, Light, Span, AABB};
+use crate::math::{Ray, Transform, Vec3};
+
+// Places a hittable in the world with an affine transform. Rays are taken into object
+// space instead of moving the object, so wrapping a shared Object instances it cheaply
+#[derive(Clone)]
+pub struct Transformed<H: Hittable> {
+    hittable: H,
+    transform: Transform,
+    aabb: Option<AABB>,
+}
+
+impl<H: Hittable> Transformed<H> {
+    pub fn new(hittable: H, transform: Transform) -> Transformed<H> {
+        // World bounds are the bounds of the transformed object space corners
+        let aabb = hittable.bounding_box().map(|aabb| {
+            let corner = |i: usize| {
+                let pick = |bit: usize, min: f32, max: f32| if i & bit == 0 { min } else { max };
+                transform.transform_point(&Vec3::new(
+                    pick(1, aabb.min.x, aabb.max.x),
+                    pick(2, aabb.min.y, aabb.max.y),
+                    pick(4, aabb.min.z, aabb.max.z),
+                ))
+            };
+
+            let first = corner(0);
+            let mut world = AABB::new(first, first);
+            for i in 1..8 {
+                let p = corner(i);
+                world.expand(&AABB::new(p, p));
+            }
+            world
+        });
+
+        Transformed {
+            hittable,
+            transform,
+            aabb,
+        }
+    }
+
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    // The direction isn't normalised so t is the same in both spaces
+    fn local_ray(&self, ray: &Ray) -> Ray {
+        let inverse = self.transform.inverse();
+        Ray {
+            origin: inverse.transform_point(&ray.origin),
+            direction: inverse.transform_vector(&ray.direction),
+            time: ray.time,
+        }
+    }
+
+    fn world_hit<'a>(&self, ray: &Ray, hit: Hit<'a>) -> Hit<'a> {
+        Hit {
+            point: ray.point_at_parameter(hit.t),
+            normal: self.transform.transform_normal(&hit.normal).make_unit(),
+            shading_normal: self
+                .transform
+                .transform_normal(&hit.shading_normal)
+                .make_unit(),
+            tangent: self.transform.transform_vector(&hit.tangent).make_unit(),
+            bitangent: self.transform.transform_vector(&hit.bitangent).make_unit(),
+            ..hit
+        }
+    }
+}
+
+impl<H: Hittable> Hittable for Transformed<H> {
+    fn intersects_ray(&self, ray: &Ray, t_range: (f32, f32)) -> Option<Hit<'_>> {
+        let hit = self
+            .hittable
+            .intersects_ray(&self.local_ray(ray), t_range)?;
+        Some(self.world_hit(ray, hit))
+    }
+
+    fn bounding_box(&self) -> Option<&AABB> {
+        self.aabb.as_ref()
+    }
+
+    fn spans(&self, ray: &Ray, t_range: (f32, f32)) -> Vec<Span<'_>> {
+        self.hittable
+            .spans(&self.local_ray(ray), t_range)
+            .into_iter()
+            .map(|span| Span {
+                enter: span.enter.map(|hit| self.world_hit(ray, hit)),
+                exit: span.exit.map(|hit| self.world_hit(ray, hit)),
+            })
+            .collect()
+    }
+
+    fn lights(&self) -> Vec<Light> {
+        self.hittable
+            .lights()
+            .iter()
+            .filter_map(|light| light.transformed(&self.transform))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::{Cuboid, Sphere};
+    use crate::material::grey;
+    use crate::math::assert_near;
+
+    #[test]
+    fn intersects_ray() {
+        let mat = grey();
+        // Unit sphere stretched along x then moved to (0, 0, -5)
+        let transform = Transform::translate(Vec3::new(0.0, 0.0, -5.0))
+            * Transform::scale(Vec3::new(2.0, 1.0, 1.0));
+        let ellipsoid = Transformed::new(Sphere::new(Vec3::new_zeroes(), 1.0, mat), transform);
+
+        // Side of the ellipsoid
+        let ray = Ray {
+            origin: Vec3::new(5.0, 0.0, -5.0),
+            direction: Vec3::new(-1.0, 0.0, 0.0),
+            time: 0.0,
+        };
+        let hit = ellipsoid.intersects_ray(&ray, (0.0, 100.0)).unwrap();
+        assert!((hit.t - 3.0).abs() < 1e-5);
+        assert_near(hit.point, Vec3::new(2.0, 0.0, -5.0));
+        assert_near(hit.normal, Vec3::new(1.0, 0.0, 0.0));
+
+        // Off axis the normal leans less along x than the unscaled one would
+        let ray = Ray {
+            origin: Vec3::new(2.0f32.sqrt(), 0.0, 0.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        let hit = ellipsoid.intersects_ray(&ray, (0.0, 100.0)).unwrap();
+        assert_near(hit.normal, Vec3::new(1.0, 0.0, 2.0).make_unit());
+
+        // Would hit the untransformed sphere
+        let ray = Ray {
+            origin: Vec3::new(0.0, 0.0, 5.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        let hit = ellipsoid.intersects_ray(&ray, (0.0, 100.0)).unwrap();
+        assert!((hit.t - 9.0).abs() < 1e-5);
+        let ray = Ray {
+            origin: Vec3::new(0.0, 2.0, 0.0),
+            direction: Vec3::new(0.0, -1.0, 0.0),
+            time: 0.0,
+        };
+        assert!(ellipsoid.intersects_ray(&ray, (0.0, 100.0)).is_none());
+    }
+
+    #[test]
+    fn bounding_box() {
+        let mat = grey();
+        // Unit cube turned 45 degrees around y
+        let cube = Cuboid::new(Vec3::new(-0.5, -0.5, -0.5), Vec3::new(0.5, 0.5, 0.5), mat);
+        let rotated = Transformed::new(cube, Transform::rotate(Vec3::new(0.0, 1.0, 0.0), 45.0));
+
+        let aabb = rotated.bounding_box().unwrap();
+        let half_diagonal = 0.5 * 2.0f32.sqrt();
+        assert!((aabb.max.x - half_diagonal).abs() < 1e-3);
+        assert!((aabb.max.z - half_diagonal).abs() < 1e-3);
+        assert!((aabb.max.y - 0.5).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,317 @@
+use crate::geometry::{Hit, Hittable, Light, AABB};
+use crate::material::Material;
+use crate::math::{orthonormal_basis, Ray, Vec3};
+
+// Möller–Trumbore ray/triangle intersection.
+// Returns t and the barycentric coordinates (b1, b2) of the hit, weighting p1 and p2
+pub fn intersect_triangle(
+    ray: &Ray,
+    p0: &Vec3,
+    p1: &Vec3,
+    p2: &Vec3,
+    t_range: (f32, f32),
+) -> Option<(f32, f32, f32)> {
+    let edge1 = p1 - p0;
+    let edge2 = p2 - p0;
+
+    let p = ray.direction.cross(&edge2);
+    let det = edge1.dot(&p);
+    // Ray is parallel to the triangle. det scales with both edges and the direction,
+    // so the cutoff does too, otherwise tiny triangles would always be missed
+    let scale = edge1.length_sq() * edge2.length_sq() * ray.direction.length_sq();
+    if det * det <= f32::EPSILON * f32::EPSILON * scale {
+        return None;
+    }
+
+    let inv_det = 1.0 / det;
+    let s = ray.origin - p0;
+    let b1 = s.dot(&p) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+
+    let q = s.cross(&edge1);
+    let b2 = ray.direction.dot(&q) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+
+    let t = edge2.dot(&q) * inv_det;
+    if t > t_range.0 && t < t_range.1 {
+        Some((t, b1, b2))
+    } else {
+        None
+    }
+}
+
+// Bounds of a triangle, padded on every axis
+pub fn triangle_bounds(p0: &Vec3, p1: &Vec3, p2: &Vec3) -> AABB {
+    let min = Vec3::new(
+        p0.x.min(p1.x).min(p2.x),
+        p0.y.min(p1.y).min(p2.y),
+        p0.z.min(p1.z).min(p2.z),
+    );
+    let max = Vec3::new(
+        p0.x.max(p1.x).max(p2.x),
+        p0.y.max(p1.y).max(p2.y),
+        p0.z.max(p1.z).max(p2.z),
+    );
+
+    AABB::new_padded(min, max)
+}
+
+// uvs for triangles without their own, matching the barycentric coordinates
+pub const BARYCENTRIC_UVS: [(f32, f32); 3] = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)];
+
+// Unit directions along which u and v increase across the triangle, made perpendicular
+// to the shading normal
+pub fn tangent_frame(
+    p0: &Vec3,
+    p1: &Vec3,
+    p2: &Vec3,
+    uvs: &[(f32, f32); 3],
+    normal: &Vec3,
+) -> (Vec3, Vec3) {
+    let (edge1, edge2) = (p1 - p0, p2 - p0);
+    let (du1, dv1) = (uvs[1].0 - uvs[0].0, uvs[1].1 - uvs[0].1);
+    let (du2, dv2) = (uvs[2].0 - uvs[0].0, uvs[2].1 - uvs[0].1);
+    let det = du1 * dv2 - du2 * dv1;
+    // Degenerate uvs leave no direction to follow
+    if det.abs() < 1e-12 {
+        return orthonormal_basis(normal);
+    }
+
+    let tangent = (dv2 * edge1 - dv1 * edge2) / det;
+    let bitangent = (du1 * edge2 - du2 * edge1) / det;
+    let tangent = tangent - tangent.dot(normal) * normal;
+    let bitangent = bitangent - bitangent.dot(normal) * normal;
+    if tangent.length_sq() < 1e-12 || bitangent.length_sq() < 1e-12 {
+        return orthonormal_basis(normal);
+    }
+
+    (tangent.make_unit(), bitangent.make_unit())
+}
+
+// Interpolate a per-vertex attribute using barycentric coordinates
+pub fn interpolate(a0: Vec3, a1: Vec3, a2: Vec3, b1: f32, b2: f32) -> Vec3 {
+    (1.0 - b1 - b2) * a0 + b1 * a1 + b2 * a2
+}
+
+// A single standalone triangle. Front face is determined by counter-clockwise winding
+#[derive(Clone)]
+pub struct Triangle {
+    pub vertices: [Vec3; 3],
+    pub normals: Option<[Vec3; 3]>,
+    pub uvs: Option<[(f32, f32); 3]>,
+    pub material: Material,
+    aabb: AABB,
+}
+
+impl Triangle {
+    pub fn new(p0: Vec3, p1: Vec3, p2: Vec3, material: Material) -> Triangle {
+        Triangle {
+            vertices: [p0, p1, p2],
+            normals: None,
+            uvs: None,
+            material,
+            aabb: triangle_bounds(&p0, &p1, &p2),
+        }
+    }
+
+    // Sets per-vertex shading normals
+    pub fn with_normals(mut self, n0: Vec3, n1: Vec3, n2: Vec3) -> Triangle {
+        self.normals = Some([n0, n1, n2]);
+        self
+    }
+
+    // Sets per-vertex texture coordinates
+    pub fn with_uvs(mut self, uv0: (f32, f32), uv1: (f32, f32), uv2: (f32, f32)) -> Triangle {
+        self.uvs = Some([uv0, uv1, uv2]);
+        self
+    }
+}
+
+impl Hittable for Triangle {
+    fn intersects_ray(&self, ray: &Ray, t_range: (f32, f32)) -> Option<Hit<'_>> {
+        let [p0, p1, p2] = &self.vertices;
+        let (t, b1, b2) = intersect_triangle(ray, p0, p1, p2, t_range)?;
+
+        // The geometric normal is turned to the same side as smoothed vertex normals
+        let mut normal = (p1 - p0).cross(&(p2 - p0)).make_unit();
+        let shading_normal = match &self.normals {
+            Some([n0, n1, n2]) => interpolate(*n0, *n1, *n2, b1, b2).make_unit(),
+            None => normal,
+        };
+        if normal.dot(&shading_normal) < 0.0 {
+            normal = -normal;
+        }
+        let uvs = self.uvs.unwrap_or(BARYCENTRIC_UVS);
+        let (tangent, bitangent) = tangent_frame(p0, p1, p2, &uvs, &shading_normal);
+        let uv = match &self.uvs {
+            Some([uv0, uv1, uv2]) => {
+                let uv = interpolate(
+                    Vec3::new(uv0.0, uv0.1, 0.0),
+                    Vec3::new(uv1.0, uv1.1, 0.0),
+                    Vec3::new(uv2.0, uv2.1, 0.0),
+                    b1,
+                    b2,
+                );
+                (uv.x, uv.y)
+            }
+            None => (b1, b2),
+        };
+
+        Some(Hit {
+            t,
+            point: ray.point_at_parameter(t),
+            normal,
+            shading_normal,
+            tangent,
+            bitangent,
+            uv,
+            vertex_color: None,
+            material: &self.material,
+        })
+    }
+
+    fn bounding_box(&self) -> Option<&AABB> {
+        Some(&self.aabb)
+    }
+
+    fn lights(&self) -> Vec<Light> {
+        if self.material.is_emissive() {
+            vec![Light::triangle(self)]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::grey;
+
+    fn unit_triangle() -> Triangle {
+        let mat = grey();
+        Triangle::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            mat,
+        )
+    }
+
+    #[test]
+    fn intersects_ray() {
+        let triangle = unit_triangle();
+
+        let ray = Ray {
+            origin: Vec3::new(0.25, 0.25, 1.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+
+        // Ray should hit the front face
+        let hit = triangle.intersects_ray(&ray, (0.0, 100.0)).unwrap();
+        assert_eq!(hit.t, 1.0);
+        assert_eq!(hit.point, Vec3::new(0.25, 0.25, 0.0));
+        assert_eq!(hit.normal, Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(hit.uv, (0.25, 0.25));
+
+        // Ray should miss when the triangle is outside the t range
+        assert!(triangle.intersects_ray(&ray, (1.5, 100.0)).is_none());
+
+        // Ray should still hit from behind
+        let ray = Ray {
+            origin: Vec3::new(0.25, 0.25, -1.0),
+            direction: Vec3::new(0.0, 0.0, 1.0),
+            time: 0.0,
+        };
+        assert!(triangle.intersects_ray(&ray, (0.0, 100.0)).is_some());
+
+        // Ray should miss outside the hypotenuse
+        let ray = Ray {
+            origin: Vec3::new(0.75, 0.75, 1.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        assert!(triangle.intersects_ray(&ray, (0.0, 100.0)).is_none());
+
+        // Ray parallel to the triangle should miss
+        let ray = Ray {
+            origin: Vec3::new(-1.0, 0.25, 0.0),
+            direction: Vec3::new(1.0, 0.0, 0.0),
+            time: 0.0,
+        };
+        assert!(triangle.intersects_ray(&ray, (0.0, 100.0)).is_none());
+
+        // Tiny triangles, e.g. scanned models in metres, are still hit
+        let mat = grey();
+        let tiny = Triangle::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1e-4, 0.0, 0.0),
+            Vec3::new(0.0, 1e-4, 0.0),
+            mat,
+        );
+        let ray = Ray {
+            origin: Vec3::new(2.5e-5, 2.5e-5, 1.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        assert!(tiny.intersects_ray(&ray, (0.0, 100.0)).is_some());
+    }
+
+    #[test]
+    fn interpolated_attributes() {
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let triangle =
+            unit_triangle()
+                .with_normals(up, up, up)
+                .with_uvs((0.0, 0.0), (1.0, 0.0), (0.0, 1.0));
+
+        let ray = Ray {
+            origin: Vec3::new(0.5, 0.25, 1.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+
+        let hit = triangle.intersects_ray(&ray, (0.0, 100.0)).unwrap();
+        assert_eq!(hit.shading_normal, up);
+        assert_eq!(hit.normal, Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(hit.uv, (0.5, 0.25));
+    }
+
+    #[test]
+    fn tangents() {
+        let ray = Ray {
+            origin: Vec3::new(0.25, 0.25, 1.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+
+        // Without uvs u and v run along the edges from the first vertex
+        let triangle = unit_triangle();
+        let hit = triangle.intersects_ray(&ray, (0.0, 100.0)).unwrap();
+        assert_eq!(hit.tangent, Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(hit.bitangent, Vec3::new(0.0, 1.0, 0.0));
+
+        // Swapped uvs swap the tangents
+        let triangle = unit_triangle().with_uvs((0.0, 0.0), (0.0, 1.0), (1.0, 0.0));
+        let hit = triangle.intersects_ray(&ray, (0.0, 100.0)).unwrap();
+        assert_eq!(hit.tangent, Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(hit.bitangent, Vec3::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn bounding_box() {
+        let triangle = unit_triangle();
+
+        // Flat triangle should still have some thickness
+        let aabb = triangle.bounding_box().unwrap();
+        assert!(aabb.min.z < 0.0);
+        assert!(aabb.max.z > 0.0);
+        assert!(aabb.min.x < 0.0 && aabb.max.x > 1.0);
+        assert!(aabb.min.y < 0.0 && aabb.max.y > 1.0);
+    }
+}
@@ -0,0 +1,273 @@
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::geometry::{Hit, Hittable, AABB};
+use crate::material::Material;
+use crate::math::{Ray, Vec3};
+
+// A dense 3D grid of values, x varying fastest then y then z.
+// The values are shared so grids are cheap to clone
+#[derive(Clone)]
+pub struct VoxelGrid {
+    values: Arc<Vec<f32>>,
+    dims: (usize, usize, usize),
+    max: f32,
+}
+
+impl VoxelGrid {
+    // values is padded with zeroes or truncated to fit dims
+    pub fn new(values: Vec<f32>, dims: (usize, usize, usize)) -> VoxelGrid {
+        let dims = (dims.0.max(1), dims.1.max(1), dims.2.max(1));
+        let mut values = values;
+        values.resize(dims.0 * dims.1 * dims.2, 0.0);
+        let max = values.iter().fold(0.0f32, |a, &b| a.max(b));
+
+        VoxelGrid {
+            values: Arc::new(values),
+            dims,
+            max,
+        }
+    }
+
+    pub fn dims(&self) -> (usize, usize, usize) {
+        self.dims
+    }
+
+    pub fn max_value(&self) -> f32 {
+        self.max
+    }
+
+    fn value(&self, x: usize, y: usize, z: usize) -> f32 {
+        self.values[(z * self.dims.1 + y) * self.dims.0 + x]
+    }
+
+    // Trilinearly interpolated value at p in [0, 1] on each axis.
+    // Voxel centres are at (i + 0.5) / n and the edges are clamped
+    pub fn sample(&self, p: Vec3) -> f32 {
+        let axis = |p: f32, n: usize| {
+            let f = (p * n as f32 - 0.5).clamp(0.0, (n - 1) as f32);
+            let i = (f as usize).min(n.saturating_sub(2));
+            (i, (i + 1).min(n - 1), f - i as f32)
+        };
+        let (x0, x1, fx) = axis(p.x, self.dims.0);
+        let (y0, y1, fy) = axis(p.y, self.dims.1);
+        let (z0, z1, fz) = axis(p.z, self.dims.2);
+
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        let plane = |z: usize| {
+            lerp(
+                lerp(self.value(x0, y0, z), self.value(x1, y0, z), fx),
+                lerp(self.value(x0, y1, z), self.value(x1, y1, z), fx),
+                fy,
+            )
+        };
+        lerp(plane(z0), plane(z1), fz)
+    }
+}
+
+// A volume whose density varies through a box, e.g. a cloud or an explosion.
+// Collisions are found by delta tracking against the densest point so any grid
+// can be rendered without bias. At a collision the ray is either absorbed, taking
+// the emission with it, or scattered by the phase material
+#[derive(Clone)]
+pub struct VoxelVolume {
+    grid: VoxelGrid,
+    aabb: AABB,
+    density: f32,
+    // Upper bound on the density anywhere in the volume
+    majorant: f32,
+    pub phase: Material,
+    // Fraction of collisions that absorb rather than scatter
+    absorption: f32,
+    emission: Material,
+}
+
+impl VoxelVolume {
+    // The grid fills the box from min to max. Grid values are scaled by density
+    pub fn new(
+        grid: VoxelGrid,
+        min: Vec3,
+        max: Vec3,
+        density: f32,
+        phase: Material,
+    ) -> VoxelVolume {
+        let majorant = grid.max_value() * density;
+        VoxelVolume {
+            grid,
+            aabb: AABB::new(min, max),
+            density,
+            majorant,
+            phase,
+            absorption: 0.0,
+            emission: Material::new_emissive(Vec3::new_zeroes()),
+        }
+    }
+
+    // Absorbing collisions glow with emission, so denser parts glow more
+    pub fn with_emission(mut self, absorption: f32, emission: Vec3) -> VoxelVolume {
+        self.absorption = absorption.clamp(0.0, 1.0);
+        self.emission = Material::new_emissive(emission);
+        self
+    }
+
+    pub fn density_at(&self, p: &Vec3) -> f32 {
+        let (min, max) = (self.aabb.min, self.aabb.max);
+        let local = Vec3::new(
+            (p.x - min.x) / (max.x - min.x),
+            (p.y - min.y) / (max.y - min.y),
+            (p.z - min.z) / (max.z - min.z),
+        );
+        self.density * self.grid.sample(local)
+    }
+
+    // Tentative collisions along the ray, spaced as if the volume was uniformly at
+    // its densest. Calls visit with each t until it returns false or the ray leaves
+    fn track(&self, ray: &Ray, t_range: (f32, f32), mut visit: impl FnMut(f32) -> bool) {
+        let Some((t_enter, t_exit)) = self.aabb.clip(ray, t_range) else {
+            return;
+        };
+        if self.majorant <= 0.0 {
+            return;
+        }
+
+        let step = 1.0 / (self.majorant * ray.direction.length());
+        let mut rng = rand::thread_rng();
+        let mut t = t_enter;
+        loop {
+            t -= (1.0 - rng.gen::<f32>()).ln() * step;
+            if t >= t_exit || !visit(t) {
+                return;
+            }
+        }
+    }
+}
+
+impl Hittable for VoxelVolume {
+    fn intersects_ray(&self, ray: &Ray, t_range: (f32, f32)) -> Option<Hit<'_>> {
+        let mut rng = rand::thread_rng();
+        let mut collision = None;
+        // A tentative collision is real with probability density / majorant
+        self.track(ray, t_range, |t| {
+            let density = self.density_at(&ray.point_at_parameter(t));
+            if rng.gen::<f32>() * self.majorant < density {
+                collision = Some(t);
+            }
+            collision.is_none()
+        });
+        let t = collision?;
+
+        let material = if rng.gen::<f32>() < self.absorption {
+            &self.emission
+        } else {
+            &self.phase
+        };
+
+        Some(Hit {
+            t,
+            point: ray.point_at_parameter(t),
+            // Arbitrary, there's no surface inside a volume
+            normal: Vec3::new(1.0, 0.0, 0.0),
+            shading_normal: Vec3::new(1.0, 0.0, 0.0),
+            tangent: Vec3::new(0.0, 1.0, 0.0),
+            bitangent: Vec3::new(0.0, 0.0, 1.0),
+            uv: (0.0, 0.0),
+            vertex_color: None,
+            material,
+        })
+    }
+
+    fn bounding_box(&self) -> Option<&AABB> {
+        Some(&self.aabb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn phase() -> Material {
+        Material::new_isotropic(Vec3::new_uniform(1.0))
+    }
+
+    #[test]
+    fn sample() {
+        // 2x1x1, 0 on the left and 1 on the right
+        let grid = VoxelGrid::new(vec![0.0, 1.0], (2, 1, 1));
+        assert_eq!(grid.sample(Vec3::new(0.25, 0.5, 0.5)), 0.0);
+        assert_eq!(grid.sample(Vec3::new(0.5, 0.5, 0.5)), 0.5);
+        assert_eq!(grid.sample(Vec3::new(0.75, 0.5, 0.5)), 1.0);
+        // Clamped outside the voxel centres
+        assert_eq!(grid.sample(Vec3::new(0.0, 0.0, 1.0)), 0.0);
+        assert_eq!(grid.sample(Vec3::new(1.0, 1.0, 0.0)), 1.0);
+        assert_eq!(grid.max_value(), 1.0);
+
+        // Single voxels are constant
+        let grid = VoxelGrid::new(vec![3.0], (1, 1, 1));
+        assert_eq!(grid.sample(Vec3::new(0.1, 0.9, 0.4)), 3.0);
+
+        // Missing values are 0
+        let grid = VoxelGrid::new(vec![1.0], (2, 2, 2));
+        assert_eq!(grid.sample(Vec3::new(0.75, 0.75, 0.75)), 0.0);
+    }
+
+    #[test]
+    fn tracking() {
+        // Unit cube at density 2, half of it empty so tracking has null collisions
+        let grid = VoxelGrid::new(vec![1.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0], (2, 2, 2));
+        let volume = VoxelVolume::new(
+            grid,
+            Vec3::new_zeroes(),
+            Vec3::new_uniform(1.0),
+            2.0,
+            phase(),
+        );
+
+        // Through the dense half only the transmittance is exp(-2), so delta tracking
+        // collides with the rest
+        let ray = Ray {
+            origin: Vec3::new(-1.0, 0.5, 0.1),
+            direction: Vec3::new(2.0, 0.0, 0.0),
+            time: 0.0,
+        };
+        let n = 20000;
+        let expected = (-2.0f32).exp();
+        let mut hits = 0;
+        for _ in 0..n {
+            if let Some(hit) = volume.intersects_ray(&ray, (0.0, 100.0)) {
+                assert!(hit.t >= 0.5 && hit.t < 1.0);
+                hits += 1;
+            }
+        }
+        assert!((hits as f32 / n as f32 - (1.0 - expected)).abs() < 0.02);
+
+        // Nothing through the empty half
+        let ray = Ray {
+            origin: Vec3::new(-1.0, 0.5, 0.9),
+            direction: Vec3::new(1.0, 0.0, 0.0),
+            time: 0.0,
+        };
+        assert!(volume.intersects_ray(&ray, (0.0, 100.0)).is_none());
+    }
+
+    #[test]
+    fn emission() {
+        let grid = VoxelGrid::new(vec![1.0], (1, 1, 1));
+        let volume = VoxelVolume::new(
+            grid,
+            Vec3::new_zeroes(),
+            Vec3::new_uniform(1.0),
+            1e6,
+            phase(),
+        )
+        .with_emission(1.0, Vec3::new(4.0, 2.0, 1.0));
+
+        let ray = Ray {
+            origin: Vec3::new(0.5, 0.5, -1.0),
+            direction: Vec3::new(0.0, 0.0, 1.0),
+            time: 0.0,
+        };
+        let hit = volume.intersects_ray(&ray, (0.0, 100.0)).unwrap();
+        assert!(matches!(hit.material, Material::Emissive(_)));
+    }
+}
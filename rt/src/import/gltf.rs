@@ -0,0 +1,492 @@
+use std::fs;
+use std::path::Path;
+
+use crate::camera::Camera;
+use crate::geometry::{MeshData, TriangleMesh};
+use crate::import::image::parse_png_image;
+use crate::import::ImportError;
+use crate::material::{Image, Material, Principled, Texture, WrapMode};
+use crate::math::{Transform, Vec3};
+
+// A perspective camera placed in the scene
+pub struct GltfCamera {
+    pub look_from: Vec3,
+    pub look_at: Vec3,
+    pub v_up: Vec3,
+    pub v_fov: f32, // Degrees
+    pub aspect: Option<f32>,
+}
+
+impl GltfCamera {
+    // The aspect ratio is optional in glTF, default_aspect is used when it's missing
+    pub fn to_camera(&self, default_aspect: f32) -> Camera {
+        Camera::new(
+            self.look_from,
+            self.look_at,
+            self.v_up,
+            self.v_fov,
+            self.aspect.unwrap_or(default_aspect),
+        )
+    }
+}
+
+pub struct GltfScene {
+    // One mesh per primitive, already transformed into world space
+    pub meshes: Vec<TriangleMesh>,
+    // The first perspective camera found in the scene
+    pub camera: Option<GltfCamera>,
+}
+
+// Loads the default scene (or first scene) of a .gltf or .glb file.
+// Primitives without a material use default_material
+pub fn load_gltf(
+    path: impl AsRef<Path>,
+    default_material: Material,
+) -> Result<GltfScene, ImportError> {
+    let path = path.as_ref();
+    let gltf = gltf::Gltf::from_slice(&fs::read(path)?).map_err(gltf_error)?;
+
+    // External buffers are relative to the file
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut buffers = Vec::new();
+    for buffer in gltf.buffers() {
+        let data = match buffer.source() {
+            gltf::buffer::Source::Bin => gltf
+                .blob
+                .clone()
+                .ok_or_else(|| ImportError::parse(0, "missing binary chunk"))?,
+            gltf::buffer::Source::Uri(uri) => match uri.strip_prefix("data:") {
+                Some(data_uri) => decode_data_uri(data_uri)?,
+                None => fs::read(dir.join(uri))?,
+            },
+        };
+        buffers.push(data);
+    }
+    let images = load_images(&gltf, &buffers, dir)?;
+
+    let scene = gltf
+        .default_scene()
+        .or_else(|| gltf.scenes().next())
+        .ok_or_else(|| ImportError::parse(0, "file has no scenes"))?;
+
+    let mut result = GltfScene {
+        meshes: Vec::new(),
+        camera: None,
+    };
+    for node in scene.nodes() {
+        visit_node(
+            &node,
+            &Transform::identity(),
+            &buffers,
+            &images,
+            &default_material,
+            &mut result,
+        )?;
+    }
+
+    Ok(result)
+}
+
+fn visit_node(
+    node: &gltf::Node,
+    parent: &Transform,
+    buffers: &[Vec<u8>],
+    images: &[Option<Image>],
+    default_material: &Material,
+    scene: &mut GltfScene,
+) -> Result<(), ImportError> {
+    let world = *parent * Transform::from_column_major(node.transform().matrix());
+
+    if let Some(mesh) = node.mesh() {
+        for primitive in mesh.primitives() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                continue;
+            }
+            let data = read_primitive(&primitive, &world, buffers)?;
+            let material = to_material(&primitive.material(), images, default_material);
+            scene.meshes.push(TriangleMesh::new(data, material));
+        }
+    }
+
+    if let (Some(camera), None) = (node.camera(), &scene.camera) {
+        if let gltf::camera::Projection::Perspective(perspective) = camera.projection() {
+            // Cameras look down -z with +y up
+            let look_from = world.transform_point(&Vec3::new_zeroes());
+            let forward = world.transform_vector(&Vec3::new(0.0, 0.0, -1.0));
+            scene.camera = Some(GltfCamera {
+                look_from,
+                look_at: look_from + forward,
+                v_up: world.transform_vector(&Vec3::new(0.0, 1.0, 0.0)),
+                v_fov: perspective.yfov().to_degrees(),
+                aspect: perspective.aspect_ratio(),
+            });
+        }
+    }
+
+    for child in node.children() {
+        visit_node(&child, &world, buffers, images, default_material, scene)?;
+    }
+
+    Ok(())
+}
+
+fn read_primitive(
+    primitive: &gltf::Primitive,
+    world: &Transform,
+    buffers: &[Vec<u8>],
+) -> Result<MeshData, ImportError> {
+    let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|b| b.as_slice()));
+
+    let positions: Vec<Vec3> = reader
+        .read_positions()
+        .ok_or_else(|| ImportError::parse(0, "primitive has no positions"))?
+        .map(|p| world.transform_point(&Vec3::new(p[0], p[1], p[2])))
+        .collect();
+    let normals = reader.read_normals().map(|normals| {
+        normals
+            .map(|n| {
+                world
+                    .transform_normal(&Vec3::new(n[0], n[1], n[2]))
+                    .make_unit()
+            })
+            .collect()
+    });
+    // glTF's uvs start at the top left of an image, textures here at the bottom left
+    let uvs = reader
+        .read_tex_coords(0)
+        .map(|uvs| uvs.into_f32().map(|uv| (uv[0], 1.0 - uv[1])).collect());
+    let colors = reader.read_colors(0).map(|colors| {
+        colors
+            .into_rgb_f32()
+            .map(|c| Vec3::new(c[0], c[1], c[2]))
+            .collect()
+    });
+
+    let flat: Vec<usize> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().map(|i| i as usize).collect(),
+        None => (0..positions.len()).collect(),
+    };
+    if flat.iter().any(|&i| i >= positions.len()) {
+        return Err(ImportError::parse(0, "index out of range"));
+    }
+
+    // Mirroring transforms flip the winding, swap it back so geometric normals face out
+    let mirrored = world.determinant() < 0.0;
+    let indices = flat
+        .chunks_exact(3)
+        .map(|f| {
+            if mirrored {
+                [f[0], f[2], f[1]]
+            } else {
+                [f[0], f[1], f[2]]
+            }
+        })
+        .collect();
+
+    Ok(MeshData {
+        positions,
+        normals,
+        uvs,
+        colors,
+        indices,
+    })
+}
+
+// Maps metallic-roughness materials onto a principled material
+fn to_material(
+    material: &gltf::Material,
+    images: &[Option<Image>],
+    default_material: &Material,
+) -> Material {
+    if material.index().is_none() {
+        return default_material.clone();
+    }
+
+    let pbr = material.pbr_metallic_roughness();
+    let [r, g, b, _] = pbr.base_color_factor();
+    let base_color = color_texture(pbr.base_color_texture(), Vec3::new(r, g, b), images);
+
+    let [er, eg, eb] = material.emissive_factor();
+    let emission = color_texture(
+        material.emissive_texture(),
+        material.emissive_strength().unwrap_or(1.0) * Vec3::new(er, eg, eb),
+        images,
+    );
+
+    let principled = Principled {
+        metallic: pbr.metallic_factor(),
+        roughness: pbr.roughness_factor(),
+        // glTF's factor scales the usual 4% reflectance
+        specular: 0.5 * material.specular().map_or(1.0, |s| s.specular_factor()),
+        transmission: material
+            .transmission()
+            .map_or(0.0, |t| t.transmission_factor()),
+        ior: material.ior().unwrap_or(1.5),
+        emission,
+        ..Principled::new(base_color)
+    };
+    let principled = Material::new_principled(principled);
+    match material.volume() {
+        Some(volume) => {
+            let [ar, ag, ab] = volume.attenuation_color();
+            principled.with_absorption(Vec3::new(ar, ag, ab), volume.attenuation_distance())
+        }
+        None => principled,
+    }
+}
+
+// A colour factor multiplied by its texture, when there is one that can be used.
+// Only PNG images on the first set of uvs are, anything else leaves the factor
+fn color_texture(
+    info: Option<gltf::texture::Info>,
+    factor: Vec3,
+    images: &[Option<Image>],
+) -> Texture {
+    let Some(info) = info.filter(|info| info.tex_coord() == 0) else {
+        return factor.into();
+    };
+    let texture = info.texture();
+    let Some(Some(image)) = images.get(texture.source().index()) else {
+        return factor.into();
+    };
+
+    // The factor is baked in as textures here can't be multiplied
+    let (width, height) = (image.width(), image.height());
+    let pixels = (0..height)
+        .flat_map(|y| (0..width).map(move |x| factor * image.pixel(x, y)))
+        .collect();
+    let wrap = match texture.sampler().wrap_s() {
+        gltf::texture::WrappingMode::Repeat => WrapMode::Repeat,
+        gltf::texture::WrappingMode::MirroredRepeat => WrapMode::Mirror,
+        gltf::texture::WrappingMode::ClampToEdge => WrapMode::Clamp,
+    };
+    Texture::new_image(Image::new(pixels, width, height)).with_wrap(wrap)
+}
+
+// Decodes every image in the file from sRGB, by index. Images that aren't PNGs
+// can't be decoded and are None
+fn load_images(
+    gltf: &gltf::Gltf,
+    buffers: &[Vec<u8>],
+    dir: &Path,
+) -> Result<Vec<Option<Image>>, ImportError> {
+    let mut images = Vec::new();
+    for image in gltf.images() {
+        let bytes = match image.source() {
+            gltf::image::Source::View { view, .. } => buffers
+                .get(view.buffer().index())
+                .and_then(|buffer| buffer.get(view.offset()..view.offset() + view.length()))
+                .ok_or_else(|| ImportError::parse(0, "image outside its buffer"))?
+                .to_vec(),
+            gltf::image::Source::Uri { uri, .. } => match uri.strip_prefix("data:") {
+                Some(data_uri) => decode_data_uri(data_uri)?,
+                None => fs::read(dir.join(uri))?,
+            },
+        };
+        images.push(parse_png_image(&bytes, true).ok());
+    }
+
+    Ok(images)
+}
+
+fn gltf_error(e: gltf::Error) -> ImportError {
+    match e {
+        gltf::Error::Io(e) => ImportError::Io(e),
+        e => ImportError::parse(0, e.to_string()),
+    }
+}
+
+// Only base64 data uris are allowed in glTF
+fn decode_data_uri(data_uri: &str) -> Result<Vec<u8>, ImportError> {
+    let (_, encoded) = data_uri
+        .split_once(";base64,")
+        .ok_or_else(|| ImportError::parse(0, "data uri is not base64"))?;
+
+    let mut bytes = Vec::with_capacity(encoded.len() * 3 / 4);
+    let mut accumulator: u32 = 0;
+    let mut bits = 0;
+    for c in encoded.bytes().filter(|&c| c != b'=') {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return Err(ImportError::parse(0, "invalid base64 in data uri")),
+        };
+        accumulator = (accumulator << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((accumulator >> bits) as u8);
+        }
+    }
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Hittable;
+    use crate::math::Ray;
+
+    // A single triangle in a child node, translated by its parent, plus a camera
+    const TRIANGLE: &str = r#"{
+        "asset": { "version": "2.0" },
+        "scene": 0,
+        "scenes": [{ "nodes": [0, 2] }],
+        "nodes": [
+            { "translation": [0.0, 0.0, -2.0], "children": [1] },
+            { "mesh": 0, "scale": [2.0, 2.0, 2.0] },
+            { "camera": 0, "translation": [0.0, 0.0, 5.0] }
+        ],
+        "cameras": [{ "type": "perspective", "perspective": { "yfov": 1.0, "znear": 0.1 } }],
+        "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 }, "material": 0 }] }],
+        "materials": [{ "pbrMetallicRoughness": { "baseColorFactor": [1.0, 0.0, 0.0, 1.0], "metallicFactor": 1.0 } }],
+        "accessors": [{
+            "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+            "min": [0.0, 0.0, 0.0], "max": [1.0, 1.0, 0.0]
+        }],
+        "bufferViews": [{ "buffer": 0, "byteLength": 36 }],
+        "buffers": [{
+            "byteLength": 36,
+            "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAA"
+        }]
+    }"#;
+
+    #[test]
+    fn load() {
+        let path = std::env::temp_dir().join("rt_gltf_load_test.gltf");
+        fs::write(&path, TRIANGLE).unwrap();
+        let scene = load_gltf(&path, Material::new_dielectric(1.5)).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(scene.meshes.len(), 1);
+        let mesh = &scene.meshes[0];
+        assert!(matches!(
+            mesh.intersects_ray(
+                &Ray {
+                    origin: Vec3::new(0.5, 0.5, 0.0),
+                    direction: Vec3::new(0.0, 0.0, -1.0),
+                    time: 0.0,
+                },
+                (0.0, 100.0),
+            ),
+            Some(hit) if hit.t == 2.0 && matches!(hit.material, Material::Principled(p) if p.metallic == 1.0)
+        ));
+
+        // Scaled by 2 so this is inside the triangle
+        let aabb = mesh.bounding_box().unwrap();
+        assert!(aabb.max.x > 1.9 && aabb.max.y > 1.9);
+
+        let camera = scene.camera.unwrap();
+        assert_eq!(camera.look_from, Vec3::new(0.0, 0.0, 5.0));
+        assert_eq!(camera.look_at, Vec3::new(0.0, 0.0, 4.0));
+        assert_eq!(camera.v_up, Vec3::new(0.0, 1.0, 0.0));
+        assert!(camera.aspect.is_none());
+    }
+
+    #[test]
+    fn materials() {
+        // A 2x1 red and white PNG, embedded as base64
+        let mut png_bytes = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut png_bytes, 2, 1);
+            encoder.set_color(png::ColorType::Rgb);
+            let mut writer = encoder.write_header().unwrap();
+            writer
+                .write_image_data(&[255, 0, 0, 255, 255, 255])
+                .unwrap();
+        }
+        const DIGITS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut encoded = String::new();
+        for chunk in png_bytes.chunks(3) {
+            let bits = chunk
+                .iter()
+                .enumerate()
+                .fold(0u32, |acc, (i, &b)| acc | (b as u32) << (16 - 8 * i));
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    encoded.push(DIGITS[(bits >> (18 - 6 * i) & 63) as usize] as char);
+                } else {
+                    encoded.push('=');
+                }
+            }
+        }
+
+        let json = format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "materials": [{{
+                    "pbrMetallicRoughness": {{
+                        "baseColorFactor": [0.5, 0.5, 0.5, 1.0],
+                        "baseColorTexture": {{ "index": 0 }},
+                        "metallicFactor": 1.0
+                    }},
+                    "emissiveFactor": [1.0, 0.5, 0.0]
+                }}],
+                "textures": [{{ "source": 0 }}],
+                "images": [{{ "uri": "data:image/png;base64,{}" }}]
+            }}"#,
+            encoded
+        );
+        let gltf = gltf::Gltf::from_slice(json.as_bytes()).unwrap();
+        let images = load_images(&gltf, &[], Path::new("")).unwrap();
+        let material = to_material(
+            &gltf.materials().next().unwrap(),
+            &images,
+            &Material::new_dielectric(1.5),
+        );
+
+        // Glowing surfaces keep the rest of their material
+        let Material::Principled(principled) = material else {
+            panic!("expected a principled material");
+        };
+        assert_eq!(principled.metallic, 1.0);
+        assert!(matches!(
+            principled.emission,
+            Texture::Constant(c) if c == Vec3::new(1.0, 0.5, 0.0)
+        ));
+
+        // With the base colour factor baked into the texture
+        let Texture::Image { image, .. } = &principled.base_color else {
+            panic!("expected an image texture");
+        };
+        assert_eq!(image.pixel(0, 0), Vec3::new(0.5, 0.0, 0.0));
+        assert_eq!(image.pixel(1, 0), Vec3::new_uniform(0.5));
+    }
+
+    #[test]
+    fn data_uri() {
+        let decoded = decode_data_uri("application/octet-stream;base64,aGVsbG8=").unwrap();
+        assert_eq!(decoded, b"hello");
+        assert!(decode_data_uri("text/plain,hello").is_err());
+    }
+
+    #[test]
+    fn mirrored_normals() {
+        // Mirroring the triangle in x flips its winding, which has to be swapped back
+        let mirrored = TRIANGLE.replace(
+            r#""scale": [2.0, 2.0, 2.0]"#,
+            r#""scale": [-1.0, 1.0, 1.0]"#,
+        );
+        let path = std::env::temp_dir().join("rt_gltf_mirrored_test.gltf");
+        fs::write(&path, mirrored).unwrap();
+        let scene = load_gltf(&path, Material::new_dielectric(1.5)).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let hit = scene.meshes[0]
+            .intersects_ray(
+                &Ray {
+                    origin: Vec3::new(-0.25, 0.25, 0.0),
+                    direction: Vec3::new(0.0, 0.0, -1.0),
+                    time: 0.0,
+                },
+                (0.0, 100.0),
+            )
+            .unwrap();
+        assert_eq!(hit.t, 2.0);
+        assert_eq!(hit.normal, Vec3::new(0.0, 0.0, 1.0));
+    }
+}
@@ -0,0 +1,182 @@
+use std::fs;
+use std::path::Path;
+
+use crate::geometry::Heightfield;
+use crate::import::ImportError;
+use crate::material::Material;
+use crate::math::Vec3;
+
+// A row major grid of heights with width samples per row
+pub struct HeightGrid {
+    pub heights: Vec<f32>,
+    pub width: usize,
+}
+
+impl HeightGrid {
+    pub fn to_heightfield(&self, corner: Vec3, size: Vec3, material: Material) -> Heightfield {
+        Heightfield::new(&self.heights, self.width, corner, size, material)
+    }
+}
+
+// Loads a greyscale PNG as a heightfield spanning size from corner.
+// Black is corner.y and white is corner.y + size.y
+pub fn load_png_heightfield(
+    path: impl AsRef<Path>,
+    corner: Vec3,
+    size: Vec3,
+    material: Material,
+) -> Result<Heightfield, ImportError> {
+    let bytes = fs::read(path)?;
+    Ok(parse_png_heights(&bytes)?.to_heightfield(corner, size, material))
+}
+
+// Loads little endian f32 heights, width per row. Heights are scaled by size.y
+pub fn load_raw_heightfield(
+    path: impl AsRef<Path>,
+    width: usize,
+    corner: Vec3,
+    size: Vec3,
+    material: Material,
+) -> Result<Heightfield, ImportError> {
+    let bytes = fs::read(path)?;
+    Ok(parse_raw_heights(&bytes, width)?.to_heightfield(corner, size, material))
+}
+
+// Heights are in [0, 1]. Image rows run along z. Colour images use the average
+// of their channels and alpha is ignored
+pub fn parse_png_heights(bytes: &[u8]) -> Result<HeightGrid, ImportError> {
+    let mut decoder = png::Decoder::new(bytes);
+    // Palettes become rgb and low bit depths become 8 bit
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder
+        .read_info()
+        .map_err(|e| ImportError::parse(0, e.to_string()))?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut buf)
+        .map_err(|e| ImportError::parse(0, e.to_string()))?;
+
+    let (width, depth) = (info.width as usize, info.height as usize);
+    if width < 2 || depth < 2 {
+        return Err(ImportError::parse(0, "heightmap must be at least 2x2"));
+    }
+
+    let channels = match info.color_type {
+        png::ColorType::Grayscale | png::ColorType::GrayscaleAlpha => 1,
+        _ => 3,
+    };
+    let samples = info.color_type.samples();
+    let sample = |row: &[u8], i: usize| match info.bit_depth {
+        png::BitDepth::Sixteen => {
+            u16::from_be_bytes([row[i * 2], row[i * 2 + 1]]) as f32 / u16::MAX as f32
+        }
+        _ => row[i] as f32 / u8::MAX as f32,
+    };
+
+    let mut heights = Vec::with_capacity(width * depth);
+    for row in buf.chunks(info.line_size).take(depth) {
+        for x in 0..width {
+            let total: f32 = (0..channels).map(|c| sample(row, x * samples + c)).sum();
+            heights.push(total / channels as f32);
+        }
+    }
+
+    Ok(HeightGrid { heights, width })
+}
+
+pub fn parse_raw_heights(bytes: &[u8], width: usize) -> Result<HeightGrid, ImportError> {
+    let row_size = width * 4;
+    if width < 2 || !bytes.len().is_multiple_of(row_size) || bytes.len() / row_size < 2 {
+        return Err(ImportError::parse(
+            0,
+            format!(
+                "{} bytes is not a whole number of rows of {} heights, with at least 2 rows",
+                bytes.len(),
+                width
+            ),
+        ));
+    }
+
+    let heights = bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+        .collect();
+
+    Ok(HeightGrid { heights, width })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_png(
+        width: u32,
+        height: u32,
+        color: png::ColorType,
+        depth: png::BitDepth,
+        data: &[u8],
+    ) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, width, height);
+        encoder.set_color(color);
+        encoder.set_depth(depth);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(data).unwrap();
+        writer.finish().unwrap();
+        bytes
+    }
+
+    #[test]
+    fn parse_png() {
+        let bytes = encode_png(
+            2,
+            2,
+            png::ColorType::Grayscale,
+            png::BitDepth::Eight,
+            &[0, 255, 51, 102],
+        );
+        let grid = parse_png_heights(&bytes).unwrap();
+        assert_eq!(grid.width, 2);
+        assert_eq!(grid.heights, vec![0.0, 1.0, 0.2, 0.4]);
+
+        // 16 bit rgb averages the channels
+        let mut data = Vec::new();
+        for value in [
+            0u16, 65535, 65535, 0, 0, 0, 65535, 65535, 65535, 0, 0, 65535,
+        ] {
+            data.extend_from_slice(&value.to_be_bytes());
+        }
+        let bytes = encode_png(2, 2, png::ColorType::Rgb, png::BitDepth::Sixteen, &data);
+        let grid = parse_png_heights(&bytes).unwrap();
+        assert!((grid.heights[0] - 2.0 / 3.0).abs() < 1e-6);
+        assert_eq!(grid.heights[1], 0.0);
+        assert_eq!(grid.heights[2], 1.0);
+        assert!((grid.heights[3] - 1.0 / 3.0).abs() < 1e-6);
+
+        // A single row isn't a surface
+        let bytes = encode_png(
+            2,
+            1,
+            png::ColorType::Grayscale,
+            png::BitDepth::Eight,
+            &[0, 0],
+        );
+        assert!(parse_png_heights(&bytes).is_err());
+        assert!(parse_png_heights(b"not a png").is_err());
+    }
+
+    #[test]
+    fn parse_raw() {
+        let bytes: Vec<u8> = [0.0f32, 1.5, -2.0, 4.0, 0.5, 0.25]
+            .iter()
+            .flat_map(|h| h.to_le_bytes())
+            .collect();
+        let grid = parse_raw_heights(&bytes, 3).unwrap();
+        assert_eq!(grid.width, 3);
+        assert_eq!(grid.heights, vec![0.0, 1.5, -2.0, 4.0, 0.5, 0.25]);
+
+        assert!(parse_raw_heights(&bytes, 4).is_err());
+        assert!(parse_raw_heights(&bytes, 6).is_err());
+        assert!(parse_raw_heights(&bytes[..22], 3).is_err());
+    }
+}
@@ -0,0 +1,109 @@
+use std::fs;
+use std::path::Path;
+
+use crate::import::ImportError;
+use crate::material::Image;
+use crate::math::Vec3;
+
+// Loads a PNG for use as a texture, see parse_png_image
+pub fn load_png_image(path: impl AsRef<Path>, srgb: bool) -> Result<Image, ImportError> {
+    parse_png_image(&fs::read(path)?, srgb)
+}
+
+// Colours are converted from sRGB to linear when srgb is set. Data like normal
+// maps should be loaded without it. Greyscale images are copied to every channel
+// and alpha is ignored
+pub fn parse_png_image(bytes: &[u8], srgb: bool) -> Result<Image, ImportError> {
+    let mut decoder = png::Decoder::new(bytes);
+    // Palettes become rgb and low bit depths become 8 bit
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder
+        .read_info()
+        .map_err(|e| ImportError::parse(0, e.to_string()))?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut buf)
+        .map_err(|e| ImportError::parse(0, e.to_string()))?;
+
+    let (width, height) = (info.width as usize, info.height as usize);
+    let grey = matches!(
+        info.color_type,
+        png::ColorType::Grayscale | png::ColorType::GrayscaleAlpha
+    );
+    let samples = info.color_type.samples();
+    let sample = |row: &[u8], i: usize| {
+        let value = match info.bit_depth {
+            png::BitDepth::Sixteen => {
+                u16::from_be_bytes([row[i * 2], row[i * 2 + 1]]) as f32 / u16::MAX as f32
+            }
+            _ => row[i] as f32 / u8::MAX as f32,
+        };
+        if srgb {
+            srgb_to_linear(value)
+        } else {
+            value
+        }
+    };
+
+    let mut pixels = Vec::with_capacity(width * height);
+    for row in buf.chunks(info.line_size).take(height) {
+        for x in 0..width {
+            let i = x * samples;
+            pixels.push(if grey {
+                Vec3::new_uniform(sample(row, i))
+            } else {
+                Vec3::new(sample(row, i), sample(row, i + 1), sample(row, i + 2))
+            });
+        }
+    }
+
+    Ok(Image::new(pixels, width, height))
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(width: u32, height: u32, color: png::ColorType, data: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut bytes, width, height);
+            encoder.set_color(color);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(data).unwrap();
+        }
+        bytes
+    }
+
+    #[test]
+    fn parse_png() {
+        // 2x1 rgb, red then white
+        let bytes = encode(2, 1, png::ColorType::Rgb, &[255, 0, 0, 255, 255, 255]);
+        let image = parse_png_image(&bytes, true).unwrap();
+        assert_eq!((image.width(), image.height()), (2, 1));
+        assert_eq!(image.pixel(0, 0), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(image.pixel(1, 0), Vec3::new_uniform(1.0));
+
+        // 1x2 greyscale, mid grey is darker once linear
+        let bytes = encode(1, 2, png::ColorType::Grayscale, &[0, 128]);
+        let image = parse_png_image(&bytes, true).unwrap();
+        assert_eq!(image.pixel(0, 0), Vec3::new_zeroes());
+        let grey = image.pixel(0, 1);
+        assert!((grey.x - 0.2158).abs() < 1e-3);
+        assert_eq!(grey.x, grey.z);
+        // Unless it's data
+        let image = parse_png_image(&bytes, false).unwrap();
+        assert_eq!(image.pixel(0, 1).x, 128.0 / 255.0);
+
+        assert!(parse_png_image(b"not a png", true).is_err());
+    }
+}
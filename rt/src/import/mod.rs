@@ -0,0 +1,63 @@
+pub mod gltf;
+pub mod heightmap;
+pub mod image;
+pub mod obj;
+pub mod ply;
+pub mod stl;
+pub mod voxels;
+
+use std::fmt;
+
+use crate::geometry::{BVHNode, Hittable, HittableList, Object, TriangleMesh};
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(std::io::Error),
+    // A malformed file. line is 1-based, or 0 if it doesn't apply
+    Parse { line: usize, message: String },
+}
+
+impl ImportError {
+    pub fn parse(line: usize, message: impl Into<String>) -> ImportError {
+        ImportError::Parse {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportError::Io(e) => write!(f, "io error: {}", e),
+            ImportError::Parse { line: 0, message } => write!(f, "parse error: {}", message),
+            ImportError::Parse { line, message } => {
+                write!(f, "parse error on line {}: {}", line, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<std::io::Error> for ImportError {
+    fn from(e: std::io::Error) -> ImportError {
+        ImportError::Io(e)
+    }
+}
+
+// Combines imported meshes into a single object
+pub fn meshes_to_object(meshes: Vec<TriangleMesh>) -> Object {
+    let mut meshes = meshes;
+    match meshes.len() {
+        0 => Object::new(HittableList::new()),
+        1 => Object::new(meshes.remove(0)),
+        _ => {
+            let list: Vec<Box<dyn Hittable>> = meshes
+                .into_iter()
+                .map(|m| Box::new(m) as Box<dyn Hittable>)
+                .collect();
+            Object::new(BVHNode::new(list))
+        }
+    }
+}
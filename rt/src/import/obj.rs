@@ -0,0 +1,482 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::geometry::{MeshData, TriangleMesh};
+use crate::import::ImportError;
+use crate::material::{Material, Principled};
+use crate::math::Vec3;
+
+// Loads a Wavefront OBJ file along with any MTL libraries it references.
+// Faces are grouped into one mesh per material. Faces without a material use default_material
+pub fn load_obj(
+    path: impl AsRef<Path>,
+    default_material: Material,
+) -> Result<Vec<TriangleMesh>, ImportError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path)?;
+
+    // MTL paths are relative to the OBJ file
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut materials = HashMap::new();
+    for line in source.lines() {
+        let mut tokens = line.split_whitespace();
+        if tokens.next() == Some("mtllib") {
+            for lib in tokens {
+                materials.extend(parse_mtl(&fs::read_to_string(dir.join(lib))?)?);
+            }
+        }
+    }
+
+    parse_obj(&source, &materials, default_material)
+}
+
+// Parses MTL source into materials keyed by name
+pub fn parse_mtl(source: &str) -> Result<HashMap<String, Material>, ImportError> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlEntry)> = None;
+
+    for (i, line) in source.lines().enumerate() {
+        let line_no = i + 1;
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(k) if !k.starts_with('#') => k,
+            _ => continue,
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        if keyword == "newmtl" {
+            if let Some((name, entry)) = current.take() {
+                materials.insert(name, entry.to_material());
+            }
+            let name = args.join(" ");
+            if name.is_empty() {
+                return Err(ImportError::parse(line_no, "newmtl without a name"));
+            }
+            current = Some((name, MtlEntry::default()));
+            continue;
+        }
+
+        let entry = match &mut current {
+            Some((_, entry)) => entry,
+            // Statements before the first newmtl have nothing to apply to
+            None => continue,
+        };
+        match keyword {
+            "Kd" => entry.kd = parse_vec3(&args, line_no)?,
+            "Ks" => entry.ks = parse_vec3(&args, line_no)?,
+            "Ke" => entry.ke = parse_vec3(&args, line_no)?,
+            "Tf" => entry.tf = Some(parse_vec3(&args, line_no)?),
+            "Ns" => entry.ns = parse_f32(&args, 0, line_no)?,
+            "Ni" => entry.ni = Some(parse_f32(&args, 0, line_no)?),
+            "d" => entry.d = parse_f32(&args, 0, line_no)?,
+            "Tr" => entry.d = 1.0 - parse_f32(&args, 0, line_no)?,
+            "illum" => entry.illum = parse_f32(&args, 0, line_no)? as u32,
+            // The PBR extension
+            "Pr" => entry.pr = Some(parse_f32(&args, 0, line_no)?),
+            "Pm" => entry.pm = Some(parse_f32(&args, 0, line_no)?),
+            "Ps" => entry.ps = parse_f32(&args, 0, line_no)?,
+            "Pc" => entry.pc = parse_f32(&args, 0, line_no)?,
+            "Pcr" => entry.pcr = Some(parse_f32(&args, 0, line_no)?),
+            // Texture maps and other statements aren't supported
+            _ => {}
+        }
+    }
+
+    if let Some((name, entry)) = current {
+        materials.insert(name, entry.to_material());
+    }
+
+    Ok(materials)
+}
+
+// Parses OBJ source into one mesh per material used.
+// mtllib statements are ignored, materials should already be loaded into materials
+pub fn parse_obj(
+    source: &str,
+    materials: &HashMap<String, Material>,
+    default_material: Material,
+) -> Result<Vec<TriangleMesh>, ImportError> {
+    let mut positions: Vec<Vec3> = Vec::new();
+    let mut uvs: Vec<(f32, f32)> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+
+    // Groups are kept in the order their material was first used
+    let mut groups: Vec<(Option<String>, MeshBuilder)> = vec![(None, MeshBuilder::default())];
+    let mut current_group = 0;
+
+    for (i, line) in source.lines().enumerate() {
+        let line_no = i + 1;
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(k) if !k.starts_with('#') => k,
+            _ => continue,
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "v" => positions.push(parse_vec3(&args, line_no)?),
+            "vn" => normals.push(parse_vec3(&args, line_no)?),
+            "vt" => {
+                let u = parse_f32(&args, 0, line_no)?;
+                // v is optional and defaults to 0
+                let v = if args.len() > 1 {
+                    parse_f32(&args, 1, line_no)?
+                } else {
+                    0.0
+                };
+                uvs.push((u, v));
+            }
+            "usemtl" => {
+                let name = Some(args.join(" "));
+                current_group = match groups.iter().position(|(n, _)| *n == name) {
+                    Some(index) => index,
+                    None => {
+                        groups.push((name, MeshBuilder::default()));
+                        groups.len() - 1
+                    }
+                };
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(ImportError::parse(
+                        line_no,
+                        "face has fewer than 3 vertices",
+                    ));
+                }
+
+                let mut corners = Vec::with_capacity(args.len());
+                for arg in &args {
+                    let mut parts = arg.split('/');
+                    let v = resolve_index(parts.next(), positions.len(), line_no)?;
+                    let vt = match parts.next() {
+                        Some("") | None => None,
+                        idx => Some(resolve_index(idx, uvs.len(), line_no)?),
+                    };
+                    let vn = match parts.next() {
+                        Some("") | None => None,
+                        idx => Some(resolve_index(idx, normals.len(), line_no)?),
+                    };
+                    corners.push((v, vt, vn));
+                }
+
+                let builder = &mut groups[current_group].1;
+                let indices: Vec<usize> = corners
+                    .iter()
+                    .map(|corner| builder.vertex(*corner, &positions, &uvs, &normals))
+                    .collect();
+
+                // Triangulate polygons as a fan
+                for k in 1..indices.len() - 1 {
+                    builder
+                        .data
+                        .indices
+                        .push([indices[0], indices[k], indices[k + 1]]);
+                }
+            }
+            // Objects, groups, smoothing groups, lines etc. don't affect the result
+            _ => {}
+        }
+    }
+
+    let meshes = groups
+        .into_iter()
+        .filter(|(_, builder)| !builder.data.indices.is_empty())
+        .map(|(name, builder)| {
+            let material = name
+                .and_then(|n| materials.get(&n).cloned())
+                .unwrap_or_else(|| default_material.clone());
+            TriangleMesh::new(builder.build(), material)
+        })
+        .collect();
+
+    Ok(meshes)
+}
+
+struct MtlEntry {
+    kd: Vec3,
+    ks: Vec3,
+    ke: Vec3,
+    tf: Option<Vec3>,
+    ns: f32,
+    ni: Option<f32>,
+    d: f32,
+    illum: u32,
+    pr: Option<f32>,
+    pm: Option<f32>,
+    ps: f32,
+    pc: f32,
+    pcr: Option<f32>,
+}
+
+impl Default for MtlEntry {
+    fn default() -> MtlEntry {
+        MtlEntry {
+            kd: Vec3::new_uniform(0.8),
+            ks: Vec3::new_zeroes(),
+            ke: Vec3::new_zeroes(),
+            tf: None,
+            ns: 0.0,
+            ni: None,
+            d: 1.0,
+            illum: 2,
+            pr: None,
+            pm: None,
+            ps: 0.0,
+            pc: 0.0,
+            pcr: None,
+        }
+    }
+}
+
+impl MtlEntry {
+    // Maps onto a principled material, or an emissive one if it glows. The PBR
+    // extension's values are used when given, otherwise a Phong material whose
+    // specular colour outweighs its diffuse one is treated as a metal
+    fn to_material(&self) -> Material {
+        let max = |v: &Vec3| v.x.max(v.y).max(v.z);
+
+        if max(&self.ke) > 0.0 {
+            return Material::new_emissive(self.ke);
+        }
+
+        let glass = self.d < 1.0 || matches!(self.illum, 4 | 6 | 7 | 9);
+        let phong_metal = max(&self.ks) > 0.0 && (self.illum == 3 || max(&self.ks) > max(&self.kd));
+        let (base_color, metallic) = match self.pm {
+            Some(pm) => (self.kd, pm),
+            None if phong_metal && !glass => (self.ks, 1.0),
+            None => (self.kd, 0.0),
+        };
+        // The Phong exponent [0, 1000] as a microfacet alpha, then as a perceptual
+        // roughness
+        let roughness = self
+            .pr
+            .unwrap_or_else(|| (2.0 / (self.ns.max(0.0) + 2.0)).sqrt().sqrt());
+
+        let mut principled = Principled {
+            metallic,
+            roughness,
+            sheen: self.ps,
+            clearcoat: self.pc,
+            // Ni defaults to 1 in MTL which would make glass invisible
+            ior: self.ni.unwrap_or(1.5),
+            transmission: if glass { 1.0 } else { 0.0 },
+            ..Principled::new(base_color.into())
+        };
+        if let Some(pcr) = self.pcr {
+            principled.clearcoat_roughness = pcr;
+        }
+        if glass {
+            // Tinted by the transmission filter rather than the diffuse colour
+            principled.base_color = self.tf.unwrap_or(Vec3::new_uniform(1.0)).into();
+        }
+        Material::new_principled(principled)
+    }
+}
+
+// Collects the vertices of one mesh, de-duplicating OBJ's separate position/uv/normal indices
+#[derive(Default)]
+struct MeshBuilder {
+    data: MeshData,
+    uvs: Vec<(f32, f32)>,
+    normals: Vec<Vec3>,
+    vertex_map: HashMap<(usize, Option<usize>, Option<usize>), usize>,
+    missing_uvs: bool,
+    missing_normals: bool,
+}
+
+impl MeshBuilder {
+    fn vertex(
+        &mut self,
+        corner: (usize, Option<usize>, Option<usize>),
+        positions: &[Vec3],
+        uvs: &[(f32, f32)],
+        normals: &[Vec3],
+    ) -> usize {
+        if let Some(&index) = self.vertex_map.get(&corner) {
+            return index;
+        }
+
+        let (v, vt, vn) = corner;
+        let index = self.data.positions.len();
+        self.data.positions.push(positions[v]);
+        match vt {
+            Some(vt) => self.uvs.push(uvs[vt]),
+            None => {
+                self.missing_uvs = true;
+                self.uvs.push((0.0, 0.0));
+            }
+        }
+        match vn {
+            Some(vn) => self.normals.push(normals[vn]),
+            None => {
+                self.missing_normals = true;
+                self.normals.push(Vec3::new_zeroes());
+            }
+        }
+        self.vertex_map.insert(corner, index);
+
+        index
+    }
+
+    // Attributes are only kept if every vertex has them
+    fn build(self) -> MeshData {
+        let mut data = self.data;
+        if !self.missing_uvs {
+            data.uvs = Some(self.uvs);
+        }
+        if !self.missing_normals {
+            data.normals = Some(self.normals);
+        }
+
+        data
+    }
+}
+
+// OBJ indices are 1-based, negative indices are relative to the end of the list so far
+fn resolve_index(token: Option<&str>, len: usize, line: usize) -> Result<usize, ImportError> {
+    let token = token.ok_or_else(|| ImportError::parse(line, "missing index"))?;
+    let index: i64 = token
+        .parse()
+        .map_err(|_| ImportError::parse(line, format!("invalid index '{}'", token)))?;
+
+    let resolved = if index > 0 {
+        index - 1
+    } else {
+        len as i64 + index
+    };
+
+    if index == 0 || resolved < 0 || resolved >= len as i64 {
+        return Err(ImportError::parse(
+            line,
+            format!("index {} out of range", index),
+        ));
+    }
+
+    Ok(resolved as usize)
+}
+
+fn parse_f32(args: &[&str], i: usize, line: usize) -> Result<f32, ImportError> {
+    let token = args
+        .get(i)
+        .ok_or_else(|| ImportError::parse(line, "missing value"))?;
+    token
+        .parse()
+        .map_err(|_| ImportError::parse(line, format!("invalid number '{}'", token)))
+}
+
+fn parse_vec3(args: &[&str], line: usize) -> Result<Vec3, ImportError> {
+    Ok(Vec3::new(
+        parse_f32(args, 0, line)?,
+        parse_f32(args, 1, line)?,
+        parse_f32(args, 2, line)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Hittable;
+    use crate::math::Ray;
+
+    const QUAD: &str = "
+# A unit quad split across two materials
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+usemtl red
+f 1/1/1 2/2/1 3/3/1
+usemtl missing
+f -4/-4/-1 -2/-2/-1 -1/-1/-1
+";
+
+    #[test]
+    fn parse() {
+        let mut materials = HashMap::new();
+        materials.insert(
+            "red".to_string(),
+            Material::new_lambertian(Vec3::new(1.0, 0.0, 0.0)),
+        );
+        let default = Material::new_lambertian(Vec3::new_uniform(0.5));
+
+        let meshes = parse_obj(QUAD, &materials, default).unwrap();
+        assert_eq!(meshes.len(), 2);
+        assert_eq!(meshes[0].face_count(), 1);
+        assert_eq!(meshes[1].face_count(), 1);
+
+        let ray = Ray {
+            origin: Vec3::new(0.75, 0.25, 1.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        let hit = meshes[0].intersects_ray(&ray, (0.0, 100.0)).unwrap();
+        assert_eq!(hit.t, 1.0);
+        assert!(meshes[1].intersects_ray(&ray, (0.0, 100.0)).is_none());
+    }
+
+    #[test]
+    fn triangulates_polygons() {
+        let source = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv -1 1 0\nf 1 2 3 4 5\n";
+        let meshes = parse_obj(source, &HashMap::new(), Material::new_dielectric(1.5)).unwrap();
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(meshes[0].face_count(), 3);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let mat = Material::new_dielectric(1.5);
+        assert!(parse_obj("v 0 0\n", &HashMap::new(), mat.clone()).is_err());
+        assert!(parse_obj("v 0 0 0\nf 1 2 3\n", &HashMap::new(), mat.clone()).is_err());
+        assert!(parse_obj("v 0 0 0\nf 1 1\n", &HashMap::new(), mat).is_err());
+    }
+
+    #[test]
+    fn mtl_mapping() {
+        let source = "
+newmtl diffuse
+Kd 0.5 0.2 0.2
+Ks 0.1 0.1 0.1
+
+newmtl metal
+Kd 0.1 0.1 0.1
+Ks 0.9 0.8 0.7
+Ns 1000
+
+newmtl glass
+Ni 1.45
+d 0.1
+
+newmtl light
+Ke 4 4 4
+
+newmtl pbr
+Kd 0.5 0.5 0.5
+Pr 0.2
+Pm 0.5
+Pc 1
+Pcr 0.05
+";
+        let materials = parse_mtl(source).unwrap();
+        assert_eq!(materials.len(), 5);
+        let principled = |name: &str| match &materials[name] {
+            Material::Principled(p) => p.clone(),
+            _ => panic!("{} isn't principled", name),
+        };
+        let diffuse = principled("diffuse");
+        assert!(diffuse.metallic == 0.0 && diffuse.transmission == 0.0);
+        let metal = principled("metal");
+        assert!(metal.metallic == 1.0 && metal.roughness < 0.3);
+        let glass = principled("glass");
+        assert!(glass.transmission == 1.0 && glass.ior == 1.45);
+        let pbr = principled("pbr");
+        assert!(pbr.metallic == 0.5 && pbr.roughness == 0.2);
+        assert!(pbr.clearcoat == 1.0 && pbr.clearcoat_roughness == 0.05);
+        assert!(matches!(materials["light"], Material::Emissive(_)));
+    }
+}
@@ -0,0 +1,448 @@
+use std::fs;
+use std::path::Path;
+
+use crate::geometry::{MeshData, TriangleMesh};
+use crate::import::ImportError;
+use crate::material::Material;
+use crate::math::Vec3;
+
+// Loads an ASCII or binary little-endian PLY file as a single mesh.
+// Vertex normals, colours and texture coordinates are used when present
+pub fn load_ply(path: impl AsRef<Path>, material: Material) -> Result<TriangleMesh, ImportError> {
+    let bytes = fs::read(path)?;
+    Ok(TriangleMesh::new(parse_ply(&bytes)?, material))
+}
+
+pub fn parse_ply(bytes: &[u8]) -> Result<MeshData, ImportError> {
+    let (header, body) = split_header(bytes)?;
+    let (format, elements) = parse_header(header)?;
+
+    let mut reader = match format {
+        Format::Ascii => {
+            let body = std::str::from_utf8(body)
+                .map_err(|_| ImportError::parse(0, "ascii body is not valid utf-8"))?;
+            Reader::Ascii(body.split_whitespace())
+        }
+        Format::BinaryLittleEndian => Reader::Binary(body),
+    };
+
+    let mut data = MeshData::default();
+    let mut normals = Vec::new();
+    let mut colors = Vec::new();
+    let mut uvs = Vec::new();
+
+    for element in &elements {
+        match element.name.as_str() {
+            "vertex" => {
+                let find = |names: &[&str]| {
+                    element
+                        .properties
+                        .iter()
+                        .position(|p| names.contains(&p.name.as_str()))
+                };
+                let position = [find(&["x"]), find(&["y"]), find(&["z"])];
+                let normal = [find(&["nx"]), find(&["ny"]), find(&["nz"])];
+                let color = [
+                    find(&["red", "r"]),
+                    find(&["green", "g"]),
+                    find(&["blue", "b"]),
+                ];
+                let uv = [
+                    find(&["u", "s", "texture_u", "texture_s"]),
+                    find(&["v", "t", "texture_v", "texture_t"]),
+                ];
+                let has = |indices: &[Option<usize>]| indices.iter().all(Option::is_some);
+                if !has(&position) {
+                    return Err(ImportError::parse(0, "vertex element is missing x, y or z"));
+                }
+
+                for _ in 0..element.count {
+                    let mut values = Vec::with_capacity(element.properties.len());
+                    for property in &element.properties {
+                        match property.kind {
+                            PropertyKind::Scalar(ty) => values.push(reader.read(ty)?),
+                            // Lists on vertices aren't meaningful to us, skip them
+                            PropertyKind::List(..) => {
+                                reader.skip(property)?;
+                                values.push(0.0);
+                            }
+                        }
+                    }
+
+                    let get = |i: Option<usize>| values[i.unwrap()] as f32;
+                    data.positions.push(Vec3::new(
+                        get(position[0]),
+                        get(position[1]),
+                        get(position[2]),
+                    ));
+                    if has(&normal) {
+                        normals.push(Vec3::new(get(normal[0]), get(normal[1]), get(normal[2])));
+                    }
+                    if has(&color) {
+                        // Integer colours are in [0, 255], floats are already normalised
+                        let scale = match element.properties[color[0].unwrap()].kind {
+                            PropertyKind::Scalar(ScalarType::F32 | ScalarType::F64) => 1.0,
+                            _ => 1.0 / 255.0,
+                        };
+                        colors.push(scale * Vec3::new(get(color[0]), get(color[1]), get(color[2])));
+                    }
+                    if has(&uv) {
+                        uvs.push((get(uv[0]), get(uv[1])));
+                    }
+                }
+            }
+            "face" => {
+                let indices = element
+                    .properties
+                    .iter()
+                    .position(|p| p.name == "vertex_indices" || p.name == "vertex_index");
+                for _ in 0..element.count {
+                    for (i, property) in element.properties.iter().enumerate() {
+                        match property.kind {
+                            PropertyKind::List(count_ty, item_ty) if Some(i) == indices => {
+                                // Not preallocated, a corrupt count would ask for gigabytes
+                                let count = reader.read_index(count_ty)?;
+                                let mut face = Vec::new();
+                                for _ in 0..count {
+                                    face.push(reader.read_index(item_ty)?);
+                                }
+
+                                // Triangulate polygons as a fan
+                                for k in 1..face.len().saturating_sub(1) {
+                                    data.indices.push([face[0], face[k], face[k + 1]]);
+                                }
+                            }
+                            _ => reader.skip(property)?,
+                        }
+                    }
+                }
+            }
+            // Skip over any other elements (edges, materials etc.)
+            _ => {
+                for _ in 0..element.count {
+                    for property in &element.properties {
+                        reader.skip(property)?;
+                    }
+                }
+            }
+        }
+    }
+
+    let vertex_count = data.positions.len();
+    if data.indices.iter().flatten().any(|&i| i >= vertex_count) {
+        return Err(ImportError::parse(0, "face index out of range"));
+    }
+    if !normals.is_empty() {
+        data.normals = Some(normals);
+    }
+    if !colors.is_empty() {
+        data.colors = Some(colors);
+    }
+    if !uvs.is_empty() {
+        data.uvs = Some(uvs);
+    }
+
+    Ok(data)
+}
+
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+}
+
+#[derive(Copy, Clone)]
+enum ScalarType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl ScalarType {
+    fn from_name(name: &str) -> Option<ScalarType> {
+        match name {
+            "char" | "int8" => Some(ScalarType::I8),
+            "uchar" | "uint8" => Some(ScalarType::U8),
+            "short" | "int16" => Some(ScalarType::I16),
+            "ushort" | "uint16" => Some(ScalarType::U16),
+            "int" | "int32" => Some(ScalarType::I32),
+            "uint" | "uint32" => Some(ScalarType::U32),
+            "float" | "float32" => Some(ScalarType::F32),
+            "double" | "float64" => Some(ScalarType::F64),
+            _ => None,
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            ScalarType::I8 | ScalarType::U8 => 1,
+            ScalarType::I16 | ScalarType::U16 => 2,
+            ScalarType::I32 | ScalarType::U32 | ScalarType::F32 => 4,
+            ScalarType::F64 => 8,
+        }
+    }
+}
+
+enum PropertyKind {
+    Scalar(ScalarType),
+    // Count type, item type
+    List(ScalarType, ScalarType),
+}
+
+struct Property {
+    name: String,
+    kind: PropertyKind,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+// Reads values from the body of the file. Everything is widened to f64
+enum Reader<'a> {
+    Ascii(std::str::SplitWhitespace<'a>),
+    Binary(&'a [u8]),
+}
+
+impl Reader<'_> {
+    fn read(&mut self, ty: ScalarType) -> Result<f64, ImportError> {
+        match self {
+            Reader::Ascii(tokens) => {
+                let token = tokens
+                    .next()
+                    .ok_or_else(|| ImportError::parse(0, "unexpected end of file"))?;
+                token
+                    .parse()
+                    .map_err(|_| ImportError::parse(0, format!("invalid number '{}'", token)))
+            }
+            Reader::Binary(bytes) => {
+                let size = ty.size();
+                if bytes.len() < size {
+                    return Err(ImportError::parse(0, "unexpected end of file"));
+                }
+                let (value, rest) = bytes.split_at(size);
+                *bytes = rest;
+
+                let value = match ty {
+                    ScalarType::I8 => value[0] as i8 as f64,
+                    ScalarType::U8 => value[0] as f64,
+                    ScalarType::I16 => i16::from_le_bytes([value[0], value[1]]) as f64,
+                    ScalarType::U16 => u16::from_le_bytes([value[0], value[1]]) as f64,
+                    ScalarType::I32 => i32::from_le_bytes(value.try_into().unwrap()) as f64,
+                    ScalarType::U32 => u32::from_le_bytes(value.try_into().unwrap()) as f64,
+                    ScalarType::F32 => f32::from_le_bytes(value.try_into().unwrap()) as f64,
+                    ScalarType::F64 => f64::from_le_bytes(value.try_into().unwrap()),
+                };
+
+                Ok(value)
+            }
+        }
+    }
+
+    // A list length or vertex index, which has to be a whole number that isn't negative
+    fn read_index(&mut self, ty: ScalarType) -> Result<usize, ImportError> {
+        let value = self.read(ty)?;
+        if value < 0.0 || value.fract() != 0.0 {
+            return Err(ImportError::parse(0, format!("invalid index {}", value)));
+        }
+
+        Ok(value as usize)
+    }
+
+    fn skip(&mut self, property: &Property) -> Result<(), ImportError> {
+        match property.kind {
+            PropertyKind::Scalar(ty) => {
+                self.read(ty)?;
+            }
+            PropertyKind::List(count_ty, item_ty) => {
+                let count = self.read_index(count_ty)?;
+                for _ in 0..count {
+                    self.read(item_ty)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Splits the file after the end_header line
+fn split_header(bytes: &[u8]) -> Result<(&str, &[u8]), ImportError> {
+    const END: &[u8] = b"end_header";
+    let end = bytes
+        .windows(END.len())
+        .position(|w| w == END)
+        .ok_or_else(|| ImportError::parse(0, "missing end_header"))?;
+
+    // The body starts after the end of the end_header line
+    let body_start = bytes[end..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map(|i| end + i + 1)
+        .unwrap_or(bytes.len());
+
+    let header = std::str::from_utf8(&bytes[..end])
+        .map_err(|_| ImportError::parse(0, "header is not valid utf-8"))?;
+
+    Ok((header, &bytes[body_start..]))
+}
+
+fn parse_header(header: &str) -> Result<(Format, Vec<Element>), ImportError> {
+    let mut lines = header.lines().enumerate();
+    match lines.next() {
+        Some((_, line)) if line.trim() == "ply" => {}
+        _ => return Err(ImportError::parse(1, "not a ply file")),
+    }
+
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for (i, line) in lines {
+        let line_no = i + 1;
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let type_of = |name: &str| {
+            ScalarType::from_name(name)
+                .ok_or_else(|| ImportError::parse(line_no, format!("unknown type '{}'", name)))
+        };
+
+        match tokens.as_slice() {
+            ["format", "ascii", _] => format = Some(Format::Ascii),
+            ["format", "binary_little_endian", _] => format = Some(Format::BinaryLittleEndian),
+            ["format", other, ..] => {
+                return Err(ImportError::parse(
+                    line_no,
+                    format!("unsupported format '{}'", other),
+                ))
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| ImportError::parse(line_no, "invalid element count"))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count_ty, item_ty, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| ImportError::parse(line_no, "property before element"))?;
+                element.properties.push(Property {
+                    name: name.to_string(),
+                    kind: PropertyKind::List(type_of(count_ty)?, type_of(item_ty)?),
+                });
+            }
+            ["property", ty, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| ImportError::parse(line_no, "property before element"))?;
+                element.properties.push(Property {
+                    name: name.to_string(),
+                    kind: PropertyKind::Scalar(type_of(ty)?),
+                });
+            }
+            // Comments, obj_info and blank lines
+            _ => {}
+        }
+    }
+
+    let format = format.ok_or_else(|| ImportError::parse(0, "missing format"))?;
+
+    Ok((format, elements))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ASCII: &str = "ply
+format ascii 1.0
+comment a unit quad
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0 255 0 0
+1 0 0 255 0 0
+1 1 0 0 255 0
+0 1 0 0 255 0
+4 0 1 2 3
+";
+
+    #[test]
+    fn parse_ascii() {
+        let data = parse_ply(ASCII.as_bytes()).unwrap();
+        assert_eq!(data.positions.len(), 4);
+        assert_eq!(data.positions[2], Vec3::new(1.0, 1.0, 0.0));
+        assert_eq!(data.indices, vec![[0, 1, 2], [0, 2, 3]]);
+        assert!(data.normals.is_none());
+
+        let colors = data.colors.unwrap();
+        assert_eq!(colors[0], Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(colors[3], Vec3::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn parse_binary() {
+        let mut bytes = b"ply
+format binary_little_endian 1.0
+element vertex 3
+property float x
+property float y
+property float z
+property float nx
+property float ny
+property float nz
+element face 1
+property list uchar uint vertex_indices
+end_header
+"
+        .to_vec();
+        for p in [[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]] {
+            for v in p.iter().chain([0.0f32, 0.0, 1.0].iter()) {
+                bytes.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        bytes.push(3);
+        for i in [0u32, 1, 2] {
+            bytes.extend_from_slice(&i.to_le_bytes());
+        }
+
+        let data = parse_ply(&bytes).unwrap();
+        assert_eq!(data.positions[1], Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(data.normals.unwrap()[0], Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(data.indices, vec![[0, 1, 2]]);
+    }
+
+    #[test]
+    fn parse_errors() {
+        // Truncated body
+        let truncated = ASCII.replace("4 0 1 2 3\n", "");
+        assert!(parse_ply(truncated.as_bytes()).is_err());
+
+        // Unsupported format
+        let big_endian = ASCII.replace("ascii", "binary_big_endian");
+        assert!(parse_ply(big_endian.as_bytes()).is_err());
+
+        // Index out of range
+        let bad_index = ASCII.replace("4 0 1 2 3", "3 0 1 4");
+        assert!(parse_ply(bad_index.as_bytes()).is_err());
+
+        // Negative indices aren't clamped to the first vertex
+        let negative = ASCII.replace("4 0 1 2 3", "3 0 1 -1");
+        assert!(parse_ply(negative.as_bytes()).is_err());
+
+        // A corrupt list count runs out of file instead of memory
+        let huge = ASCII.replace("4 0 1 2 3", "4000000000 0 1 2 3");
+        assert!(parse_ply(huge.as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,196 @@
+use std::fs;
+use std::path::Path;
+
+use crate::geometry::{MeshData, TriangleMesh};
+use crate::import::ImportError;
+use crate::material::Material;
+use crate::math::Vec3;
+
+const BINARY_HEADER_SIZE: usize = 84;
+const BINARY_FACET_SIZE: usize = 50;
+
+// Loads an ASCII or binary STL file as a single mesh.
+// Facet normals are used as vertex normals and binary facet colours
+// (SolidView style, bit 15 set) become vertex colours
+pub fn load_stl(path: impl AsRef<Path>, material: Material) -> Result<TriangleMesh, ImportError> {
+    let bytes = fs::read(path)?;
+    Ok(TriangleMesh::new(parse_stl(&bytes)?, material))
+}
+
+pub fn parse_stl(bytes: &[u8]) -> Result<MeshData, ImportError> {
+    // Binary files can also start with "solid" so check the size matches first
+    let is_binary = bytes.len() >= BINARY_HEADER_SIZE && {
+        let count = u32::from_le_bytes(bytes[80..84].try_into().unwrap()) as usize;
+        bytes.len() == BINARY_HEADER_SIZE + count * BINARY_FACET_SIZE
+    };
+
+    if is_binary {
+        Ok(parse_binary(bytes))
+    } else if bytes.starts_with(b"solid") {
+        let source = std::str::from_utf8(bytes)
+            .map_err(|_| ImportError::parse(0, "ascii stl is not valid utf-8"))?;
+        parse_ascii(source)
+    } else {
+        Err(ImportError::parse(0, "not an stl file"))
+    }
+}
+
+// Facets don't share vertices so every facet adds 3 new ones
+struct FacetBuilder {
+    data: MeshData,
+    normals: Vec<Vec3>,
+    colors: Vec<Vec3>,
+}
+
+impl FacetBuilder {
+    fn new() -> FacetBuilder {
+        FacetBuilder {
+            data: MeshData::default(),
+            normals: Vec::new(),
+            colors: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, normal: Vec3, vertices: [Vec3; 3], color: Option<Vec3>) {
+        let first = self.data.positions.len();
+        self.data.positions.extend_from_slice(&vertices);
+        self.data.indices.push([first, first + 1, first + 2]);
+        self.normals.extend_from_slice(&[normal; 3]);
+        if let Some(color) = color {
+            self.colors.extend_from_slice(&[color; 3]);
+        }
+    }
+
+    fn build(self) -> MeshData {
+        let mut data = self.data;
+        // Some exporters write zero normals, fall back to the geometric normal then
+        if self.normals.iter().all(|n| n.length_sq() > 0.0) {
+            data.normals = Some(
+                self.normals
+                    .iter()
+                    .map(|n| n.make_unit())
+                    .collect::<Vec<Vec3>>(),
+            );
+        }
+        // Colours are only meaningful if every facet has one
+        if !self.colors.is_empty() && self.colors.len() == data.positions.len() {
+            data.colors = Some(self.colors);
+        }
+
+        data
+    }
+}
+
+fn parse_binary(bytes: &[u8]) -> MeshData {
+    let read_vec3 = |b: &[u8]| {
+        let f = |i: usize| f32::from_le_bytes(b[i * 4..i * 4 + 4].try_into().unwrap());
+        Vec3::new(f(0), f(1), f(2))
+    };
+
+    let mut builder = FacetBuilder::new();
+    for facet in bytes[BINARY_HEADER_SIZE..].chunks_exact(BINARY_FACET_SIZE) {
+        let normal = read_vec3(&facet[0..12]);
+        let vertices = [
+            read_vec3(&facet[12..24]),
+            read_vec3(&facet[24..36]),
+            read_vec3(&facet[36..48]),
+        ];
+
+        let attribute = u16::from_le_bytes([facet[48], facet[49]]);
+        // 5 bits per channel, blue in the lowest bits
+        let color = if attribute & 0x8000 != 0 {
+            let channel = |shift: u16| ((attribute >> shift) & 0x1f) as f32 / 31.0;
+            Some(Vec3::new(channel(10), channel(5), channel(0)))
+        } else {
+            None
+        };
+
+        builder.push(normal, vertices, color);
+    }
+
+    builder.build()
+}
+
+fn parse_ascii(source: &str) -> Result<MeshData, ImportError> {
+    let mut builder = FacetBuilder::new();
+    let mut normal = Vec3::new_zeroes();
+    let mut vertices: Vec<Vec3> = Vec::with_capacity(3);
+
+    for (i, line) in source.lines().enumerate() {
+        let line_no = i + 1;
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let parse_vec3 = |values: &[&str]| -> Result<Vec3, ImportError> {
+            let parsed: Result<Vec<f32>, _> = values.iter().map(|v| v.parse::<f32>()).collect();
+            match parsed {
+                Ok(v) if v.len() == 3 => Ok(Vec3::new(v[0], v[1], v[2])),
+                _ => Err(ImportError::parse(line_no, "expected 3 numbers")),
+            }
+        };
+
+        match tokens.as_slice() {
+            ["facet", "normal", values @ ..] => {
+                normal = parse_vec3(values)?;
+                vertices.clear();
+            }
+            ["vertex", values @ ..] => vertices.push(parse_vec3(values)?),
+            ["endfacet"] => {
+                if vertices.len() != 3 {
+                    return Err(ImportError::parse(line_no, "facet must have 3 vertices"));
+                }
+                builder.push(normal, [vertices[0], vertices[1], vertices[2]], None);
+            }
+            // solid, outer loop, endloop, endsolid
+            _ => {}
+        }
+    }
+
+    Ok(builder.build())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_ascii() {
+        let source = "solid test
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 0 1 0
+    endloop
+  endfacet
+endsolid test
+";
+        let data = parse_stl(source.as_bytes()).unwrap();
+        assert_eq!(data.positions.len(), 3);
+        assert_eq!(data.indices, vec![[0, 1, 2]]);
+        assert_eq!(data.normals.unwrap()[0], Vec3::new(0.0, 0.0, 1.0));
+        assert!(data.colors.is_none());
+
+        let missing_vertex = source.replace("      vertex 0 1 0\n", "");
+        assert!(parse_stl(missing_vertex.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn parse_binary() {
+        // Header deliberately starts with "solid" to make sure it isn't read as ascii
+        let mut bytes = b"solid".to_vec();
+        bytes.resize(80, 0);
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        for v in [
+            0.0f32, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0,
+        ] {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        // Pure red
+        bytes.extend_from_slice(&(0x8000u16 | 0x1f << 10).to_le_bytes());
+
+        let data = parse_stl(&bytes).unwrap();
+        assert_eq!(data.positions[1], Vec3::new(1.0, 0.0, 0.0));
+        // Zero normal means no normals
+        assert!(data.normals.is_none());
+        assert_eq!(data.colors.unwrap()[0], Vec3::new(1.0, 0.0, 0.0));
+    }
+}
@@ -0,0 +1,119 @@
+use std::fs;
+use std::path::Path;
+
+use crate::geometry::VoxelGrid;
+use crate::import::ImportError;
+
+// Loads headerless little endian f32 values, x varying fastest then y then z
+pub fn load_raw_voxels(
+    path: impl AsRef<Path>,
+    dims: (usize, usize, usize),
+) -> Result<VoxelGrid, ImportError> {
+    parse_raw_voxels(&fs::read(path)?, dims)
+}
+
+// Loads a dense grid dump, see parse_dense_voxels
+pub fn load_dense_voxels(path: impl AsRef<Path>) -> Result<VoxelGrid, ImportError> {
+    parse_dense_voxels(&fs::read(path)?)
+}
+
+pub fn parse_raw_voxels(
+    bytes: &[u8],
+    dims: (usize, usize, usize),
+) -> Result<VoxelGrid, ImportError> {
+    let size = dims
+        .0
+        .checked_mul(dims.1)
+        .and_then(|n| n.checked_mul(dims.2))
+        .and_then(|n| n.checked_mul(4))
+        .ok_or_else(|| ImportError::parse(1, "grid too large"))?;
+    if size == 0 || bytes.len() != size {
+        return Err(ImportError::parse(
+            0,
+            format!(
+                "expected {} bytes for a {}x{}x{} grid but found {}",
+                size,
+                dims.0,
+                dims.1,
+                dims.2,
+                bytes.len()
+            ),
+        ));
+    }
+
+    let values = bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+        .collect();
+
+    Ok(VoxelGrid::new(values, dims))
+}
+
+// A dense grid as exported from a volume tool: a text line "dense <nx> <ny> <nz>"
+// followed by the raw values as in parse_raw_voxels
+pub fn parse_dense_voxels(bytes: &[u8]) -> Result<VoxelGrid, ImportError> {
+    let header_end = bytes
+        .iter()
+        .position(|&b| b == b'\n')
+        .ok_or_else(|| ImportError::parse(1, "missing header"))?;
+    let header = std::str::from_utf8(&bytes[..header_end])
+        .map_err(|_| ImportError::parse(1, "header is not valid utf-8"))?;
+
+    let mut tokens = header.split_whitespace();
+    if tokens.next() != Some("dense") {
+        return Err(ImportError::parse(1, "not a dense grid"));
+    }
+    let dims: Vec<usize> = tokens
+        .map(|t| t.parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| ImportError::parse(1, "invalid grid size"))?;
+    if dims.len() != 3 {
+        return Err(ImportError::parse(1, "expected 3 grid dimensions"));
+    }
+
+    parse_raw_voxels(&bytes[header_end + 1..], (dims[0], dims[1], dims[2]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Vec3;
+
+    fn to_bytes(values: &[f32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn parse_raw() {
+        let bytes = to_bytes(&[0.0, 1.0, 2.0, 3.0]);
+        let grid = parse_raw_voxels(&bytes, (2, 1, 2)).unwrap();
+        assert_eq!(grid.dims(), (2, 1, 2));
+        assert_eq!(grid.max_value(), 3.0);
+        // x = 1, z = 0
+        assert_eq!(grid.sample(Vec3::new(0.75, 0.5, 0.25)), 1.0);
+
+        assert!(parse_raw_voxels(&bytes, (2, 2, 2)).is_err());
+        assert!(parse_raw_voxels(&bytes[..12], (3, 1, 1)).is_ok());
+        assert!(parse_raw_voxels(&[], (0, 1, 1)).is_err());
+    }
+
+    #[test]
+    fn parse_dense() {
+        let mut bytes = b"dense 1 2 1\n".to_vec();
+        bytes.extend(to_bytes(&[0.5, 0.25]));
+        let grid = parse_dense_voxels(&bytes).unwrap();
+        assert_eq!(grid.dims(), (1, 2, 1));
+        assert_eq!(grid.sample(Vec3::new(0.5, 0.25, 0.5)), 0.5);
+
+        assert!(parse_dense_voxels(b"dense 1 2\n").is_err());
+        assert!(parse_dense_voxels(b"sparse 1 1 1\n\0\0\0\0").is_err());
+        assert!(parse_dense_voxels(&bytes[..bytes.len() - 1]).is_err());
+
+        let overflow = parse_dense_voxels(b"dense 4294967296 4294967296 2\n\0\0\0\0");
+        assert!(matches!(
+            overflow,
+            Err(ImportError::Parse { ref message, .. }) if message == "grid too large"
+        ));
+        assert!(parse_raw_voxels(&[], (usize::MAX, 2, 1)).is_err());
+    }
+}
@@ -1,18 +1,45 @@
 pub mod camera;
 pub mod geometry;
+pub mod import;
 pub mod material;
 pub mod math;
 
-use camera::Camera;
-use geometry::{Hittable, Sphere};
-use material::Material;
-use math::{Ray, Vec3};
+use camera::{Aperture, Camera, StereoLayout};
+use geometry::{
+    Capsule, Cone, ConstantMedium, Csg, CsgOperation, Cuboid, Cylinder, Disk, Heightfield, Hit,
+    Hittable, LightList, MovingSphere, Object, Plane, Quad, Sdf, SdfObject, Sphere, Torus,
+    Transformed, Triangle, VoxelGrid, VoxelVolume,
+};
+use material::{
+    ComplexIor, Material, NoisePattern, NormalMap, Principled, Texture, TextureMapping, WrapMode,
+};
+use math::{Ray, Transform, Vec3};
 use rand::Rng;
+use std::sync::Arc;
 
 const MAX_DEPTH: u32 = 16;
 
 // Take ownership of the ray so it can be dropped sooner
-pub fn cast_ray(ray: Ray, world: &Box<dyn Hittable>, skybox_scale: f32, depth: u32) -> Vec3 {
+pub fn cast_ray(
+    ray: Ray,
+    world: &Box<dyn Hittable>,
+    lights: &LightList,
+    skybox_scale: f32,
+    depth: u32,
+) -> Vec3 {
+    trace(ray, world.as_ref(), lights, skybox_scale, depth, None)
+}
+
+// bsdf_pdf is the density the last bounce picked the ray with, if the lights could have
+// been sampled there instead. Light the ray finds is then weighted against that
+fn trace(
+    ray: Ray,
+    world: &dyn Hittable,
+    lights: &LightList,
+    skybox_scale: f32,
+    depth: u32,
+    bsdf_pdf: Option<f32>,
+) -> Vec3 {
     // 0.0001 is to  avoid reintersecting the same object on bounces
     let hit = world.intersects_ray(&ray, (0.001, f32::MAX));
 
@@ -27,15 +54,29 @@ pub fn cast_ray(ray: Ray, world: &Box<dyn Hittable>, skybox_scale: f32, depth: u
         }
         Some(hit) => {
             if depth < MAX_DEPTH {
-                match hit.material.scatter(&ray, &hit) {
-                    Some(scatter) => {
-                        scatter.attenuation
-                            * match scatter.ray {
-                                Some(ray) => cast_ray(ray, world, skybox_scale, depth + 1),
-                                None => Vec3::new_uniform(1.0),
-                            }
+                let mut emitted = hit.material.emitted(&hit);
+                if let (Some(bsdf_pdf), true) = (bsdf_pdf, hit.material.is_emissive()) {
+                    let light_pdf = lights.pdf(&ray.origin, &hit);
+                    emitted = power_heuristic(bsdf_pdf, light_pdf) * emitted;
+                }
+                let direct = sample_lights(&ray, &hit, world, lights);
+
+                match hit.material.sample(&ray, &hit) {
+                    Some(sample) => {
+                        let scattered = Ray {
+                            origin: hit.point,
+                            direction: sample.direction,
+                            time: ray.time,
+                        };
+                        // Lights found off mirrors and clear glass keep their full weight, nothing
+                        // else could have sampled them
+                        let bsdf_pdf = (!sample.delta && !lights.is_empty()).then_some(sample.pdf);
+                        emitted
+                            + direct
+                            + sample.weight
+                                * trace(scattered, world, lights, skybox_scale, depth + 1, bsdf_pdf)
                     }
-                    None => Vec3::new(0.0, 0.0, 0.0),
+                    None => emitted + direct,
                 }
             } else {
                 Vec3::new(0.0, 0.0, 0.0)
@@ -46,6 +87,57 @@ pub fn cast_ray(ray: Ray, world: &Box<dyn Hittable>, skybox_scale: f32, depth: u
     color
 }
 
+// Light reaching the hit straight from a randomly picked light, if the shadow ray
+// towards it isn't blocked. Weighted against finding the same light by bouncing
+fn sample_lights(ray: &Ray, hit: &Hit, world: &dyn Hittable, lights: &LightList) -> Vec3 {
+    let none = Vec3::new_zeroes();
+    if hit.material.is_delta() {
+        return none;
+    }
+    let Some(sample) = lights.sample(&hit.point) else {
+        return none;
+    };
+    let direction = sample.direction;
+    let f = hit.material.eval(ray, hit, &direction);
+    if f == none {
+        return none;
+    }
+
+    let shadow = Ray {
+        origin: hit.point,
+        direction,
+        time: ray.time,
+    };
+    // The light only counts if the shadow ray reaches the point picked on it. The margin
+    // is kept tight so other faces of the same mesh close by don't count as reaching it
+    let reach = (0.9999 * sample.distance, 1.0001 * sample.distance);
+    let Some(light_hit) = world.intersects_ray(&shadow, (0.001, reach.1)) else {
+        return none;
+    };
+    if light_hit.t < reach.0 {
+        return none;
+    }
+
+    let light_pdf = lights.light_pdf(sample.light, &hit.point, &light_hit);
+    if light_pdf <= 0.0 {
+        return none;
+    }
+    let bsdf_pdf = hit.material.pdf(ray, hit, &direction);
+    (power_heuristic(light_pdf, bsdf_pdf) / light_pdf)
+        * (f * light_hit.material.emitted(&light_hit))
+}
+
+// Veach's power heuristic, weighting a sample by how likely its own strategy was to find
+// it compared to the other
+fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 {
+        a / (a + b)
+    } else {
+        0.0
+    }
+}
+
 // Converts an f32 buffer to u8.
 // f32 values are clamped to [0, 1], gamma corrected, and then mapped to [0, 255]
 pub fn f32_buf_to_u8(fb: &[f32]) -> Vec<u8> {
@@ -76,6 +168,7 @@ pub fn output_buffer(
     let mut rng = rand::thread_rng();
     let size = (width * height * 3) as usize;
     let mut data: Vec<f32> = vec![0.0; size];
+    let lights = LightList::from_hittable(scene.as_ref());
     for s in 0..samples {
         for y in 0..height {
             for x in 0..width {
@@ -88,9 +181,10 @@ pub fn output_buffer(
                 // Jitter the ray by a random amount
                 let u = (x as f32 + rng.gen::<f32>()) / width as f32;
                 let v = ((height - y) as f32 + rng.gen::<f32>()) / height as f32;
-                let ray = camera.get_ray(u, v);
+                // And send it at a random time while the shutter is open
+                let ray = camera.get_ray(u, v, rng.gen::<f32>());
 
-                color += cast_ray(ray, &scene, skybox_scale, 0);
+                color += cast_ray(ray, &scene, &lights, skybox_scale, 0);
 
                 // Write colour value into buffer
                 data[i] += color.x;
@@ -150,7 +244,65 @@ impl rhai::CustomType for Vec3 {
 
 impl rhai::CustomType for Camera {
     fn build(mut builder: rhai::TypeBuilder<Self>) {
-        builder.with_name("Camera").with_fn("camera", Self::new);
+        builder
+            .with_name("Camera")
+            .with_fn("camera", Self::new)
+            // Thin lens, aperture is the lens diameter
+            .with_fn("camera", Self::new_thin_lens)
+            .with_fn("orthographic_camera", Self::new_orthographic)
+            .with_fn("fisheye_camera", Self::new_fisheye)
+            .with_fn("equirectangular_camera", Self::new_equirectangular)
+            .with_fn("with_shutter", Self::with_shutter)
+            // layout is "side_by_side" or "over_under"
+            .with_fn(
+                "with_stereo",
+                |camera: Camera,
+                 interocular: f32,
+                 convergence: f32,
+                 layout: &str|
+                 -> Result<Camera, Box<rhai::EvalAltResult>> {
+                    let layout = match layout {
+                        "side_by_side" => StereoLayout::SideBySide,
+                        "over_under" => StereoLayout::OverUnder,
+                        _ => return Err(format!("unknown stereo layout '{}'", layout).into()),
+                    };
+                    Ok(camera.with_stereo(interocular, convergence, layout))
+                },
+            )
+            .with_fn(
+                "with_polygon_aperture",
+                |camera: Camera, blades: i64, rotation: f32| {
+                    camera.with_aperture(Aperture::Polygon {
+                        blades: blades.clamp(3, 64) as u32,
+                        rotation,
+                    })
+                },
+            )
+            // Outline points are [x, y] arrays within the unit circle
+            .with_fn(
+                "with_custom_aperture",
+                |camera: Camera, points: rhai::Array| -> Result<Camera, Box<rhai::EvalAltResult>> {
+                    let points = points
+                        .into_iter()
+                        .map(|point| {
+                            let type_name = point.type_name();
+                            point
+                                .try_cast::<rhai::Array>()
+                                .filter(|xy| xy.len() == 2)
+                                .and_then(|xy| {
+                                    Some((xy[0].as_float().ok()?, xy[1].as_float().ok()?))
+                                })
+                                .ok_or_else(|| {
+                                    format!("cannot use a {} as an aperture point", type_name)
+                                })
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    if points.len() < 3 {
+                        return Err("an aperture outline needs at least 3 points".into());
+                    }
+                    Ok(camera.with_aperture(Aperture::Custom(Arc::new(points))))
+                },
+            );
     }
 }
 
@@ -161,7 +313,187 @@ impl rhai::CustomType for Material {
             .with_fn("lambertian", Material::new_lambertian)
             .with_fn("metal", Material::new_metal)
             .with_fn("dielectric", Material::new_dielectric)
-            .with_fn("emissive", Material::new_emissive);
+            .with_fn("dielectric", Material::new_rough_dielectric)
+            .with_fn("with_absorption", Material::with_absorption)
+            .with_fn("emissive", Material::new_emissive)
+            .with_fn("isotropic", Material::new_isotropic)
+            .with_fn("henyey_greenstein", Material::new_henyey_greenstein)
+            // Conductors from a complex index of refraction, or a named metal
+            .with_fn("conductor", |eta: Vec3, k: Vec3, roughness: f32| {
+                Material::new_conductor(ComplexIor { eta, k }, roughness)
+            })
+            .with_fn(
+                "metal",
+                |name: &str, roughness: f32| -> Result<Material, Box<rhai::EvalAltResult>> {
+                    match ComplexIor::named(name) {
+                        Some(ior) => Ok(Material::new_conductor(ior, roughness)),
+                        None => Err(format!("unknown metal '{}'", name).into()),
+                    }
+                },
+            )
+            // Principled materials start as rough plastic and are adjusted with the
+            // with_* functions below
+            .with_fn("principled", |base_color: Vec3| {
+                Material::new_principled(Principled::new(base_color.into()))
+            })
+            .with_fn("principled", |base_color: Texture| {
+                Material::new_principled(Principled::new(base_color))
+            })
+            .with_fn(
+                "principled",
+                |base_color: Vec3, metallic: f32, roughness: f32| {
+                    Material::new_principled(Principled {
+                        metallic,
+                        roughness,
+                        ..Principled::new(base_color.into())
+                    })
+                },
+            )
+            .with_fn("with_metallic", |material: Material, metallic: f32| {
+                with_principled(material, "with_metallic", |p| p.metallic = metallic)
+            })
+            .with_fn("with_roughness", |material: Material, roughness: f32| {
+                with_principled(material, "with_roughness", |p| p.roughness = roughness)
+            })
+            .with_fn("with_specular", |material: Material, specular: f32| {
+                with_principled(material, "with_specular", |p| p.specular = specular)
+            })
+            .with_fn("with_sheen", |material: Material, sheen: f32| {
+                with_principled(material, "with_sheen", |p| p.sheen = sheen)
+            })
+            .with_fn("with_clearcoat", |material: Material, clearcoat: f32| {
+                with_principled(material, "with_clearcoat", |p| p.clearcoat = clearcoat)
+            })
+            .with_fn(
+                "with_clearcoat",
+                |material: Material, clearcoat: f32, roughness: f32| {
+                    with_principled(material, "with_clearcoat", |p| {
+                        p.clearcoat = clearcoat;
+                        p.clearcoat_roughness = roughness;
+                    })
+                },
+            )
+            .with_fn(
+                "with_transmission",
+                |material: Material, transmission: f32| {
+                    with_principled(material, "with_transmission", |p| {
+                        p.transmission = transmission
+                    })
+                },
+            )
+            .with_fn("with_ior", |material: Material, ior: f32| {
+                with_principled(material, "with_ior", |p| p.ior = ior)
+            })
+            // Textured versions
+            .with_fn("lambertian", Material::new_textured_lambertian)
+            .with_fn("metal", Material::new_textured_metal)
+            .with_fn("emissive", Material::new_textured_emissive)
+            // Normal maps hold tangent space normals, bump maps heights
+            .with_fn("with_normal_map", |material: Material, texture: Texture| {
+                material.with_normal_map(NormalMap::Normal {
+                    texture,
+                    strength: 1.0,
+                })
+            })
+            .with_fn(
+                "with_normal_map",
+                |material: Material, texture: Texture, strength: f32| {
+                    material.with_normal_map(NormalMap::Normal { texture, strength })
+                },
+            )
+            .with_fn(
+                "with_bump_map",
+                |material: Material, texture: Texture, strength: f32| {
+                    material.with_normal_map(NormalMap::Bump { texture, strength })
+                },
+            );
+    }
+}
+
+impl rhai::CustomType for Texture {
+    fn build(mut builder: rhai::TypeBuilder<Self>) {
+        builder
+            .with_name("Texture")
+            .with_fn("constant_texture", Texture::Constant)
+            .with_fn("checker_texture", Texture::new_checker)
+            .with_fn("checker_texture", |even: Vec3, odd: Vec3, scale: f32| {
+                Texture::new_checker(even.into(), odd.into(), scale)
+            })
+            .with_fn(
+                "load_image_texture",
+                |path: &str| -> Result<Texture, Box<rhai::EvalAltResult>> {
+                    let image = import::image::load_png_image(path, true)
+                        .map_err(|e| format!("failed to load '{}': {}", path, e))?;
+                    Ok(Texture::new_image(image))
+                },
+            )
+            // For normal maps and other data that isn't a colour
+            .with_fn(
+                "load_linear_image_texture",
+                |path: &str| -> Result<Texture, Box<rhai::EvalAltResult>> {
+                    let image = import::image::load_png_image(path, false)
+                        .map_err(|e| format!("failed to load '{}': {}", path, e))?;
+                    Ok(Texture::new_image(image))
+                },
+            )
+            // pattern is "perlin", "fbm", "turbulence", "marble", "wood", "worley" or "stone"
+            .with_fn(
+                "noise_texture",
+                |pattern: &str,
+                 scale: f32,
+                 low: Vec3,
+                 high: Vec3|
+                 -> Result<Texture, Box<rhai::EvalAltResult>> {
+                    let pattern = match pattern {
+                        "perlin" => NoisePattern::Perlin,
+                        "fbm" => NoisePattern::Fbm,
+                        "turbulence" => NoisePattern::Turbulence,
+                        "marble" => NoisePattern::Marble,
+                        "wood" => NoisePattern::Wood,
+                        "worley" => NoisePattern::Worley,
+                        "stone" => NoisePattern::Stone,
+                        _ => return Err(format!("unknown noise pattern '{}'", pattern).into()),
+                    };
+                    Ok(Texture::new_noise(pattern, scale, low, high))
+                },
+            )
+            .with_fn("with_octaves", |texture: Texture, octaves: i64| {
+                texture.with_octaves(octaves.clamp(1, 16) as u32)
+            })
+            .with_fn("with_seed", |texture: Texture, seed: i64| {
+                texture.with_seed(seed as u64)
+            })
+            .with_fn(
+                "with_wrap",
+                |texture: Texture, wrap: &str| -> Result<Texture, Box<rhai::EvalAltResult>> {
+                    let wrap = match wrap {
+                        "repeat" => WrapMode::Repeat,
+                        "mirror" => WrapMode::Mirror,
+                        "clamp" => WrapMode::Clamp,
+                        _ => return Err(format!("unknown wrap mode '{}'", wrap).into()),
+                    };
+                    Ok(texture.with_wrap(wrap))
+                },
+            )
+            .with_fn("with_uv_mapping", |texture: Texture| {
+                texture.with_mapping(TextureMapping::Uv)
+            })
+            .with_fn(
+                "with_planar_mapping",
+                |texture: Texture, origin: Vec3, u_axis: Vec3, v_axis: Vec3| {
+                    texture.with_mapping(TextureMapping::Planar {
+                        origin,
+                        u_axis,
+                        v_axis,
+                    })
+                },
+            )
+            .with_fn(
+                "with_spherical_mapping",
+                |texture: Texture, center: Vec3| {
+                    texture.with_mapping(TextureMapping::Spherical { center })
+                },
+            );
     }
 }
 
@@ -170,3 +502,481 @@ impl rhai::CustomType for Sphere {
         builder.with_name("Sphere").with_fn("sphere", Sphere::new);
     }
 }
+
+impl rhai::CustomType for MovingSphere {
+    fn build(mut builder: rhai::TypeBuilder<Self>) {
+        builder
+            .with_name("MovingSphere")
+            .with_fn("moving_sphere", MovingSphere::new);
+    }
+}
+
+impl rhai::CustomType for Triangle {
+    fn build(mut builder: rhai::TypeBuilder<Self>) {
+        builder
+            .with_name("Triangle")
+            .with_fn("triangle", Triangle::new);
+    }
+}
+
+impl rhai::CustomType for Quad {
+    fn build(mut builder: rhai::TypeBuilder<Self>) {
+        builder
+            .with_name("Quad")
+            .with_fn("quad", Quad::new)
+            .with_fn("xy_rect", Quad::xy_rect)
+            .with_fn("xz_rect", Quad::xz_rect)
+            .with_fn("yz_rect", Quad::yz_rect);
+    }
+}
+
+impl rhai::CustomType for Cuboid {
+    fn build(mut builder: rhai::TypeBuilder<Self>) {
+        builder.with_name("Cuboid").with_fn("cuboid", Cuboid::new);
+    }
+}
+
+impl rhai::CustomType for Plane {
+    fn build(mut builder: rhai::TypeBuilder<Self>) {
+        builder.with_name("Plane").with_fn("plane", Plane::new);
+    }
+}
+
+impl rhai::CustomType for Disk {
+    fn build(mut builder: rhai::TypeBuilder<Self>) {
+        builder.with_name("Disk").with_fn("disk", Disk::new);
+    }
+}
+
+impl rhai::CustomType for Cylinder {
+    fn build(mut builder: rhai::TypeBuilder<Self>) {
+        builder
+            .with_name("Cylinder")
+            .with_fn("cylinder", Cylinder::new);
+    }
+}
+
+impl rhai::CustomType for Cone {
+    fn build(mut builder: rhai::TypeBuilder<Self>) {
+        builder.with_name("Cone").with_fn("cone", Cone::new);
+    }
+}
+
+impl rhai::CustomType for Capsule {
+    fn build(mut builder: rhai::TypeBuilder<Self>) {
+        builder
+            .with_name("Capsule")
+            .with_fn("capsule", Capsule::new);
+    }
+}
+
+impl rhai::CustomType for Torus {
+    fn build(mut builder: rhai::TypeBuilder<Self>) {
+        builder.with_name("Torus").with_fn("torus", Torus::new);
+    }
+}
+
+impl rhai::CustomType for Sdf {
+    fn build(mut builder: rhai::TypeBuilder<Self>) {
+        builder
+            .with_name("Sdf")
+            // Primitives
+            .with_fn("sdf_sphere", Sdf::Sphere)
+            .with_fn("sdf_box", Sdf::Box)
+            .with_fn("sdf_round_box", Sdf::RoundBox)
+            .with_fn("sdf_torus", Sdf::Torus)
+            .with_fn("sdf_cylinder", Sdf::Cylinder)
+            .with_fn("sdf_capsule", Sdf::Capsule)
+            .with_fn("sdf_plane", |normal: Vec3, offset: f32| {
+                Sdf::Plane(normal.make_unit(), offset)
+            })
+            .with_fn("sdf_mandelbulb", |power: f32, iterations: i64| {
+                Sdf::Mandelbulb(power, iterations.max(0) as u32)
+            })
+            // Combinations
+            .with_fn("sdf_union", |a: Sdf, b: Sdf| {
+                Sdf::Union(Arc::new(a), Arc::new(b))
+            })
+            .with_fn("sdf_intersection", |a: Sdf, b: Sdf| {
+                Sdf::Intersection(Arc::new(a), Arc::new(b))
+            })
+            .with_fn("sdf_difference", |a: Sdf, b: Sdf| {
+                Sdf::Difference(Arc::new(a), Arc::new(b))
+            })
+            .with_fn("sdf_smooth_union", |a: Sdf, b: Sdf, k: f32| {
+                Sdf::SmoothUnion(Arc::new(a), Arc::new(b), k)
+            })
+            .with_fn("sdf_smooth_intersection", |a: Sdf, b: Sdf, k: f32| {
+                Sdf::SmoothIntersection(Arc::new(a), Arc::new(b), k)
+            })
+            .with_fn("sdf_smooth_difference", |a: Sdf, b: Sdf, k: f32| {
+                Sdf::SmoothDifference(Arc::new(a), Arc::new(b), k)
+            })
+            // Modifiers
+            .with_fn("sdf_translate", |sdf: Sdf, offset: Vec3| {
+                Sdf::Translate(Arc::new(sdf), offset)
+            })
+            .with_fn("sdf_rotate", |sdf: Sdf, axis: Vec3, degrees: f32| {
+                Sdf::Transform(Arc::new(sdf), Transform::rotate(axis, degrees))
+            })
+            .with_fn("sdf_scale", |sdf: Sdf, factor: f32| {
+                Sdf::Scale(Arc::new(sdf), factor)
+            })
+            .with_fn("sdf_round", |sdf: Sdf, radius: f32| {
+                Sdf::Round(Arc::new(sdf), radius)
+            })
+            .with_fn("sdf_onion", |sdf: Sdf, thickness: f32| {
+                Sdf::Onion(Arc::new(sdf), thickness)
+            })
+            .with_fn("sdf_repeat", |sdf: Sdf, period: Vec3| {
+                Sdf::Repeat(Arc::new(sdf), period)
+            });
+    }
+}
+
+impl rhai::CustomType for SdfObject {
+    fn build(mut builder: rhai::TypeBuilder<Self>) {
+        builder
+            .with_name("SdfObject")
+            .with_fn("sdf_object", SdfObject::new);
+    }
+}
+
+impl rhai::CustomType for VoxelGrid {
+    fn build(mut builder: rhai::TypeBuilder<Self>) {
+        builder
+            .with_name("VoxelGrid")
+            // Values from a script array, x varying fastest then y then z
+            .with_fn(
+                "voxel_grid",
+                |values: rhai::Array,
+                 nx: i64,
+                 ny: i64,
+                 nz: i64|
+                 -> Result<VoxelGrid, Box<rhai::EvalAltResult>> {
+                    let values = values
+                        .iter()
+                        .map(|v| {
+                            v.as_float()
+                                .map_err(|t| format!("cannot use a {} as a voxel value", t))
+                        })
+                        .collect::<Result<Vec<f32>, _>>()?;
+                    let dims = (nx.max(0) as usize, ny.max(0) as usize, nz.max(0) as usize);
+                    let count = dims
+                        .0
+                        .checked_mul(dims.1)
+                        .and_then(|n| n.checked_mul(dims.2));
+                    if count != Some(values.len()) || values.is_empty() {
+                        return Err(format!(
+                            "{} values do not fill a {}x{}x{} grid",
+                            values.len(),
+                            nx,
+                            ny,
+                            nz
+                        )
+                        .into());
+                    }
+                    Ok(VoxelGrid::new(values, dims))
+                },
+            )
+            .with_fn(
+                "load_voxel_grid",
+                |path: &str| -> Result<VoxelGrid, Box<rhai::EvalAltResult>> {
+                    let grid = import::voxels::load_dense_voxels(path)
+                        .map_err(|e| format!("failed to load '{}': {}", path, e))?;
+                    Ok(grid)
+                },
+            )
+            .with_fn(
+                "load_voxel_grid_raw",
+                |path: &str,
+                 nx: i64,
+                 ny: i64,
+                 nz: i64|
+                 -> Result<VoxelGrid, Box<rhai::EvalAltResult>> {
+                    let dims = (nx.max(0) as usize, ny.max(0) as usize, nz.max(0) as usize);
+                    let grid = import::voxels::load_raw_voxels(path, dims)
+                        .map_err(|e| format!("failed to load '{}': {}", path, e))?;
+                    Ok(grid)
+                },
+            )
+            .with_fn(
+                "voxel_volume",
+                |grid: VoxelGrid, min: Vec3, max: Vec3, density: f32, phase: Material| {
+                    Object::new(VoxelVolume::new(grid, min, max, density, phase))
+                },
+            )
+            .with_fn(
+                "voxel_volume",
+                |grid: VoxelGrid,
+                 min: Vec3,
+                 max: Vec3,
+                 density: f32,
+                 phase: Material,
+                 absorption: f32,
+                 emission: Vec3| {
+                    Object::new(
+                        VoxelVolume::new(grid, min, max, density, phase)
+                            .with_emission(absorption, emission),
+                    )
+                },
+            );
+    }
+}
+
+impl rhai::CustomType for Object {
+    fn build(mut builder: rhai::TypeBuilder<Self>) {
+        builder
+            .with_name("Object")
+            .with_fn(
+                "load_obj",
+                |path: &str,
+                 default_material: Material|
+                 -> Result<Object, Box<rhai::EvalAltResult>> {
+                    let meshes = import::obj::load_obj(path, default_material)
+                        .map_err(|e| format!("failed to load '{}': {}", path, e))?;
+                    Ok(import::meshes_to_object(meshes))
+                },
+            )
+            .with_fn(
+                "load_gltf",
+                |path: &str,
+                 default_material: Material,
+                 aspect: f32|
+                 -> Result<rhai::Map, Box<rhai::EvalAltResult>> {
+                    let scene = import::gltf::load_gltf(path, default_material)
+                        .map_err(|e| format!("failed to load '{}': {}", path, e))?;
+
+                    // Returns #{ object, camera }. camera is () if the file doesn't have one
+                    let mut map = rhai::Map::new();
+                    map.insert(
+                        "object".into(),
+                        rhai::Dynamic::from(import::meshes_to_object(scene.meshes)),
+                    );
+                    map.insert(
+                        "camera".into(),
+                        match scene.camera {
+                            Some(camera) => rhai::Dynamic::from(camera.to_camera(aspect)),
+                            None => rhai::Dynamic::UNIT,
+                        },
+                    );
+                    Ok(map)
+                },
+            )
+            .with_fn(
+                "load_ply",
+                |path: &str, material: Material| -> Result<Object, Box<rhai::EvalAltResult>> {
+                    let mesh = import::ply::load_ply(path, material)
+                        .map_err(|e| format!("failed to load '{}': {}", path, e))?;
+                    Ok(Object::new(mesh))
+                },
+            )
+            .with_fn(
+                "load_stl",
+                |path: &str, material: Material| -> Result<Object, Box<rhai::EvalAltResult>> {
+                    let mesh = import::stl::load_stl(path, material)
+                        .map_err(|e| format!("failed to load '{}': {}", path, e))?;
+                    Ok(Object::new(mesh))
+                },
+            )
+            .with_fn(
+                "load_heightfield",
+                |path: &str,
+                 corner: Vec3,
+                 size: Vec3,
+                 material: Material|
+                 -> Result<Object, Box<rhai::EvalAltResult>> {
+                    let field =
+                        import::heightmap::load_png_heightfield(path, corner, size, material)
+                            .map_err(|e| format!("failed to load '{}': {}", path, e))?;
+                    Ok(Object::new(field))
+                },
+            )
+            .with_fn(
+                "load_heightfield_raw",
+                |path: &str,
+                 width: i64,
+                 corner: Vec3,
+                 size: Vec3,
+                 material: Material|
+                 -> Result<Object, Box<rhai::EvalAltResult>> {
+                    let field = import::heightmap::load_raw_heightfield(
+                        path,
+                        width.max(0) as usize,
+                        corner,
+                        size,
+                        material,
+                    )
+                    .map_err(|e| format!("failed to load '{}': {}", path, e))?;
+                    Ok(Object::new(field))
+                },
+            )
+            // Heights from a script array of floats, width per row
+            .with_fn(
+                "heightfield",
+                |heights: rhai::Array,
+                 width: i64,
+                 corner: Vec3,
+                 size: Vec3,
+                 material: Material|
+                 -> Result<Object, Box<rhai::EvalAltResult>> {
+                    let heights = heights
+                        .iter()
+                        .map(|h| {
+                            h.as_float()
+                                .map_err(|t| format!("cannot use a {} as a height", t))
+                        })
+                        .collect::<Result<Vec<f32>, _>>()?;
+                    let width = width.max(0) as usize;
+                    if width < 2
+                        || !heights.len().is_multiple_of(width)
+                        || heights.len() < 2 * width
+                    {
+                        return Err(format!(
+                            "{} heights is not a whole number of rows of {}, with at least 2 rows",
+                            heights.len(),
+                            width
+                        )
+                        .into());
+                    }
+                    Ok(Object::new(Heightfield::new(
+                        &heights, width, corner, size, material,
+                    )))
+                },
+            )
+            .with_fn("translate", |value: rhai::Dynamic, offset: Vec3| {
+                transform_dynamic(value, Transform::translate(offset))
+            })
+            .with_fn(
+                "rotate",
+                |value: rhai::Dynamic, axis: Vec3, degrees: f32| {
+                    transform_dynamic(value, Transform::rotate(axis, degrees))
+                },
+            )
+            .with_fn("scale", |value: rhai::Dynamic, factor: f32| {
+                transform_dynamic(value, Transform::scale(Vec3::new_uniform(factor)))
+            })
+            .with_fn("scale", |value: rhai::Dynamic, factors: Vec3| {
+                transform_dynamic(value, Transform::scale(factors))
+            })
+            .with_fn(
+                "constant_medium",
+                |boundary: rhai::Dynamic,
+                 density: f32,
+                 phase: Material|
+                 -> Result<Object, Box<rhai::EvalAltResult>> {
+                    let boundary = object_from_dynamic(&boundary).ok_or_else(|| {
+                        format!("cannot fill a value of type {}", boundary.type_name())
+                    })?;
+                    Ok(Object::new(ConstantMedium::new(boundary, density, phase)))
+                },
+            )
+            .with_fn("union", |a: rhai::Dynamic, b: rhai::Dynamic| {
+                csg_dynamic(a, b, CsgOperation::Union)
+            })
+            .with_fn("intersection", |a: rhai::Dynamic, b: rhai::Dynamic| {
+                csg_dynamic(a, b, CsgOperation::Intersection)
+            })
+            .with_fn("difference", |a: rhai::Dynamic, b: rhai::Dynamic| {
+                csg_dynamic(a, b, CsgOperation::Difference)
+            });
+    }
+}
+
+// Registers every type scenes are built from. Front ends add their own render function
+pub fn register_types(engine: &mut rhai::Engine) {
+    engine
+        .build_type::<Vec3>()
+        .build_type::<Camera>()
+        .build_type::<Material>()
+        .build_type::<Texture>()
+        .build_type::<Sphere>()
+        .build_type::<MovingSphere>()
+        .build_type::<Triangle>()
+        .build_type::<Quad>()
+        .build_type::<Cuboid>()
+        .build_type::<Plane>()
+        .build_type::<Disk>()
+        .build_type::<Cylinder>()
+        .build_type::<Cone>()
+        .build_type::<Capsule>()
+        .build_type::<Torus>()
+        .build_type::<Sdf>()
+        .build_type::<SdfObject>()
+        .build_type::<VoxelGrid>()
+        .build_type::<Object>();
+}
+
+// Converts a script value into an object that can be rendered, if it is one
+pub fn object_from_dynamic(value: &rhai::Dynamic) -> Option<Object> {
+    if let Some(object) = value.clone().try_cast::<Object>() {
+        Some(object)
+    } else if let Some(sphere) = value.clone().try_cast::<Sphere>() {
+        Some(Object::new(sphere))
+    } else if let Some(sphere) = value.clone().try_cast::<MovingSphere>() {
+        Some(Object::new(sphere))
+    } else if let Some(triangle) = value.clone().try_cast::<Triangle>() {
+        Some(Object::new(triangle))
+    } else if let Some(quad) = value.clone().try_cast::<Quad>() {
+        Some(Object::new(quad))
+    } else if let Some(cuboid) = value.clone().try_cast::<Cuboid>() {
+        Some(Object::new(cuboid))
+    } else if let Some(plane) = value.clone().try_cast::<Plane>() {
+        Some(Object::new(plane))
+    } else if let Some(disk) = value.clone().try_cast::<Disk>() {
+        Some(Object::new(disk))
+    } else if let Some(cylinder) = value.clone().try_cast::<Cylinder>() {
+        Some(Object::new(cylinder))
+    } else if let Some(cone) = value.clone().try_cast::<Cone>() {
+        Some(Object::new(cone))
+    } else if let Some(capsule) = value.clone().try_cast::<Capsule>() {
+        Some(Object::new(capsule))
+    } else if let Some(torus) = value.clone().try_cast::<Torus>() {
+        Some(Object::new(torus))
+    } else {
+        value.clone().try_cast::<SdfObject>().map(Object::new)
+    }
+}
+
+// Adjusts a principled material. Scripts calling name on any other material get an
+// error rather than a silently unchanged material
+fn with_principled(
+    material: Material,
+    name: &str,
+    f: impl FnOnce(&mut Principled),
+) -> Result<Material, Box<rhai::EvalAltResult>> {
+    match material {
+        Material::Principled(mut principled) => {
+            f(&mut principled);
+            Ok(Material::Principled(principled))
+        }
+        _ => Err(format!("{} needs a principled material", name).into()),
+    }
+}
+
+// Wraps a script object in a transform. Shares the object rather than copying it
+fn transform_dynamic(
+    value: rhai::Dynamic,
+    transform: Transform,
+) -> Result<Object, Box<rhai::EvalAltResult>> {
+    let object = object_from_dynamic(&value)
+        .ok_or_else(|| format!("cannot transform a value of type {}", value.type_name()))?;
+    Ok(Object::new(Transformed::new(object, transform)))
+}
+
+// Combines two script objects into a CSG solid
+fn csg_dynamic(
+    a: rhai::Dynamic,
+    b: rhai::Dynamic,
+    operation: CsgOperation,
+) -> Result<Object, Box<rhai::EvalAltResult>> {
+    let to_object = |value: &rhai::Dynamic| {
+        object_from_dynamic(value)
+            .ok_or_else(|| format!("cannot combine a value of type {}", value.type_name()))
+    };
+    Ok(Object::new(Csg::new(
+        to_object(&a)?,
+        to_object(&b)?,
+        operation,
+    )))
+}
@@ -1,82 +1,334 @@
 use crate::geometry::Hit;
-use crate::math::{random_in_unit_sphere, schlick, Ray, Vec3};
+use crate::material::microfacet::{
+    dielectric_bsdf, dielectric_pdf, fresnel_schlick, reflection, reflection_pdf,
+    roughness_to_alpha, sample_dielectric, sample_visible_normal, smith_g1, smith_g2, ComplexIor,
+    MIN_ALPHA,
+};
+use crate::material::{Principled, Texture};
+use crate::math::{orthonormal_basis, schlick, Ray, Vec3};
 use rand::Rng;
+use std::f32::consts::PI;
+use std::sync::Arc;
 
-pub struct Scatter {
-    pub attenuation: Vec3,
-    pub ray: Option<Ray>,
+// A direction for light to come from, chosen by a material
+pub struct BsdfSample {
+    pub direction: Vec3,
+    // The BSDF times the cosine over the pdf, what light from direction is
+    // scaled by
+    pub weight: Vec3,
+    // Density of direction over solid angle. Meaningless for delta lobes
+    pub pdf: f32,
+    // Whether direction came from a delta lobe, like a mirror, that only sample
+    // can find. eval and pdf leave those out
+    pub delta: bool,
 }
 
-#[derive(Copy, Clone)]
+// Applies the hit's vertex colour, if any
+pub(crate) fn tint(albedo: Vec3, hit: &Hit) -> Vec3 {
+    match hit.vertex_color {
+        Some(color) => albedo * color,
+        None => albedo,
+    }
+}
+
+// Beer-Lambert, what's left of light after distance through a medium absorbing
+// absorption per unit distance
+pub(crate) fn transmittance(absorption: Vec3, distance: f32) -> Vec3 {
+    Vec3::new(
+        (-absorption.x * distance).exp(),
+        (-absorption.y * distance).exp(),
+        (-absorption.z * distance).exp(),
+    )
+}
+
+// The absorption that leaves white light as color after distance
+pub(crate) fn absorption_for(color: Vec3, distance: f32) -> Vec3 {
+    let absorb = |c: f32| -c.clamp(1e-6, 1.0).ln() / distance.max(1e-6);
+    Vec3::new(absorb(color.x), absorb(color.y), absorb(color.z))
+}
+
+// Local frame around the shading normal on the side the ray came from, which is
+// +z. The direction back along the ray, wo, is always above the surface
+pub(crate) struct ShadingFrame {
+    tangent: Vec3,
+    bitangent: Vec3,
+    normal: Vec3,
+    // The true normal on the side the ray came from
+    geometric: Vec3,
+    pub wo: Vec3,
+    // Whether the ray hit the back of the true surface
+    pub leaving: bool,
+}
+
+impl ShadingFrame {
+    pub(crate) fn new(ray: &Ray, hit: &Hit) -> ShadingFrame {
+        let normal = hit.facing_normal(ray);
+        let (tangent, bitangent) = orthonormal_basis(&normal);
+        let leaving = ray.direction.dot(&hit.normal) > 0.0;
+        let wo = -ray.direction.make_unit();
+        ShadingFrame {
+            tangent,
+            bitangent,
+            normal,
+            geometric: if leaving { -hit.normal } else { hit.normal },
+            wo: Vec3::new(wo.dot(&tangent), wo.dot(&bitangent), wo.dot(&normal)),
+            leaving,
+        }
+    }
+
+    pub(crate) fn to_local(&self, v: &Vec3) -> Vec3 {
+        let v = v.make_unit();
+        Vec3::new(
+            v.dot(&self.tangent),
+            v.dot(&self.bitangent),
+            v.dot(&self.normal),
+        )
+    }
+
+    pub(crate) fn to_world(&self, v: &Vec3) -> Vec3 {
+        v.x * self.tangent + v.y * self.bitangent + v.z * self.normal
+    }
+
+    // Whether a world direction and its local one agree on which side of the
+    // surface it's on. A shading normal can make them disagree, and light can't
+    // pass through the true surface without refracting
+    pub(crate) fn agrees(&self, world: &Vec3, local: &Vec3) -> bool {
+        (world.dot(&self.geometric) > 0.0) == (local.z > 0.0)
+    }
+}
+
+// Cosine weighted direction above the surface, pdf cos / pi
+pub(crate) fn sample_cosine(u1: f32, u2: f32) -> Vec3 {
+    let r = u1.sqrt();
+    let phi = 2.0 * PI * u2;
+    Vec3::new(r * phi.cos(), r * phi.sin(), (1.0 - u1).max(0.0).sqrt())
+}
+
+#[derive(Clone)]
 pub struct Lambertian {
-    albedo: Vec3,
+    albedo: Texture,
 }
 
 impl Lambertian {
-    fn scatter(&self, _: &Ray, hit: &Hit) -> Option<Scatter> {
-        // Diffuse bounce (uniformly random direction)
-        Some(Scatter {
-            ray: Some(Ray {
-                origin: hit.point,
-                direction: hit.normal + random_in_unit_sphere(),
-                // direction: hit.normal + random_unit_vector(),
-                // direction: hit.normal + random_in_hemisphere(&hit.normal),
-            }),
-            attenuation: self.albedo,
+    fn sample(&self, ray: &Ray, hit: &Hit) -> Option<BsdfSample> {
+        let frame = ShadingFrame::new(ray, hit);
+        let mut rng = rand::thread_rng();
+        let wi = sample_cosine(rng.gen(), rng.gen());
+        let direction = frame.to_world(&wi);
+        if !frame.agrees(&direction, &wi) {
+            return None;
+        }
+
+        Some(BsdfSample {
+            direction,
+            weight: tint(self.albedo.value(hit), hit),
+            pdf: wi.z / PI,
+            delta: false,
         })
     }
+
+    fn eval(&self, ray: &Ray, hit: &Hit, direction: &Vec3) -> Vec3 {
+        let frame = ShadingFrame::new(ray, hit);
+        let wi = frame.to_local(direction);
+        if wi.z <= 0.0 || !frame.agrees(direction, &wi) {
+            return Vec3::new_zeroes();
+        }
+        (wi.z / PI) * tint(self.albedo.value(hit), hit)
+    }
+
+    // Zero wherever eval is, so light sampling is weighed fairly against it
+    fn pdf(&self, ray: &Ray, hit: &Hit, direction: &Vec3) -> f32 {
+        let frame = ShadingFrame::new(ray, hit);
+        let wi = frame.to_local(direction);
+        if !frame.agrees(direction, &wi) {
+            return 0.0;
+        }
+        wi.z.max(0.0) / PI
+    }
 }
 
-#[derive(Copy, Clone)]
+// GGX microfacet conductor. Reflectance comes from the complex index of
+// refraction, tinted by albedo, when there is one and otherwise from Schlick's
+// approximation with albedo as the colour straight on
+#[derive(Clone)]
 pub struct Metal {
-    albedo: Vec3,
+    albedo: Texture,
     roughness: f32,
+    ior: Option<ComplexIor>,
 }
 
 impl Metal {
-    fn scatter(&self, ray: &Ray, hit: &Hit) -> Option<Scatter> {
-        let reflected = Vec3::reflect(&ray.direction.make_unit(), &hit.normal)
-            + self.roughness * random_in_unit_sphere();
-
-        if reflected.dot(&hit.normal) > 0.0 {
-            Some(Scatter {
-                attenuation: self.albedo.clone(),
-                ray: Some(Ray {
-                    origin: hit.point.clone(),
-                    direction: reflected,
-                }),
-            })
+    fn reflectance(&self, albedo: Vec3, cos_theta: f32) -> Vec3 {
+        match &self.ior {
+            Some(ior) => albedo * ior.fresnel(cos_theta),
+            None => fresnel_schlick(cos_theta, albedo),
+        }
+    }
+
+    // Smooth metal is a perfect mirror
+    fn is_delta(&self) -> bool {
+        roughness_to_alpha(self.roughness) < MIN_ALPHA
+    }
+
+    fn sample(&self, ray: &Ray, hit: &Hit) -> Option<BsdfSample> {
+        let frame = ShadingFrame::new(ray, hit);
+        let wo = frame.wo;
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let albedo = tint(self.albedo.value(hit), hit);
+        let alpha = roughness_to_alpha(self.roughness);
+        let (wi, weight, pdf) = if self.is_delta() {
+            let wi = Vec3::new(-wo.x, -wo.y, wo.z);
+            (wi, self.reflectance(albedo, wo.z), 0.0)
         } else {
-            None
+            // Sampling visible normals leaves only the Fresnel term and the part of
+            // the shadowing not already accounted for by the masking
+            let mut rng = rand::thread_rng();
+            let h = sample_visible_normal(&wo, alpha, rng.gen(), rng.gen());
+            let cos_h = wo.dot(&h);
+            let wi = 2.0 * cos_h * h - wo;
+            if wi.z <= 0.0 {
+                return None;
+            }
+            let shadowing = smith_g2(&wo, &wi, alpha) / smith_g1(&wo, alpha);
+            let pdf = reflection_pdf(&wo, &wi, alpha);
+            (wi, shadowing * self.reflectance(albedo, cos_h), pdf)
+        };
+
+        let direction = frame.to_world(&wi);
+        if !frame.agrees(&direction, &wi) {
+            return None;
+        }
+
+        Some(BsdfSample {
+            direction,
+            weight,
+            pdf,
+            delta: self.is_delta(),
+        })
+    }
+
+    fn eval(&self, ray: &Ray, hit: &Hit, direction: &Vec3) -> Vec3 {
+        if self.is_delta() {
+            return Vec3::new_zeroes();
+        }
+        let frame = ShadingFrame::new(ray, hit);
+        let (wo, wi) = (frame.wo, frame.to_local(direction));
+        if wi.z <= 0.0 || !frame.agrees(direction, &wi) {
+            return Vec3::new_zeroes();
+        }
+
+        let alpha = roughness_to_alpha(self.roughness);
+        let cos_h = wo.dot(&(wo + wi).make_unit());
+        let albedo = tint(self.albedo.value(hit), hit);
+        (reflection(&wo, &wi, alpha) * wi.z) * self.reflectance(albedo, cos_h)
+    }
+
+    fn pdf(&self, ray: &Ray, hit: &Hit, direction: &Vec3) -> f32 {
+        if self.is_delta() {
+            return 0.0;
+        }
+        let frame = ShadingFrame::new(ray, hit);
+        let wi = frame.to_local(direction);
+        if !frame.agrees(direction, &wi) {
+            return 0.0;
         }
+        reflection_pdf(&frame.wo, &wi, roughness_to_alpha(self.roughness))
     }
 }
 
+// Glass and other clear materials. Rough surfaces scatter through GGX
+// microfacets, see microfacet::dielectric_bsdf. Light travelling inside is
+// absorbed at absorption per unit distance, for each channel
 #[derive(Copy, Clone)]
 pub struct Dielectric {
     refraction_index: f32,
+    roughness: f32,
+    absorption: Vec3,
 }
 
 impl Dielectric {
-    fn scatter(&self, ray: &Ray, hit: &Hit) -> Option<Scatter> {
-        let reflected = Vec3::reflect(&ray.direction, &hit.normal);
-        let attenuation = Vec3::new(1.0, 1.0, 1.0);
+    // Smooth glass only reflects and refracts in one direction each
+    fn is_delta(&self) -> bool {
+        roughness_to_alpha(self.roughness) < MIN_ALPHA
+    }
+
+    // Beer-Lambert over the distance travelled inside since the last surface
+    fn absorbed(&self, ray: &Ray, hit: &Hit, leaving: bool) -> Vec3 {
+        if leaving {
+            transmittance(self.absorption, hit.t * ray.direction.length())
+        } else {
+            Vec3::new_uniform(1.0)
+        }
+    }
+
+    // Relative index of refraction of the far side
+    fn eta(&self, leaving: bool) -> f32 {
+        if leaving {
+            1.0 / self.refraction_index
+        } else {
+            self.refraction_index
+        }
+    }
+
+    fn sample(&self, ray: &Ray, hit: &Hit) -> Option<BsdfSample> {
+        let leaving = ray.direction.dot(&hit.normal) > 0.0;
+        let absorbed = self.absorbed(ray, hit, leaving);
+        if self.is_delta() {
+            return Some(BsdfSample {
+                direction: self.sample_smooth(ray, hit, leaving),
+                weight: absorbed,
+                pdf: 0.0,
+                delta: true,
+            });
+        }
+
+        let frame = ShadingFrame::new(ray, hit);
+        let wo = frame.wo;
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let (eta, alpha) = (self.eta(leaving), roughness_to_alpha(self.roughness));
+        let mut rng = rand::thread_rng();
+        let wi = sample_dielectric(&wo, eta, alpha, [rng.gen(), rng.gen(), rng.gen()])?;
+        let direction = frame.to_world(&wi);
+        if !frame.agrees(&direction, &wi) {
+            return None;
+        }
+
+        // Choosing between reflection and refraction by the Fresnel term cancels
+        // it from the weight, leaving the same shadowing as for metals
+        let shadowing = smith_g2(&wo, &wi, alpha) / smith_g1(&wo, alpha);
+        Some(BsdfSample {
+            direction,
+            weight: shadowing * absorbed,
+            pdf: dielectric_pdf(&wo, &wi, eta, alpha),
+            delta: false,
+        })
+    }
 
+    fn sample_smooth(&self, ray: &Ray, hit: &Hit, leaving: bool) -> Vec3 {
+        let normal = hit.shading_normal;
+        let reflected = Vec3::reflect(&ray.direction, &normal);
+
+        // The true surface decides whether the ray is leaving, the shading normal
+        // how it bends
         let ni_over_nt;
         let outward_normal;
         let cosine;
-        if ray.direction.dot(&hit.normal) > 0.0 {
-            outward_normal = -hit.normal;
+        if leaving {
+            outward_normal = -normal;
             ni_over_nt = self.refraction_index;
-            cosine =
-                self.refraction_index * ray.direction.dot(&hit.normal) / ray.direction.length();
+            cosine = self.refraction_index * ray.direction.dot(&normal) / ray.direction.length();
         } else {
-            outward_normal = hit.normal;
+            outward_normal = normal;
             ni_over_nt = 1.0 / self.refraction_index;
-            cosine = -ray.direction.dot(&hit.normal) / ray.direction.length();
+            cosine = -ray.direction.dot(&normal) / ray.direction.length();
         }
 
-        let direction = match Vec3::refract(&ray.direction, &outward_normal, ni_over_nt) {
+        match Vec3::refract(&ray.direction, &outward_normal, ni_over_nt) {
             Some(refracted) => {
                 if rand::thread_rng().gen::<f32>() < schlick(cosine, self.refraction_index) {
                     reflected
@@ -85,63 +337,600 @@ impl Dielectric {
                 }
             }
             None => reflected,
-        };
+        }
+    }
 
-        Some(Scatter {
-            attenuation,
-            ray: Some(Ray {
-                origin: hit.point.clone(),
-                direction,
-            }),
-        })
+    fn eval(&self, ray: &Ray, hit: &Hit, direction: &Vec3) -> Vec3 {
+        if self.is_delta() {
+            return Vec3::new_zeroes();
+        }
+        let frame = ShadingFrame::new(ray, hit);
+        let wi = frame.to_local(direction);
+        if !frame.agrees(direction, &wi) {
+            return Vec3::new_zeroes();
+        }
+
+        let (eta, alpha) = (self.eta(frame.leaving), roughness_to_alpha(self.roughness));
+        let f = dielectric_bsdf(&frame.wo, &wi, eta, alpha) * wi.z.abs();
+        f * self.absorbed(ray, hit, frame.leaving)
+    }
+
+    fn pdf(&self, ray: &Ray, hit: &Hit, direction: &Vec3) -> f32 {
+        if self.is_delta() {
+            return 0.0;
+        }
+        let frame = ShadingFrame::new(ray, hit);
+        let wi = frame.to_local(direction);
+        if !frame.agrees(direction, &wi) {
+            return 0.0;
+        }
+        let (eta, alpha) = (self.eta(frame.leaving), roughness_to_alpha(self.roughness));
+        dielectric_pdf(&frame.wo, &wi, eta, alpha)
     }
 }
 
-#[derive(Copy, Clone)]
+// Only gives off light, absorbing any that reaches it
+#[derive(Clone)]
 pub struct Emissive {
-    emittance: Vec3,
+    emittance: Texture,
+}
+
+// Phase function for participating media. Scatters equally in every direction so
+// the surface normal, which a point inside a volume doesn't have, isn't used
+#[derive(Copy, Clone)]
+pub struct Isotropic {
+    albedo: Vec3,
 }
 
-impl Emissive {
-    fn scatter(&self, _: &Ray, _: &Hit) -> Option<Scatter> {
-        Some(Scatter {
-            ray: None,
-            attenuation: self.emittance,
+impl Isotropic {
+    fn sample(&self) -> Option<BsdfSample> {
+        let mut rng = rand::thread_rng();
+        let z = 1.0 - 2.0 * rng.gen::<f32>();
+        let phi = 2.0 * PI * rng.gen::<f32>();
+        let r = (1.0 - z * z).max(0.0).sqrt();
+
+        Some(BsdfSample {
+            direction: Vec3::new(r * phi.cos(), r * phi.sin(), z),
+            weight: self.albedo,
+            pdf: 1.0 / (4.0 * PI),
+            delta: false,
         })
     }
+
+    fn eval(&self) -> Vec3 {
+        self.albedo / (4.0 * PI)
+    }
 }
 
+// Henyey–Greenstein phase function. g is the mean cosine of the scattering angle,
+// in (-1, 1). Positive values scatter forwards like haze, negative ones backwards
 #[derive(Copy, Clone)]
+pub struct HenyeyGreenstein {
+    albedo: Vec3,
+    g: f32,
+}
+
+impl HenyeyGreenstein {
+    // Density of scattering by an angle with cosine cos_theta
+    fn phase(&self, cos_theta: f32) -> f32 {
+        let g = self.g;
+        let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * PI * denom * denom.sqrt())
+    }
+
+    fn sample(&self, ray: &Ray) -> Option<BsdfSample> {
+        let mut rng = rand::thread_rng();
+        let g = self.g;
+
+        // Invert the CDF for the cosine of the angle from the ray's direction
+        let xi = rng.gen::<f32>();
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * xi
+        } else {
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * xi);
+            ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * rng.gen::<f32>();
+
+        let w = ray.direction.make_unit();
+        let (u, v) = orthonormal_basis(&w);
+        let direction = sin_theta * phi.cos() * u + sin_theta * phi.sin() * v + cos_theta * w;
+
+        Some(BsdfSample {
+            direction,
+            weight: self.albedo,
+            pdf: self.phase(cos_theta),
+            delta: false,
+        })
+    }
+
+    fn pdf(&self, ray: &Ray, direction: &Vec3) -> f32 {
+        self.phase(ray.direction.make_unit().dot(&direction.make_unit()))
+    }
+}
+
+// Where a normal mapped material gets its shading normals from
+#[derive(Clone)]
+pub enum NormalMap {
+    // Tangent space normals, with each channel in [0, 1] standing for [-1, 1].
+    // strength scales how far they lean from the surface normal
+    Normal { texture: Texture, strength: f32 },
+    // Heights from the average of the texture's channels. The surface leans away
+    // from higher ground, more so the higher strength is
+    Bump { texture: Texture, strength: f32 },
+}
+
+// Distance in uv, and along the tangents, between bump map height samples
+const BUMP_DELTA: f32 = 1e-3;
+
+// Perturbs the shading normal then defers to the base material
+#[derive(Clone)]
+pub struct NormalMapped {
+    base: Arc<Material>,
+    map: NormalMap,
+}
+
+impl NormalMapped {
+    // The hit as the base material sees it
+    fn mapped_hit<'a>(&'a self, hit: &Hit<'a>) -> Hit<'a> {
+        Hit {
+            shading_normal: self.shading_normal(hit),
+            material: &self.base,
+            ..*hit
+        }
+    }
+
+    fn shading_normal(&self, hit: &Hit) -> Vec3 {
+        let (normal, tangent, bitangent) = (hit.shading_normal, hit.tangent, hit.bitangent);
+        let perturbed = match &self.map {
+            NormalMap::Normal { texture, strength } => {
+                let encoded = 2.0 * texture.value(hit) - Vec3::new_uniform(1.0);
+                strength * encoded.x * tangent
+                    + strength * encoded.y * bitangent
+                    + encoded.z * normal
+            }
+            NormalMap::Bump { texture, strength } => {
+                let height = |du: f32, dv: f32| {
+                    let shifted = Hit {
+                        point: hit.point + du * tangent + dv * bitangent,
+                        uv: (hit.uv.0 + du, hit.uv.1 + dv),
+                        ..*hit
+                    };
+                    let color = texture.value(&shifted);
+                    (color.x + color.y + color.z) / 3.0
+                };
+                let h = height(0.0, 0.0);
+                let dh_du = (height(BUMP_DELTA, 0.0) - h) / BUMP_DELTA;
+                let dh_dv = (height(0.0, BUMP_DELTA) - h) / BUMP_DELTA;
+                normal - *strength * (dh_du * tangent + dh_dv * bitangent)
+            }
+        };
+
+        if perturbed.length_sq() > 0.0 {
+            perturbed.make_unit()
+        } else {
+            normal
+        }
+    }
+}
+
+#[derive(Clone)]
 pub enum Material {
     Lambertian(Lambertian),
     Metal(Metal),
     Dielectric(Dielectric),
     Emissive(Emissive),
+    Isotropic(Isotropic),
+    HenyeyGreenstein(HenyeyGreenstein),
+    NormalMapped(NormalMapped),
+    Principled(Principled),
 }
 
 impl Material {
     pub fn new_lambertian(albedo: Vec3) -> Material {
+        Material::new_textured_lambertian(albedo.into())
+    }
+
+    pub fn new_textured_lambertian(albedo: Texture) -> Material {
         Material::Lambertian(Lambertian { albedo })
     }
 
     pub fn new_metal(albedo: Vec3, roughness: f32) -> Material {
-        Material::Metal(Metal { albedo, roughness })
+        Material::new_textured_metal(albedo.into(), roughness)
+    }
+
+    // roughness is perceptual, from a mirror at 0 to very rough at 1
+    pub fn new_textured_metal(albedo: Texture, roughness: f32) -> Material {
+        Material::Metal(Metal {
+            albedo,
+            roughness,
+            ior: None,
+        })
+    }
+
+    pub fn new_conductor(ior: ComplexIor, roughness: f32) -> Material {
+        Material::Metal(Metal {
+            albedo: Vec3::new_uniform(1.0).into(),
+            roughness,
+            ior: Some(ior),
+        })
     }
 
     pub fn new_dielectric(refraction_index: f32) -> Material {
-        Material::Dielectric(Dielectric { refraction_index })
+        Material::new_rough_dielectric(refraction_index, 0.0)
+    }
+
+    pub fn new_rough_dielectric(refraction_index: f32, roughness: f32) -> Material {
+        Material::Dielectric(Dielectric {
+            refraction_index,
+            roughness,
+            absorption: Vec3::new_zeroes(),
+        })
+    }
+
+    pub fn new_principled(principled: Principled) -> Material {
+        Material::Principled(principled)
+    }
+
+    // Light passing through distance units of a dielectric is left with color.
+    // Only affects dielectrics and principled materials
+    pub fn with_absorption(self, color: Vec3, distance: f32) -> Material {
+        match self {
+            Material::Dielectric(d) => Material::Dielectric(Dielectric {
+                absorption: absorption_for(color, distance),
+                ..d
+            }),
+            Material::Principled(p) => Material::Principled(Principled {
+                absorption: absorption_for(color, distance),
+                ..p
+            }),
+            _ => self,
+        }
     }
 
     pub fn new_emissive(emittance: Vec3) -> Material {
+        Material::new_textured_emissive(emittance.into())
+    }
+
+    pub fn new_textured_emissive(emittance: Texture) -> Material {
         Material::Emissive(Emissive { emittance })
     }
 
-    pub fn scatter(&self, ray: &Ray, hit: &Hit) -> Option<Scatter> {
+    pub fn new_isotropic(albedo: Vec3) -> Material {
+        Material::Isotropic(Isotropic { albedo })
+    }
+
+    pub fn new_henyey_greenstein(albedo: Vec3, g: f32) -> Material {
+        Material::HenyeyGreenstein(HenyeyGreenstein {
+            albedo,
+            g: g.clamp(-0.99, 0.99),
+        })
+    }
+
+    pub fn with_normal_map(self, map: NormalMap) -> Material {
+        Material::NormalMapped(NormalMapped {
+            base: Arc::new(self),
+            map,
+        })
+    }
+
+    // Whether the material only has delta lobes, so eval and pdf are always 0 and
+    // there's no point sampling lights for it
+    pub fn is_delta(&self) -> bool {
+        match self {
+            Material::Metal(m) => m.is_delta(),
+            Material::Dielectric(m) => m.is_delta(),
+            Material::NormalMapped(m) => m.base.is_delta(),
+            _ => false,
+        }
+    }
+
+    // Whether hits on this material give off light
+    pub fn is_emissive(&self) -> bool {
+        match self {
+            Material::Emissive(_) => true,
+            Material::Principled(m) => m.is_emissive(),
+            Material::NormalMapped(m) => m.base.is_emissive(),
+            _ => false,
+        }
+    }
+
+    // Light given off at the hit, the same in every direction
+    pub fn emitted(&self, hit: &Hit) -> Vec3 {
+        match hit.material {
+            Material::Emissive(m) => m.emittance.value(hit),
+            Material::Principled(m) => m.emission.value(hit),
+            Material::NormalMapped(m) => m.base.emitted(&m.mapped_hit(hit)),
+            _ => Vec3::new_zeroes(),
+        }
+    }
+
+    // Picks a direction for light to come from, which may be from a delta lobe.
+    // None if the light is absorbed
+    pub fn sample(&self, ray: &Ray, hit: &Hit) -> Option<BsdfSample> {
         match hit.material {
-            Material::Lambertian(l) => l.scatter(ray, hit),
-            Material::Metal(m) => m.scatter(ray, hit),
-            Material::Dielectric(m) => m.scatter(ray, hit),
-            Material::Emissive(m) => m.scatter(ray, hit),
+            Material::Lambertian(m) => m.sample(ray, hit),
+            Material::Metal(m) => m.sample(ray, hit),
+            Material::Dielectric(m) => m.sample(ray, hit),
+            Material::Emissive(_) => None,
+            Material::Isotropic(m) => m.sample(),
+            Material::HenyeyGreenstein(m) => m.sample(ray),
+            Material::Principled(m) => m.sample(ray, hit),
+            Material::NormalMapped(m) => m.base.sample(ray, &m.mapped_hit(hit)),
         }
     }
+
+    // How much light arriving from direction is scattered back along the ray: the
+    // BSDF times the cosine at surfaces, and the phase function in media. Leaves
+    // out delta lobes
+    pub fn eval(&self, ray: &Ray, hit: &Hit, direction: &Vec3) -> Vec3 {
+        match hit.material {
+            Material::Lambertian(m) => m.eval(ray, hit, direction),
+            Material::Metal(m) => m.eval(ray, hit, direction),
+            Material::Dielectric(m) => m.eval(ray, hit, direction),
+            Material::Emissive(_) => Vec3::new_zeroes(),
+            Material::Isotropic(m) => m.eval(),
+            Material::HenyeyGreenstein(m) => m.pdf(ray, direction) * m.albedo,
+            Material::Principled(m) => m.eval(ray, hit, direction),
+            Material::NormalMapped(m) => m.base.eval(ray, &m.mapped_hit(hit), direction),
+        }
+    }
+
+    // Density of sample choosing direction, over solid angle. Leaves out delta
+    // lobes
+    pub fn pdf(&self, ray: &Ray, hit: &Hit, direction: &Vec3) -> f32 {
+        match hit.material {
+            Material::Lambertian(m) => m.pdf(ray, hit, direction),
+            Material::Metal(m) => m.pdf(ray, hit, direction),
+            Material::Dielectric(m) => m.pdf(ray, hit, direction),
+            Material::Emissive(_) => 0.0,
+            Material::Isotropic(_) => 1.0 / (4.0 * PI),
+            Material::HenyeyGreenstein(m) => m.pdf(ray, direction),
+            Material::Principled(m) => m.pdf(ray, hit, direction),
+            Material::NormalMapped(m) => m.base.pdf(ray, &m.mapped_hit(hit), direction),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Image, WrapMode};
+
+    // A hit on the xz plane, facing up
+    fn hit_on(material: &Material, t: f32) -> Hit<'_> {
+        Hit {
+            t,
+            point: Vec3::new_zeroes(),
+            normal: Vec3::new(0.0, 1.0, 0.0),
+            shading_normal: Vec3::new(0.0, 1.0, 0.0),
+            tangent: Vec3::new(1.0, 0.0, 0.0),
+            bitangent: Vec3::new(0.0, 0.0, -1.0),
+            uv: (0.0, 0.0),
+            vertex_color: None,
+            material,
+        }
+    }
+
+    fn ray_along(direction: Vec3) -> Ray {
+        Ray {
+            origin: Vec3::new_zeroes(),
+            direction,
+            time: 0.0,
+        }
+    }
+
+    // Checks a material's samples agree with its eval and pdf, and returns the
+    // average weight, how much light it keeps
+    fn check_sampling(material: &Material, ray: &Ray, hit: &Hit) -> f32 {
+        let n = 20000;
+        let mut total = 0.0;
+        for _ in 0..n {
+            let Some(sample) = material.sample(ray, hit) else {
+                continue;
+            };
+            total += sample.weight.x;
+            if sample.delta {
+                continue;
+            }
+
+            let pdf = material.pdf(ray, hit, &sample.direction);
+            assert!((pdf - sample.pdf).abs() <= 1e-2 * pdf.max(1.0));
+            let expected = material.eval(ray, hit, &sample.direction) / pdf;
+            assert!(
+                (sample.weight - expected).length() <= 1e-2 * expected.length().max(1.0),
+                "{:?} != {:?}",
+                sample.weight,
+                expected
+            );
+        }
+        total / n as f32
+    }
+
+    #[test]
+    fn lambertian() {
+        let white = Material::new_lambertian(Vec3::new_uniform(1.0));
+        let hit = hit_on(&white, 1.0);
+        let ray = ray_along(Vec3::new(1.0, -1.0, 0.0));
+        assert!((check_sampling(&white, &ray, &hit) - 1.0).abs() < 1e-5);
+
+        // Nothing comes from below
+        assert_eq!(white.pdf(&ray, &hit, &Vec3::new(0.0, -1.0, 0.0)), 0.0);
+        let up = white.eval(&ray, &hit, &Vec3::new(0.0, 1.0, 0.0));
+        assert!((up.x - 1.0 / PI).abs() < 1e-6);
+
+        // Nor from below the true surface under a tilted shading normal, where the
+        // pdf has to agree with eval for light sampling to be weighted right
+        let tilted = Hit {
+            shading_normal: Vec3::new(1.0, 1.0, 0.0).make_unit(),
+            ..hit_on(&white, 1.0)
+        };
+        let below = Vec3::new(1.0, -0.2, 0.0).make_unit();
+        assert_eq!(white.eval(&ray, &tilted, &below), Vec3::new_zeroes());
+        assert_eq!(white.pdf(&ray, &tilted, &below), 0.0);
+    }
+
+    #[test]
+    fn henyey_greenstein() {
+        let ray = ray_along(Vec3::new(0.0, 0.0, -2.0));
+
+        // The average cosine from the incoming direction is g
+        for g in [-0.6, 0.0, 0.8] {
+            let phase = Material::new_henyey_greenstein(Vec3::new_uniform(0.5), g);
+            let hit = hit_on(&phase, 1.0);
+            assert!((check_sampling(&phase, &ray, &hit) - 0.5).abs() < 1e-5);
+
+            let n = 20000;
+            let mut total = 0.0;
+            for _ in 0..n {
+                let direction = phase.sample(&ray, &hit).unwrap().direction;
+                assert!((direction.length() - 1.0).abs() < 1e-4);
+                total -= direction.z;
+            }
+            assert!((total / n as f32 - g).abs() < 0.02, "g = {}", g);
+        }
+    }
+
+    #[test]
+    fn metal() {
+        let white = Material::new_metal(Vec3::new_uniform(1.0), 0.0);
+        let hit = hit_on(&white, 1.0);
+
+        // Smooth metal is a mirror, which only sample can find
+        let ray = ray_along(Vec3::new(1.0, -1.0, 0.0));
+        let s = white.sample(&ray, &hit).unwrap();
+        assert!(s.delta && white.is_delta());
+        assert!((s.direction - Vec3::new(1.0, 1.0, 0.0).make_unit()).length() < 1e-5);
+        assert_eq!(s.weight, Vec3::new_uniform(1.0));
+        assert_eq!(white.eval(&ray, &hit, &s.direction), Vec3::new_zeroes());
+
+        // Rough metal that reflects everything keeps most of its energy and never
+        // scatters below the surface
+        let rough = Material::new_metal(Vec3::new_uniform(1.0), 0.5);
+        let hit = hit_on(&rough, 1.0);
+        let ray = ray_along(Vec3::new(0.0, -1.0, 0.0));
+        assert!(!rough.is_delta());
+        assert!(check_sampling(&rough, &ray, &hit) > 0.85);
+        for _ in 0..1000 {
+            if let Some(s) = rough.sample(&ray, &hit) {
+                assert!(s.direction.y > 0.0);
+            }
+        }
+
+        // Gold is yellow straight on
+        let gold = Material::new_conductor(ComplexIor::named("gold").unwrap(), 0.0);
+        let s = gold.sample(&ray, &hit_on(&gold, 1.0)).unwrap();
+        assert!(s.weight.x > s.weight.y && s.weight.y > s.weight.z);
+    }
+
+    #[test]
+    fn dielectric() {
+        // Most light goes into rough glass, spread around the straight path, and
+        // little is lost
+        let glass = Material::new_rough_dielectric(1.5, 0.4);
+        let hit = hit_on(&glass, 1.0);
+        let ray = ray_along(Vec3::new(0.0, -1.0, 0.0));
+        assert!(check_sampling(&glass, &ray, &hit) > 0.85);
+        let n = 10000;
+        let mut transmitted = 0;
+        for _ in 0..n {
+            if let Some(s) = glass.sample(&ray, &hit) {
+                if s.direction.y < 0.0 {
+                    transmitted += 1;
+                }
+            }
+        }
+        assert!(transmitted as f32 / n as f32 > 0.85);
+        // and the same coming out
+        let ray = ray_along(Vec3::new(0.3, 1.0, 0.0));
+        assert!(check_sampling(&glass, &ray, &hit) > 0.85);
+
+        // Half the red light is absorbed in each unit travelled inside, none on
+        // the way in
+        let tinted = Material::new_dielectric(1.5).with_absorption(Vec3::new(0.5, 1.0, 1.0), 1.0);
+        let hit = hit_on(&tinted, 2.0);
+        let s = tinted
+            .sample(&ray_along(Vec3::new(0.0, 1.0, 0.0)), &hit)
+            .unwrap();
+        assert!(s.delta);
+        assert!((s.weight - Vec3::new(0.25, 1.0, 1.0)).length() < 1e-5);
+        let s = tinted
+            .sample(&ray_along(Vec3::new(0.0, -1.0, 0.0)), &hit)
+            .unwrap();
+        assert_eq!(s.weight, Vec3::new_uniform(1.0));
+    }
+
+    #[test]
+    fn principled() {
+        let ray = ray_along(Vec3::new(0.6, -0.8, 0.0));
+        let plastic = Material::new_principled(Principled {
+            clearcoat: 0.5,
+            sheen: 0.3,
+            ..Principled::new(Vec3::new(0.8, 0.4, 0.2).into())
+        });
+        check_sampling(&plastic, &ray, &hit_on(&plastic, 1.0));
+
+        let glass = Material::new_principled(Principled {
+            transmission: 0.8,
+            roughness: 0.3,
+            ..Principled::new(Vec3::new(0.8, 0.9, 1.0).into())
+        });
+        check_sampling(&glass, &ray, &hit_on(&glass, 1.0));
+        check_sampling(
+            &glass,
+            &ray_along(Vec3::new(0.0, 1.0, 0.0)),
+            &hit_on(&glass, 1.0),
+        );
+
+        // Emission is separate from scattering
+        let light = Material::new_emissive(Vec3::new_uniform(4.0));
+        let hit = hit_on(&light, 1.0);
+        assert!(light.sample(&ray, &hit).is_none());
+        assert_eq!(light.emitted(&hit), Vec3::new_uniform(4.0));
+        assert_eq!(plastic.emitted(&hit_on(&plastic, 1.0)), Vec3::new_zeroes());
+    }
+
+    #[test]
+    fn normal_maps() {
+        let base = Material::new_lambertian(Vec3::new_uniform(0.5));
+        let hit = Hit {
+            t: 1.0,
+            point: Vec3::new_zeroes(),
+            normal: Vec3::new(0.0, 1.0, 0.0),
+            shading_normal: Vec3::new(0.0, 1.0, 0.0),
+            tangent: Vec3::new(1.0, 0.0, 0.0),
+            bitangent: Vec3::new(0.0, 0.0, -1.0),
+            uv: (0.5, 0.5),
+            vertex_color: None,
+            material: &base,
+        };
+        let shading_normal = |map: NormalMap| match base.clone().with_normal_map(map) {
+            Material::NormalMapped(m) => m.shading_normal(&hit),
+            _ => unreachable!(),
+        };
+
+        // Encoded normals are in tangent space, so a flat map changes nothing
+        let flat = Texture::Constant(Vec3::new(0.5, 0.5, 1.0));
+        let normal = shading_normal(NormalMap::Normal {
+            texture: flat,
+            strength: 1.0,
+        });
+        assert_eq!(normal, hit.shading_normal);
+        let sideways = Texture::Constant(Vec3::new(1.0, 0.5, 0.5));
+        let normal = shading_normal(NormalMap::Normal {
+            texture: sideways,
+            strength: 1.0,
+        });
+        assert_eq!(normal, hit.tangent);
+
+        // Height rising by 2 per unit of u leans the normal back along -u
+        let ramp = Image::new(vec![Vec3::new_zeroes(), Vec3::new_uniform(1.0)], 2, 1);
+        let normal = shading_normal(NormalMap::Bump {
+            texture: Texture::new_image(ramp).with_wrap(WrapMode::Clamp),
+            strength: 0.5,
+        });
+        let expected = Vec3::new(-1.0, 1.0, 0.0).make_unit();
+        assert!((normal - expected).length() < 1e-3);
+    }
 }
@@ -0,0 +1,403 @@
+use std::f32::consts::PI;
+
+use crate::math::Vec3;
+
+// GGX (Trowbridge-Reitz) microfacet helpers. Directions are in a local frame with
+// the surface normal along +z and point away from the surface. alpha is the width
+// of the distribution, 0 being a perfect mirror
+
+// Below this roughness surfaces are treated as perfectly smooth
+pub const MIN_ALPHA: f32 = 1e-3;
+
+// Perceptual roughness in [0, 1] to alpha, as in glTF and Disney's BRDF
+pub fn roughness_to_alpha(roughness: f32) -> f32 {
+    let roughness = roughness.clamp(0.0, 1.0);
+    roughness * roughness
+}
+
+// Distribution of microfacet normals
+pub fn ggx_d(h: &Vec3, alpha: f32) -> f32 {
+    let cos2 = h.z * h.z;
+    if cos2 <= 0.0 {
+        return 0.0;
+    }
+    let a2 = alpha * alpha;
+    let e = 1.0 + (1.0 - cos2) / (cos2 * a2);
+    1.0 / (PI * a2 * cos2 * cos2 * e * e)
+}
+
+// Smith's Λ for GGX, the masking of w by microfacets
+pub fn lambda(w: &Vec3, alpha: f32) -> f32 {
+    let cos2 = w.z * w.z;
+    if cos2 == 0.0 {
+        return f32::MAX;
+    }
+    let tan2 = (1.0 - cos2).max(0.0) / cos2;
+    0.5 * ((1.0 + alpha * alpha * tan2).sqrt() - 1.0)
+}
+
+// Fraction of microfacets visible from w
+pub fn smith_g1(w: &Vec3, alpha: f32) -> f32 {
+    1.0 / (1.0 + lambda(w, alpha))
+}
+
+// Height correlated masking and shadowing of both directions
+pub fn smith_g2(wo: &Vec3, wi: &Vec3, alpha: f32) -> f32 {
+    1.0 / (1.0 + lambda(wo, alpha) + lambda(wi, alpha))
+}
+
+// A microfacet normal sampled in proportion to how much of it wo can see.
+// Heitz 2018, "Sampling the GGX Distribution of Visible Normals"
+pub fn sample_visible_normal(wo: &Vec3, alpha: f32, u1: f32, u2: f32) -> Vec3 {
+    // Stretch the view so the distribution becomes a hemisphere
+    let vh = Vec3::new(alpha * wo.x, alpha * wo.y, wo.z).make_unit();
+
+    // Orthonormal basis around the view
+    let len_sq = vh.x * vh.x + vh.y * vh.y;
+    let t1 = if len_sq > 0.0 {
+        Vec3::new(-vh.y, vh.x, 0.0) / len_sq.sqrt()
+    } else {
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+    let t2 = vh.cross(&t1);
+
+    // Uniform point on a disk, squashed onto the part of the hemisphere facing vh
+    let r = u1.sqrt();
+    let phi = 2.0 * PI * u2;
+    let p1 = r * phi.cos();
+    let s = 0.5 * (1.0 + vh.z);
+    let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * r * phi.sin();
+    let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+
+    // And unstretch
+    Vec3::new(alpha * nh.x, alpha * nh.y, nh.z.max(1e-6)).make_unit()
+}
+
+// Density of sample_visible_normal returning h
+pub fn visible_normal_pdf(wo: &Vec3, h: &Vec3, alpha: f32) -> f32 {
+    if wo.z <= 0.0 {
+        return 0.0;
+    }
+    smith_g1(wo, alpha) * wo.dot(h).max(0.0) * ggx_d(h, alpha) / wo.z
+}
+
+// Schlick's approximation to the Fresnel reflectance, f0 being the reflectance
+// straight on
+pub fn fresnel_schlick(cos_theta: f32, f0: Vec3) -> Vec3 {
+    let f = (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5);
+    f0 + f * (Vec3::new_uniform(1.0) - f0)
+}
+
+// Unpolarised Fresnel reflectance of a dielectric, eta being the index of
+// refraction on the far side relative to this one. 1 under total internal
+// reflection
+pub fn fresnel_dielectric(cos_theta: f32, eta: f32) -> f32 {
+    let cos_i = cos_theta.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+
+    let rs = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let rp = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    0.5 * (rs * rs + rp * rp)
+}
+
+// Refracts w through a microfacet with normal h, both on the same side, into a
+// medium with relative index of refraction eta. None under total internal
+// reflection
+pub fn refract(w: &Vec3, h: &Vec3, eta: f32) -> Option<Vec3> {
+    let cos_i = w.dot(h);
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-*w / eta + (cos_i / eta - cos_t) * *h)
+}
+
+// Microfacet reflection from wo into wi, both above the surface, without the
+// Fresnel term or cosine
+pub fn reflection(wo: &Vec3, wi: &Vec3, alpha: f32) -> f32 {
+    if wo.z <= 0.0 || wi.z <= 0.0 {
+        return 0.0;
+    }
+    let h = (*wo + *wi).make_unit();
+    ggx_d(&h, alpha) * smith_g2(wo, wi, alpha) / (4.0 * wo.z * wi.z)
+}
+
+// Density of reflecting wo off a visible normal into wi
+pub fn reflection_pdf(wo: &Vec3, wi: &Vec3, alpha: f32) -> f32 {
+    if wo.z <= 0.0 || wi.z <= 0.0 {
+        return 0.0;
+    }
+    let h = (*wo + *wi).make_unit();
+    visible_normal_pdf(wo, &h, alpha) / (4.0 * wo.dot(&h).max(1e-6))
+}
+
+// The microfacet normal, facing wo, that refracts wo into wi, and the
+// denominator of the change of variables from it to wi
+fn transmission_normal(wo: &Vec3, wi: &Vec3, eta: f32) -> Option<(Vec3, f32)> {
+    let h = eta * *wi + *wo;
+    if h.length_sq() == 0.0 {
+        return None;
+    }
+    let h = h.make_unit();
+    let h = if h.z < 0.0 { -h } else { h };
+    // Refraction only happens from the front of a microfacet to its back
+    if wo.dot(&h) <= 0.0 || wi.dot(&h) >= 0.0 {
+        return None;
+    }
+    Some((h, wi.dot(&h) + wo.dot(&h) / eta))
+}
+
+// A rough dielectric's BSDF, without the cosine, reflecting wi above the surface
+// and transmitting it below. Walter et al. 2007, "Microfacet Models for
+// Refraction through Rough Surfaces". Like the smooth dielectric it leaves out
+// the change in radiance across the surface
+pub fn dielectric_bsdf(wo: &Vec3, wi: &Vec3, eta: f32, alpha: f32) -> f32 {
+    if wo.z <= 0.0 || wi.z == 0.0 {
+        return 0.0;
+    }
+    if wi.z > 0.0 {
+        let h = (*wo + *wi).make_unit();
+        return fresnel_dielectric(wo.dot(&h), eta) * reflection(wo, wi, alpha);
+    }
+
+    let Some((h, denom)) = transmission_normal(wo, wi, eta) else {
+        return 0.0;
+    };
+    (1.0 - fresnel_dielectric(wo.dot(&h), eta))
+        * ggx_d(&h, alpha)
+        * smith_g2(wo, wi, alpha)
+        * (wi.dot(&h) * wo.dot(&h)).abs()
+        / (-wi.z * wo.z * denom * denom)
+}
+
+// Density of sample_dielectric returning wi
+pub fn dielectric_pdf(wo: &Vec3, wi: &Vec3, eta: f32, alpha: f32) -> f32 {
+    if wo.z <= 0.0 || wi.z == 0.0 {
+        return 0.0;
+    }
+    if wi.z > 0.0 {
+        let h = (*wo + *wi).make_unit();
+        return fresnel_dielectric(wo.dot(&h), eta) * reflection_pdf(wo, wi, alpha);
+    }
+
+    let Some((h, denom)) = transmission_normal(wo, wi, eta) else {
+        return 0.0;
+    };
+    (1.0 - fresnel_dielectric(wo.dot(&h), eta))
+        * visible_normal_pdf(wo, &h, alpha)
+        * wi.dot(&h).abs()
+        / (denom * denom)
+}
+
+// Reflects or refracts wo off a visible normal, choosing by the Fresnel term.
+// Either way the sample's weight is smith_g2 / smith_g1. None if the microfacet
+// sends it to the wrong side of the surface
+pub fn sample_dielectric(wo: &Vec3, eta: f32, alpha: f32, u: [f32; 3]) -> Option<Vec3> {
+    let h = sample_visible_normal(wo, alpha, u[0], u[1]);
+    let cos_h = wo.dot(&h);
+    match refract(wo, &h, eta) {
+        Some(refracted) if u[2] >= fresnel_dielectric(cos_h, eta) => {
+            Some(refracted).filter(|wi| wi.z < 0.0)
+        }
+        _ => Some(2.0 * cos_h * h - *wo).filter(|wi| wi.z > 0.0),
+    }
+}
+
+// Unpolarised Fresnel reflectance of a conductor with complex index of refraction
+// eta + ik, relative to the outside medium
+pub fn fresnel_conductor(cos_theta: f32, eta: f32, k: f32) -> f32 {
+    let cos2 = cos_theta.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+    let (eta2, k2) = (eta * eta, k * k);
+
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos_theta * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    0.5 * (rp + rs)
+}
+
+// Complex index of refraction of a conductor for red, green and blue light
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ComplexIor {
+    pub eta: Vec3,
+    pub k: Vec3,
+}
+
+impl ComplexIor {
+    // Measured metals, sampled at about 650, 550 and 450nm
+    pub fn named(name: &str) -> Option<ComplexIor> {
+        let (eta, k) = match name {
+            "gold" => (
+                Vec3::new(0.143, 0.374, 1.442),
+                Vec3::new(3.983, 2.385, 1.603),
+            ),
+            "copper" => (
+                Vec3::new(0.200, 0.924, 1.102),
+                Vec3::new(3.912, 2.452, 2.142),
+            ),
+            "aluminium" | "aluminum" => (
+                Vec3::new(1.657, 0.880, 0.521),
+                Vec3::new(9.224, 6.270, 4.837),
+            ),
+            "silver" => (
+                Vec3::new(0.155, 0.117, 0.138),
+                Vec3::new(4.828, 3.122, 2.147),
+            ),
+            _ => return None,
+        };
+
+        Some(ComplexIor { eta, k })
+    }
+
+    pub fn fresnel(&self, cos_theta: f32) -> Vec3 {
+        Vec3::new(
+            fresnel_conductor(cos_theta, self.eta.x, self.k.x),
+            fresnel_conductor(cos_theta, self.eta.y, self.k.y),
+            fresnel_conductor(cos_theta, self.eta.z, self.k.z),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn fresnel() {
+        // Straight on the reflectance is ((eta - 1)^2 + k^2) / ((eta + 1)^2 + k^2)
+        let (eta, k) = (0.2f32, 3.9f32);
+        let expected = ((eta - 1.0).powi(2) + k * k) / ((eta + 1.0).powi(2) + k * k);
+        assert!((fresnel_conductor(1.0, eta, k) - expected).abs() < 1e-5);
+        // and everything reflects at grazing angles
+        assert!((fresnel_conductor(0.0, eta, k) - 1.0).abs() < 1e-5);
+
+        let gold = ComplexIor::named("gold").unwrap().fresnel(1.0);
+        assert!(gold.x > gold.y && gold.y > gold.z);
+        assert!(ComplexIor::named("unobtainium").is_none());
+
+        // Glass reflects 4% straight on, and all light past the critical angle
+        // from inside
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-5);
+        assert_eq!(fresnel_dielectric(0.5, 1.0 / 1.5), 1.0);
+        assert_eq!(fresnel_dielectric(0.0, 1.5), 1.0);
+
+        assert_eq!(
+            fresnel_schlick(1.0, Vec3::new_uniform(0.5)),
+            Vec3::new_uniform(0.5)
+        );
+        assert_eq!(
+            fresnel_schlick(0.0, Vec3::new_uniform(0.5)),
+            Vec3::new_uniform(1.0)
+        );
+    }
+
+    #[test]
+    fn visible_normals() {
+        let mut rng = rand::thread_rng();
+        let alpha = 0.25;
+        for wo in [
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.6, 0.0, 0.8),
+            Vec3::new(-0.7, 0.7, 0.141),
+        ] {
+            let n = 20000;
+            let mut total = 0.0;
+            for _ in 0..n {
+                let h = sample_visible_normal(&wo, alpha, rng.gen(), rng.gen());
+                assert!((h.length() - 1.0).abs() < 1e-4);
+                // Only normals facing the viewer are visible
+                assert!(h.z > 0.0 && h.dot(&wo) >= -1e-4);
+
+                // The sampling weight of a reflection off h is G2 / G1
+                let wi = 2.0 * wo.dot(&h) * h - wo;
+                if wi.z > 0.0 {
+                    total += smith_g2(&wo, &wi, alpha) / smith_g1(&wo, alpha);
+                }
+            }
+            // Single scattering loses a little energy, more at grazing angles
+            let albedo = total / n as f32;
+            assert!(albedo > 0.8 && albedo <= 1.0, "albedo {}", albedo);
+        }
+    }
+
+    #[test]
+    fn distribution() {
+        // Projected microfacet area adds up to the macro surface's
+        let mut rng = rand::thread_rng();
+        let alpha = 0.3;
+        let n = 100000;
+        let mut total = 0.0;
+        for _ in 0..n {
+            // Uniform over the hemisphere, pdf 1 / 2pi
+            let z: f32 = rng.gen();
+            let phi = 2.0 * PI * rng.gen::<f32>();
+            let r = (1.0 - z * z).sqrt();
+            let h = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+            total += ggx_d(&h, alpha) * h.z * 2.0 * PI;
+        }
+        assert!((total / n as f32 - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn rough_dielectric() {
+        // The sample weight agrees with the BSDF and pdf, and the pdf integrates
+        // to the fraction of samples that aren't lost below or above the surface
+        let mut rng = rand::thread_rng();
+        let (alpha, eta) = (0.5, 1.5);
+        let wo = Vec3::new(0.6, 0.0, 0.8);
+        let n = 200000;
+        let mut accepted = 0;
+        let mut total = 0.0;
+        for _ in 0..n {
+            let z = 1.0 - 2.0 * rng.gen::<f32>();
+            let phi = 2.0 * PI * rng.gen::<f32>();
+            let r = (1.0 - z * z).max(0.0).sqrt();
+            let wi = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+            total += dielectric_pdf(&wo, &wi, eta, alpha) * 4.0 * PI;
+
+            let Some(wi) = sample_dielectric(&wo, eta, alpha, [rng.gen(), rng.gen(), rng.gen()])
+            else {
+                continue;
+            };
+            accepted += 1;
+            let pdf = dielectric_pdf(&wo, &wi, eta, alpha);
+            if pdf > 0.0 {
+                let weight = dielectric_bsdf(&wo, &wi, eta, alpha) * wi.z.abs() / pdf;
+                let g2 = smith_g2(&wo, &wi, alpha);
+                assert!((weight - g2 / smith_g1(&wo, alpha)).abs() < 1e-2);
+            }
+        }
+        let expected = accepted as f32 / n as f32;
+        assert!(expected > 0.9);
+        assert!((total / n as f32 - expected).abs() < 0.05);
+    }
+
+    #[test]
+    fn refraction() {
+        // Snell's law, bending towards the normal going into glass
+        let w = Vec3::new(0.6, 0.0, 0.8);
+        let h = Vec3::new(0.0, 0.0, 1.0);
+        let t = refract(&w, &h, 1.5).unwrap();
+        assert!((t.length() - 1.0).abs() < 1e-5);
+        assert!((t.x + 0.4).abs() < 1e-5 && t.z < 0.0);
+
+        // and away from it coming out, until it can't
+        let t = refract(&Vec3::new(0.4, 0.0, 0.9165), &h, 1.0 / 1.5).unwrap();
+        assert!((t.x + 0.6).abs() < 1e-3);
+        assert!(refract(&Vec3::new(0.8, 0.0, 0.6), &h, 1.0 / 1.5).is_none());
+    }
+}
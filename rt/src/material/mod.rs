@@ -1,3 +1,17 @@
 pub mod material;
+pub mod microfacet;
+pub mod noise;
+pub mod principled;
+pub mod texture;
 
-pub use material::Material;
+pub use material::{Material, NormalMap};
+pub use microfacet::ComplexIor;
+pub use noise::{Noise, NoisePattern};
+pub use principled::Principled;
+pub use texture::{Image, Texture, TextureMapping, WrapMode};
+
+// The plain grey material most geometry tests are built with
+#[cfg(test)]
+pub(crate) fn grey() -> Material {
+    Material::new_lambertian(crate::math::Vec3::new(0.8, 0.8, 0.8))
+}
@@ -0,0 +1,219 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use crate::math::Vec3;
+
+const POINT_COUNT: usize = 256;
+
+// Lattice noise from random tables. The same seed always gives the same noise so
+// renders split across threads agree
+pub struct Noise {
+    gradients: Vec<Vec3>,
+    // Feature point offsets within each cell for Worley noise
+    points: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Noise {
+    pub fn new(seed: u64) -> Noise {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let random_vec =
+            |rng: &mut StdRng| Vec3::new(rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>());
+
+        let gradients = (0..POINT_COUNT)
+            .map(|_| (2.0 * random_vec(&mut rng) - Vec3::new_uniform(1.0)).make_unit())
+            .collect();
+        let points = (0..POINT_COUNT).map(|_| random_vec(&mut rng)).collect();
+        let mut permutation = || {
+            let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+            p.shuffle(&mut rng);
+            p
+        };
+
+        Noise {
+            gradients,
+            points,
+            perm_x: permutation(),
+            perm_y: permutation(),
+            perm_z: permutation(),
+        }
+    }
+
+    fn hash(&self, i: i64, j: i64, k: i64) -> usize {
+        let mask = POINT_COUNT as i64 - 1;
+        self.perm_x[(i & mask) as usize]
+            ^ self.perm_y[(j & mask) as usize]
+            ^ self.perm_z[(k & mask) as usize]
+    }
+
+    // Gradient noise in about [-1, 1], 0 at every lattice point. The corners are
+    // blended trilinearly with Hermite smoothing so there are no visible creases
+    pub fn perlin(&self, p: &Vec3) -> f32 {
+        let (fx, fy, fz) = (p.x.floor(), p.y.floor(), p.z.floor());
+        let (u, v, w) = (p.x - fx, p.y - fy, p.z - fz);
+        let (i, j, k) = (fx as i64, fy as i64, fz as i64);
+
+        let hermite = |t: f32| t * t * (3.0 - 2.0 * t);
+        let (uu, vv, ww) = (hermite(u), hermite(v), hermite(w));
+
+        let mut total = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let gradient = self.gradients[self.hash(i + di, j + dj, k + dk)];
+                    let (a, b, c) = (di as f32, dj as f32, dk as f32);
+                    let offset = Vec3::new(u - a, v - b, w - c);
+                    total += (a * uu + (1.0 - a) * (1.0 - uu))
+                        * (b * vv + (1.0 - b) * (1.0 - vv))
+                        * (c * ww + (1.0 - c) * (1.0 - ww))
+                        * gradient.dot(&offset);
+                }
+            }
+        }
+
+        total
+    }
+
+    // Fractal Brownian motion, octaves of perlin noise each at twice the frequency
+    // and half the weight of the last
+    pub fn fbm(&self, p: &Vec3, octaves: u32) -> f32 {
+        let mut total = 0.0;
+        let mut p = *p;
+        let mut weight = 1.0;
+        for _ in 0..octaves {
+            total += weight * self.perlin(&p);
+            weight *= 0.5;
+            p = 2.0 * p;
+        }
+
+        total
+    }
+
+    // Like fbm but summing the absolute value of each octave, which gives creases
+    // where the noise crosses 0
+    pub fn turbulence(&self, p: &Vec3, octaves: u32) -> f32 {
+        let mut total = 0.0;
+        let mut p = *p;
+        let mut weight = 1.0;
+        for _ in 0..octaves {
+            total += weight * self.perlin(&p).abs();
+            weight *= 0.5;
+            p = 2.0 * p;
+        }
+
+        total
+    }
+
+    // Cellular noise. Every cell has a random feature point and this returns the
+    // distances to the closest and second closest points
+    pub fn worley(&self, p: &Vec3) -> (f32, f32) {
+        let (i, j, k) = (p.x.floor() as i64, p.y.floor() as i64, p.z.floor() as i64);
+
+        let mut closest = (f32::MAX, f32::MAX);
+        for di in -1..=1 {
+            for dj in -1..=1 {
+                for dk in -1..=1 {
+                    let (ci, cj, ck) = (i + di, j + dj, k + dk);
+                    let point = Vec3::new(ci as f32, cj as f32, ck as f32)
+                        + self.points[self.hash(ci, cj, ck)];
+                    let distance = (point - *p).length();
+                    if distance < closest.0 {
+                        closest = (distance, closest.0);
+                    } else if distance < closest.1 {
+                        closest.1 = distance;
+                    }
+                }
+            }
+        }
+
+        closest
+    }
+}
+
+// How noise is shaped into a value in [0, 1] for a noise texture
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum NoisePattern {
+    Perlin,
+    Fbm,
+    Turbulence,
+    // Veins from a sine wave along z distorted by turbulence
+    Marble,
+    // Rings around the y axis distorted by turbulence
+    Wood,
+    // Distance to the closest feature point, dark at the points
+    Worley,
+    // Distance between the two closest feature points, dark at the cell borders
+    Stone,
+}
+
+impl NoisePattern {
+    pub fn value(&self, noise: &Noise, p: &Vec3, octaves: u32) -> f32 {
+        let value = match self {
+            NoisePattern::Perlin => 0.5 * (1.0 + noise.perlin(p)),
+            NoisePattern::Fbm => 0.5 * (1.0 + noise.fbm(p, octaves)),
+            NoisePattern::Turbulence => noise.turbulence(p, octaves),
+            NoisePattern::Marble => {
+                let veins = p.z + 10.0 * noise.turbulence(p, octaves);
+                0.5 * (1.0 + veins.sin())
+            }
+            NoisePattern::Wood => {
+                let radius = (p.x * p.x + p.z * p.z).sqrt();
+                let rings = radius + 0.5 * noise.turbulence(p, octaves);
+                rings - rings.floor()
+            }
+            NoisePattern::Worley => noise.worley(p).0,
+            NoisePattern::Stone => {
+                let (f1, f2) = noise.worley(p);
+                f2 - f1
+            }
+        };
+
+        value.clamp(0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn perlin() {
+        let noise = Noise::new(7);
+        // 0 on the lattice, smooth and bounded between
+        assert_eq!(noise.perlin(&Vec3::new(3.0, -2.0, 5.0)), 0.0);
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..1000 {
+            let p = 10.0 * Vec3::new(rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>());
+            let n = noise.perlin(&p);
+            assert!(n.abs() <= 1.0);
+            let nearby = noise.perlin(&(p + Vec3::new_uniform(1e-3)));
+            assert!((n - nearby).abs() < 0.01);
+            assert!(noise.turbulence(&p, 4) >= 0.0);
+        }
+
+        // Seeded
+        let p = Vec3::new(0.3, 1.7, -2.2);
+        assert_eq!(noise.fbm(&p, 5), Noise::new(7).fbm(&p, 5));
+        assert_ne!(noise.perlin(&p), Noise::new(8).perlin(&p));
+    }
+
+    #[test]
+    fn worley() {
+        let noise = Noise::new(7);
+        let mut rng = StdRng::seed_from_u64(2);
+        for _ in 0..1000 {
+            let p = 10.0 * Vec3::new(rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>());
+            let (f1, f2) = noise.worley(&p);
+            // There's always a point within the cell itself
+            assert!(f1 <= f2 && f1 < 3.0f32.sqrt());
+        }
+
+        // Feature points are at distance 0
+        let point = noise.points[noise.hash(0, 0, 0)];
+        assert_eq!(noise.worley(&point).0, 0.0);
+        assert_eq!(NoisePattern::Worley.value(&noise, &point, 1), 0.0);
+    }
+}
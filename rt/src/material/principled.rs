@@ -0,0 +1,347 @@
+use std::f32::consts::PI;
+
+use rand::Rng;
+
+use crate::geometry::Hit;
+use crate::material::material::{sample_cosine, tint, transmittance, BsdfSample, ShadingFrame};
+use crate::material::microfacet::{
+    dielectric_bsdf, dielectric_pdf, fresnel_schlick, reflection, reflection_pdf,
+    roughness_to_alpha, sample_dielectric, sample_visible_normal, MIN_ALPHA,
+};
+use crate::material::Texture;
+use crate::math::{Ray, Vec3};
+
+// Disney's principled BSDF (Burley 2012, "Physically Based Shading at Disney"),
+// one material covering everything from plastic to metal to glass. Parameters
+// other than base_color and ior are in [0, 1]
+#[derive(Clone)]
+pub struct Principled {
+    pub base_color: Texture,
+    // Blends from a dielectric to a metal tinted by base_color
+    pub metallic: f32,
+    pub roughness: f32,
+    // Reflectance of the dielectric straight on, 0.5 being the usual 4%
+    pub specular: f32,
+    // Soft white reflection at grazing angles, like cloth
+    pub sheen: f32,
+    // A second, clear specular layer on top
+    pub clearcoat: f32,
+    pub clearcoat_roughness: f32,
+    // Blends the dielectric from diffuse to glass tinted by base_color
+    pub transmission: f32,
+    pub ior: f32,
+    // Absorbed per unit distance by light transmitted inside
+    pub absorption: Vec3,
+    // Light given off on top of what's reflected
+    pub emission: Texture,
+}
+
+// Lobes, in the order of their weights
+const DIFFUSE: usize = 0;
+const SPECULAR: usize = 1;
+const CLEARCOAT: usize = 2;
+const GLASS: usize = 3;
+
+// Schlick's (1 - cos)^5
+fn schlick_weight(cos_theta: f32) -> f32 {
+    (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5)
+}
+
+// What one hit on the surface looks like, with directions in a local frame around
+// the normal facing wo
+struct Lobes {
+    base: Vec3,
+    alpha: f32,
+    clearcoat_alpha: f32,
+    // Relative index of refraction of the far side
+    eta: f32,
+    inside: bool,
+    weights: [f32; 4],
+    // Chance of sampling each lobe
+    probabilities: [f32; 4],
+}
+
+impl Principled {
+    pub fn new(base_color: Texture) -> Principled {
+        Principled {
+            base_color,
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            sheen: 0.0,
+            clearcoat: 0.0,
+            clearcoat_roughness: 0.1,
+            transmission: 0.0,
+            ior: 1.5,
+            absorption: Vec3::new_zeroes(),
+            emission: Vec3::new_zeroes().into(),
+        }
+    }
+
+    // Only constant black emission is known to give off nothing
+    pub(crate) fn is_emissive(&self) -> bool {
+        !matches!(self.emission, Texture::Constant(c) if c == Vec3::new_zeroes())
+    }
+
+    // Light only gets inside through the glass lobe, so that's all there is
+    // there. Opaque surfaces look the same from both sides
+    fn lobes(&self, base: Vec3, leaving: bool) -> Lobes {
+        let metallic = self.metallic.clamp(0.0, 1.0);
+        let transmission = self.transmission.clamp(0.0, 1.0);
+        let glass = (1.0 - metallic) * transmission;
+        let inside = leaving && glass > 0.0;
+
+        let weights = if inside {
+            [0.0, 0.0, 0.0, 1.0]
+        } else {
+            [
+                (1.0 - metallic) * (1.0 - transmission),
+                1.0 - glass,
+                0.25 * self.clearcoat.clamp(0.0, 1.0),
+                glass,
+            ]
+        };
+        let total: f32 = weights.iter().sum();
+
+        Lobes {
+            base,
+            alpha: roughness_to_alpha(self.roughness).max(MIN_ALPHA),
+            clearcoat_alpha: roughness_to_alpha(self.clearcoat_roughness).max(MIN_ALPHA),
+            eta: if inside { 1.0 / self.ior } else { self.ior },
+            inside,
+            weights,
+            probabilities: weights.map(|w| w / total),
+        }
+    }
+
+    fn specular_f0(&self, base: Vec3) -> Vec3 {
+        let dielectric = Vec3::new_uniform(0.08 * self.specular.clamp(0.0, 1.0));
+        let metallic = self.metallic.clamp(0.0, 1.0);
+        (1.0 - metallic) * dielectric + metallic * base
+    }
+
+    // The BSDF in the local frame, without the cosine
+    fn eval_local(&self, lobes: &Lobes, wo: &Vec3, wi: &Vec3) -> Vec3 {
+        let mut f = Vec3::new_zeroes();
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return f;
+        }
+        let w = &lobes.weights;
+
+        if wi.z > 0.0 {
+            let h = (*wo + *wi).make_unit();
+            let cos_d = wi.dot(&h);
+
+            if w[DIFFUSE] > 0.0 {
+                // Burley's diffuse, darker at grazing angles when smooth and
+                // brighter when rough
+                let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+                let diffuse = (1.0 + (fd90 - 1.0) * schlick_weight(wi.z))
+                    * (1.0 + (fd90 - 1.0) * schlick_weight(wo.z))
+                    / PI;
+                let sheen = self.sheen * schlick_weight(cos_d);
+                f += w[DIFFUSE] * (diffuse * lobes.base + Vec3::new_uniform(sheen));
+            }
+            if w[SPECULAR] > 0.0 {
+                let fresnel = fresnel_schlick(wo.dot(&h), self.specular_f0(lobes.base));
+                f += (w[SPECULAR] * reflection(wo, wi, lobes.alpha)) * fresnel;
+            }
+            if w[CLEARCOAT] > 0.0 {
+                let fresnel = fresnel_schlick(wo.dot(&h), Vec3::new_uniform(0.04));
+                f += (w[CLEARCOAT] * reflection(wo, wi, lobes.clearcoat_alpha)) * fresnel;
+            }
+        }
+        if w[GLASS] > 0.0 {
+            let glass = w[GLASS] * dielectric_bsdf(wo, wi, lobes.eta, lobes.alpha);
+            // Transmission is tinted once, on the way in
+            f += if wi.z < 0.0 && !lobes.inside {
+                glass * lobes.base
+            } else {
+                Vec3::new_uniform(glass)
+            };
+        }
+
+        f
+    }
+
+    fn pdf_local(&self, lobes: &Lobes, wo: &Vec3, wi: &Vec3) -> f32 {
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return 0.0;
+        }
+        let p = &lobes.probabilities;
+
+        let mut pdf = p[GLASS] * dielectric_pdf(wo, wi, lobes.eta, lobes.alpha);
+        if wi.z > 0.0 {
+            pdf += p[DIFFUSE] * wi.z / PI
+                + p[SPECULAR] * reflection_pdf(wo, wi, lobes.alpha)
+                + p[CLEARCOAT] * reflection_pdf(wo, wi, lobes.clearcoat_alpha);
+        }
+        pdf
+    }
+
+    fn sample_local(&self, lobes: &Lobes, wo: &Vec3) -> Option<Vec3> {
+        let mut rng = rand::thread_rng();
+        let p = &lobes.probabilities;
+
+        let mut u = rng.gen::<f32>();
+        let mut lobe = GLASS;
+        for (i, &probability) in p.iter().enumerate() {
+            if u < probability {
+                lobe = i;
+                break;
+            }
+            u -= probability;
+        }
+
+        let (u1, u2) = (rng.gen::<f32>(), rng.gen::<f32>());
+        let reflect = |h: Vec3| Some(2.0 * wo.dot(&h) * h - *wo).filter(|wi| wi.z > 0.0);
+        match lobe {
+            DIFFUSE => Some(sample_cosine(u1, u2)),
+            SPECULAR => reflect(sample_visible_normal(wo, lobes.alpha, u1, u2)),
+            CLEARCOAT => reflect(sample_visible_normal(wo, lobes.clearcoat_alpha, u1, u2)),
+            _ => sample_dielectric(wo, lobes.eta, lobes.alpha, [u1, u2, rng.gen()]),
+        }
+    }
+
+    // Beer-Lambert over the distance travelled inside since the last surface
+    fn absorbed(&self, ray: &Ray, hit: &Hit, lobes: &Lobes) -> Vec3 {
+        if lobes.inside {
+            transmittance(self.absorption, hit.t * ray.direction.length())
+        } else {
+            Vec3::new_uniform(1.0)
+        }
+    }
+
+    // Samples one lobe and weights by every lobe that could have produced the
+    // same direction, so sharp and broad lobes mix without fireflies
+    pub(crate) fn sample(&self, ray: &Ray, hit: &Hit) -> Option<BsdfSample> {
+        let frame = ShadingFrame::new(ray, hit);
+        if frame.wo.z <= 0.0 {
+            return None;
+        }
+
+        let lobes = self.lobes(tint(self.base_color.value(hit), hit), frame.leaving);
+        let wi = self.sample_local(&lobes, &frame.wo)?;
+        let direction = frame.to_world(&wi);
+        let pdf = self.pdf_local(&lobes, &frame.wo, &wi);
+        if pdf <= 0.0 || !frame.agrees(&direction, &wi) {
+            return None;
+        }
+        let f = self.eval_local(&lobes, &frame.wo, &wi);
+
+        Some(BsdfSample {
+            direction,
+            weight: (wi.z.abs() / pdf) * f * self.absorbed(ray, hit, &lobes),
+            pdf,
+            delta: false,
+        })
+    }
+
+    pub(crate) fn eval(&self, ray: &Ray, hit: &Hit, direction: &Vec3) -> Vec3 {
+        let frame = ShadingFrame::new(ray, hit);
+        let wi = frame.to_local(direction);
+        if !frame.agrees(direction, &wi) {
+            return Vec3::new_zeroes();
+        }
+
+        let lobes = self.lobes(tint(self.base_color.value(hit), hit), frame.leaving);
+        let f = self.eval_local(&lobes, &frame.wo, &wi);
+        wi.z.abs() * f * self.absorbed(ray, hit, &lobes)
+    }
+
+    pub(crate) fn pdf(&self, ray: &Ray, hit: &Hit, direction: &Vec3) -> f32 {
+        let frame = ShadingFrame::new(ray, hit);
+        let wi = frame.to_local(direction);
+        if !frame.agrees(direction, &wi) {
+            return 0.0;
+        }
+        // Only the weights matter for the pdf, not the colour
+        let lobes = self.lobes(Vec3::new_uniform(1.0), frame.leaving);
+        self.pdf_local(&lobes, &frame.wo, &wi)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The estimate of how much light leaves towards every direction
+    fn albedo(principled: &Principled, wo: &Vec3, leaving: bool) -> f32 {
+        let lobes = principled.lobes(Vec3::new_uniform(1.0), leaving);
+        let n = 20000;
+        let mut total = 0.0;
+        for _ in 0..n {
+            let Some(wi) = principled.sample_local(&lobes, wo) else {
+                continue;
+            };
+            let pdf = principled.pdf_local(&lobes, wo, &wi);
+            if pdf > 0.0 {
+                total += principled.eval_local(&lobes, wo, &wi).x * wi.z.abs() / pdf;
+            }
+        }
+        total / n as f32
+    }
+
+    #[test]
+    fn sampling() {
+        let wo = Vec3::new(0.6, 0.0, 0.8);
+        let white = Principled::new(Vec3::new_uniform(1.0).into());
+
+        // A white metal loses only what single scattering does
+        let metal = Principled {
+            metallic: 1.0,
+            roughness: 0.3,
+            ..white.clone()
+        };
+        let a = albedo(&metal, &wo, false);
+        assert!(a > 0.9 && a < 1.02, "metal {}", a);
+
+        // White plastic reflects about everything
+        let plastic = Principled {
+            clearcoat: 1.0,
+            sheen: 0.5,
+            ..white.clone()
+        };
+        let a = albedo(&plastic, &wo, false);
+        assert!(a > 0.9 && a < 1.15, "plastic {}", a);
+
+        // As does glass in both directions
+        let glass = Principled {
+            transmission: 1.0,
+            roughness: 0.3,
+            ..white.clone()
+        };
+        let a = albedo(&glass, &wo, false);
+        assert!(a > 0.9 && a < 1.02, "glass in {}", a);
+        let a = albedo(&glass, &Vec3::new(0.0, 0.0, 1.0), true);
+        assert!(a > 0.9 && a < 1.02, "glass out {}", a);
+    }
+
+    #[test]
+    fn pdf() {
+        // The pdf integrates to at most 1 over the sphere
+        let wo = Vec3::new(0.0, 0.6, 0.8);
+        let principled = Principled {
+            metallic: 0.3,
+            transmission: 0.5,
+            clearcoat: 0.5,
+            roughness: 0.4,
+            ..Principled::new(Vec3::new_uniform(0.5).into())
+        };
+        let lobes = principled.lobes(Vec3::new_uniform(0.5), false);
+
+        let mut rng = rand::thread_rng();
+        let n = 200000;
+        let mut total = 0.0;
+        for _ in 0..n {
+            // Uniform over the sphere, pdf 1 / 4pi
+            let z = 1.0 - 2.0 * rng.gen::<f32>();
+            let phi = 2.0 * PI * rng.gen::<f32>();
+            let r = (1.0 - z * z).max(0.0).sqrt();
+            let wi = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+            total += principled.pdf_local(&lobes, &wo, &wi) * 4.0 * PI;
+        }
+        let total = total / n as f32;
+        assert!(total > 0.85 && total < 1.05, "{}", total);
+    }
+}
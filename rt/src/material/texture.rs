@@ -0,0 +1,406 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use crate::geometry::Hit;
+use crate::material::noise::{Noise, NoisePattern};
+use crate::math::Vec3;
+
+// A grid of linear colours, row 0 at the top
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Vec3>,
+}
+
+impl Image {
+    // pixels is padded with black or truncated to fit
+    pub fn new(pixels: Vec<Vec3>, width: usize, height: usize) -> Image {
+        let (width, height) = (width.max(1), height.max(1));
+        let mut pixels = pixels;
+        pixels.resize(width * height, Vec3::new_zeroes());
+        Image {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Vec3 {
+        self.pixels[y * self.width + x]
+    }
+}
+
+// What happens to image lookups outside [0, 1]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum WrapMode {
+    Repeat,
+    Mirror,
+    Clamp,
+}
+
+impl WrapMode {
+    // Wraps texel i into [0, n)
+    fn apply(&self, i: i64, n: usize) -> usize {
+        let n = n as i64;
+        let i = match self {
+            WrapMode::Repeat => i.rem_euclid(n),
+            WrapMode::Mirror => {
+                let i = i.rem_euclid(2 * n);
+                if i < n {
+                    i
+                } else {
+                    2 * n - 1 - i
+                }
+            }
+            WrapMode::Clamp => i.clamp(0, n - 1),
+        };
+        i as usize
+    }
+}
+
+// How a hit is turned into the texture coordinates a texture is looked up with
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TextureMapping {
+    // The surface's own uv
+    Uv,
+    // Distance along u_axis and v_axis from origin, so the texture repeats every
+    // unit of the axes' length. Useful for surfaces without a uv of their own
+    Planar {
+        origin: Vec3,
+        u_axis: Vec3,
+        v_axis: Vec3,
+    },
+    // Longitude and latitude around center, with v running from the bottom pole to the top
+    Spherical {
+        center: Vec3,
+    },
+}
+
+impl TextureMapping {
+    pub fn uv(&self, hit: &Hit) -> (f32, f32) {
+        match self {
+            TextureMapping::Uv => hit.uv,
+            TextureMapping::Planar {
+                origin,
+                u_axis,
+                v_axis,
+            } => {
+                let d = hit.point - *origin;
+                (d.dot(u_axis), d.dot(v_axis))
+            }
+            TextureMapping::Spherical { center } => {
+                let d = (hit.point - *center).make_unit();
+                (
+                    ((-d.z).atan2(d.x) + PI) / (2.0 * PI),
+                    (-d.y).clamp(-1.0, 1.0).acos() / PI,
+                )
+            }
+        }
+    }
+}
+
+// The colour of a surface at a hit. Cheap to clone as anything large is shared
+#[derive(Clone)]
+pub enum Texture {
+    Constant(Vec3),
+    // Alternates between even and odd in squares 1 / scale wide
+    Checker {
+        even: Arc<Texture>,
+        odd: Arc<Texture>,
+        scale: f32,
+        mapping: TextureMapping,
+    },
+    // Bilinearly filtered with (0, 0) at the bottom left of the image
+    Image {
+        image: Arc<Image>,
+        wrap: WrapMode,
+        mapping: TextureMapping,
+    },
+    // Solid noise through space, blended from low to high. Features are about
+    // 1 / scale across
+    Noise {
+        noise: Arc<Noise>,
+        pattern: NoisePattern,
+        scale: f32,
+        octaves: u32,
+        low: Vec3,
+        high: Vec3,
+    },
+}
+
+impl Texture {
+    pub fn new_checker(even: Texture, odd: Texture, scale: f32) -> Texture {
+        Texture::Checker {
+            even: Arc::new(even),
+            odd: Arc::new(odd),
+            scale,
+            mapping: TextureMapping::Uv,
+        }
+    }
+
+    pub fn new_image(image: Image) -> Texture {
+        Texture::Image {
+            image: Arc::new(image),
+            wrap: WrapMode::Repeat,
+            mapping: TextureMapping::Uv,
+        }
+    }
+
+    pub fn new_noise(pattern: NoisePattern, scale: f32, low: Vec3, high: Vec3) -> Texture {
+        Texture::Noise {
+            noise: Arc::new(Noise::new(0)),
+            pattern,
+            scale,
+            octaves: 7,
+            low,
+            high,
+        }
+    }
+
+    // Constant textures have no coordinates to map and noise is looked up with the
+    // hit point, so both are left as they are
+    pub fn with_mapping(self, mapping: TextureMapping) -> Texture {
+        match self {
+            Texture::Constant(_) | Texture::Noise { .. } => self,
+            Texture::Checker {
+                even, odd, scale, ..
+            } => Texture::Checker {
+                even,
+                odd,
+                scale,
+                mapping,
+            },
+            Texture::Image { image, wrap, .. } => Texture::Image {
+                image,
+                wrap,
+                mapping,
+            },
+        }
+    }
+
+    // Only affect noise textures
+    pub fn with_octaves(self, octaves: u32) -> Texture {
+        match self {
+            Texture::Noise {
+                noise,
+                pattern,
+                scale,
+                low,
+                high,
+                ..
+            } => Texture::Noise {
+                noise,
+                pattern,
+                scale,
+                octaves: octaves.max(1),
+                low,
+                high,
+            },
+            _ => self,
+        }
+    }
+
+    pub fn with_seed(self, seed: u64) -> Texture {
+        match self {
+            Texture::Noise {
+                pattern,
+                scale,
+                octaves,
+                low,
+                high,
+                ..
+            } => Texture::Noise {
+                noise: Arc::new(Noise::new(seed)),
+                pattern,
+                scale,
+                octaves,
+                low,
+                high,
+            },
+            _ => self,
+        }
+    }
+
+    // Only affects image textures
+    pub fn with_wrap(self, wrap: WrapMode) -> Texture {
+        match self {
+            Texture::Image { image, mapping, .. } => Texture::Image {
+                image,
+                wrap,
+                mapping,
+            },
+            _ => self,
+        }
+    }
+
+    pub fn value(&self, hit: &Hit) -> Vec3 {
+        match self {
+            Texture::Constant(color) => *color,
+            Texture::Checker {
+                even,
+                odd,
+                scale,
+                mapping,
+            } => {
+                let (u, v) = mapping.uv(hit);
+                let parity = (u * scale).floor() as i64 + (v * scale).floor() as i64;
+                if parity.rem_euclid(2) == 0 {
+                    even.value(hit)
+                } else {
+                    odd.value(hit)
+                }
+            }
+            Texture::Image {
+                image,
+                wrap,
+                mapping,
+            } => {
+                let (u, v) = mapping.uv(hit);
+                sample_bilinear(image, *wrap, u, v)
+            }
+            Texture::Noise {
+                noise,
+                pattern,
+                scale,
+                octaves,
+                low,
+                high,
+            } => {
+                let t = pattern.value(noise, &(*scale * hit.point), *octaves);
+                *low + t * (*high - *low)
+            }
+        }
+    }
+}
+
+impl From<Vec3> for Texture {
+    fn from(color: Vec3) -> Texture {
+        Texture::Constant(color)
+    }
+}
+
+// Texel centres are at (i + 0.5) / n
+fn sample_bilinear(image: &Image, wrap: WrapMode, u: f32, v: f32) -> Vec3 {
+    let x = u * image.width as f32 - 0.5;
+    let y = (1.0 - v) * image.height as f32 - 0.5;
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+
+    let texel = |dx: i64, dy: i64| {
+        image.pixel(
+            wrap.apply(x0 as i64 + dx, image.width),
+            wrap.apply(y0 as i64 + dy, image.height),
+        )
+    };
+    let top = (1.0 - fx) * texel(0, 0) + fx * texel(1, 0);
+    let bottom = (1.0 - fx) * texel(0, 1) + fx * texel(1, 1);
+    (1.0 - fy) * top + fy * bottom
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Material;
+
+    fn hit_at<'a>(point: Vec3, uv: (f32, f32), material: &'a Material) -> Hit<'a> {
+        Hit {
+            t: 1.0,
+            point,
+            normal: Vec3::new(0.0, 1.0, 0.0),
+            shading_normal: Vec3::new(0.0, 1.0, 0.0),
+            tangent: Vec3::new(1.0, 0.0, 0.0),
+            bitangent: Vec3::new(0.0, 0.0, -1.0),
+            uv,
+            vertex_color: None,
+            material,
+        }
+    }
+
+    #[test]
+    fn checker() {
+        let mat = Material::new_lambertian(Vec3::new_uniform(1.0));
+        let black = Vec3::new_zeroes();
+        let white = Vec3::new_uniform(1.0);
+        let checker = Texture::new_checker(white.into(), black.into(), 2.0);
+
+        assert_eq!(checker.value(&hit_at(black, (0.1, 0.1), &mat)), white);
+        assert_eq!(checker.value(&hit_at(black, (0.6, 0.1), &mat)), black);
+        assert_eq!(checker.value(&hit_at(black, (0.6, 0.6), &mat)), white);
+        // Continues past [0, 1]
+        assert_eq!(checker.value(&hit_at(black, (-0.1, 0.1), &mat)), black);
+
+        // Planar mapping ignores uv
+        let planar = checker.with_mapping(TextureMapping::Planar {
+            origin: Vec3::new_zeroes(),
+            u_axis: Vec3::new(1.0, 0.0, 0.0),
+            v_axis: Vec3::new(0.0, 0.0, 1.0),
+        });
+        let point = Vec3::new(0.75, 5.0, 0.25);
+        assert_eq!(planar.value(&hit_at(point, (0.1, 0.1), &mat)), black);
+    }
+
+    #[test]
+    fn image() {
+        let mat = Material::new_lambertian(Vec3::new_uniform(1.0));
+        // 2x1, black on the left and white on the right
+        let image = Image::new(vec![Vec3::new_zeroes(), Vec3::new_uniform(1.0)], 2, 1);
+        let texture = Texture::new_image(image);
+        let value = |texture: &Texture, u: f32| {
+            texture.value(&hit_at(Vec3::new_zeroes(), (u, 0.5), &mat)).x
+        };
+
+        // Exact at texel centres and blended between them
+        assert_eq!(value(&texture, 0.25), 0.0);
+        assert_eq!(value(&texture, 0.5), 0.5);
+        assert_eq!(value(&texture, 0.75), 1.0);
+
+        // Repeating blends back towards the left edge
+        assert_eq!(value(&texture, 1.0), 0.5);
+        assert_eq!(value(&texture, 1.25), 0.0);
+
+        let clamped = texture.clone().with_wrap(WrapMode::Clamp);
+        assert_eq!(value(&clamped, 1.0), 1.0);
+        assert_eq!(value(&clamped, -1.0), 0.0);
+
+        let mirrored = texture.with_wrap(WrapMode::Mirror);
+        assert_eq!(value(&mirrored, 1.25), 1.0);
+        assert_eq!(value(&mirrored, 1.75), 0.0);
+    }
+
+    #[test]
+    fn noise() {
+        let mat = Material::new_lambertian(Vec3::new_uniform(1.0));
+        let low = Vec3::new(0.0, 0.0, 1.0);
+        let high = Vec3::new(1.0, 1.0, 1.0);
+        let marble = Texture::new_noise(NoisePattern::Marble, 4.0, low, high);
+        let reseeded = marble.clone().with_seed(3).with_octaves(2);
+
+        let point = Vec3::new(0.3, 0.6, 0.1);
+        let value = marble.value(&hit_at(point, (0.0, 0.0), &mat));
+        // Blended between low and high
+        assert_eq!(value.z, 1.0);
+        assert!(value.x >= 0.0 && value.x <= 1.0 && value.x == value.y);
+        assert_ne!(reseeded.value(&hit_at(point, (0.0, 0.0), &mat)), value);
+    }
+
+    #[test]
+    fn spherical_mapping() {
+        let mat = Material::new_lambertian(Vec3::new_uniform(1.0));
+        let mapping = TextureMapping::Spherical {
+            center: Vec3::new(0.0, 2.0, 0.0),
+        };
+        let (_, v) = mapping.uv(&hit_at(Vec3::new(0.0, 3.0, 0.0), (0.0, 0.0), &mat));
+        assert_eq!(v, 1.0);
+        let (u, v) = mapping.uv(&hit_at(Vec3::new(-1.0, 2.0, 0.0), (0.0, 0.0), &mat));
+        assert!((u - 1.0).abs() < 1e-6 || u.abs() < 1e-6);
+        assert!((v - 0.5).abs() < 1e-6);
+    }
+}
@@ -1,7 +1,10 @@
+pub mod poly;
 pub mod ray;
+pub mod transform;
 pub mod vec3;
 
 pub use self::ray::Ray;
+pub use self::transform::Transform;
 pub use self::vec3::Vec3;
 
 use rand::Rng;
@@ -35,9 +38,28 @@ pub fn random_in_unit_sphere() -> Vec3 {
 //     }
 // }
 
+// Two unit vectors perpendicular to each other and to the unit vector n.
+// Duff et al. 2017, "Building an Orthonormal Basis, Revisited"
+pub fn orthonormal_basis(n: &Vec3) -> (Vec3, Vec3) {
+    let sign = 1.0f32.copysign(n.z);
+    let a = -1.0 / (sign + n.z);
+    let b = n.x * n.y * a;
+
+    (
+        Vec3::new(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x),
+        Vec3::new(b, sign + n.y * n.y * a, -n.y),
+    )
+}
+
 pub fn schlick(cosine: f32, refraction_index: f32) -> f32 {
     let mut r0 = (1.0 - refraction_index) / (1.0 + refraction_index);
     r0 *= r0;
 
     r0 + (1.0 - r0) * (1.0 - cosine).powf(5.0)
 }
+
+// Fails the test unless a and b are within 1e-5 of each other
+#[cfg(test)]
+pub(crate) fn assert_near(a: Vec3, b: Vec3) {
+    assert!((a - b).length() < 1e-5, "{:?} != {:?}", a, b);
+}
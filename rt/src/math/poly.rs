@@ -0,0 +1,178 @@
+// Real roots of low order polynomials. Coefficients are in increasing order of power,
+// so [c0, c1, c2] is c0 + c1 x + c2 x^2.
+// Based on Jochen Schwarze's solvers from Graphics Gems I. Done in f64 since the quartic
+// loses too much precision in f32
+
+const EPSILON: f64 = 1e-9;
+
+fn is_zero(x: f64) -> bool {
+    x.abs() < EPSILON
+}
+
+pub fn solve_quadratic(c: [f64; 3]) -> Vec<f64> {
+    // Normal form x^2 + 2px + q = 0
+    let p = c[1] / (2.0 * c[2]);
+    let q = c[0] / c[2];
+    let d = p * p - q;
+
+    if is_zero(d) {
+        vec![-p]
+    } else if d < 0.0 {
+        vec![]
+    } else {
+        let sqrt_d = d.sqrt();
+        vec![-sqrt_d - p, sqrt_d - p]
+    }
+}
+
+pub fn solve_cubic(c: [f64; 4]) -> Vec<f64> {
+    // Normal form x^3 + Ax^2 + Bx + C = 0
+    let a = c[2] / c[3];
+    let b = c[1] / c[3];
+    let c = c[0] / c[3];
+
+    // Substitute x = y - A/3 to eliminate the quadratic term: y^3 + 3py + 2q = 0
+    let sq_a = a * a;
+    let p = (-sq_a / 3.0 + b) / 3.0;
+    let q = (2.0 / 27.0 * a * sq_a - a * b / 3.0 + c) / 2.0;
+
+    // Cardano's formula
+    let cb_p = p * p * p;
+    let d = q * q + cb_p;
+
+    let mut roots = if is_zero(d) {
+        if is_zero(q) {
+            // One triple root
+            vec![0.0]
+        } else {
+            // One single and one double root
+            let u = (-q).cbrt();
+            vec![2.0 * u, -u]
+        }
+    } else if d < 0.0 {
+        // Three real roots
+        let phi = (-q / (-cb_p).sqrt()).clamp(-1.0, 1.0).acos() / 3.0;
+        let t = 2.0 * (-p).sqrt();
+        vec![
+            t * phi.cos(),
+            -t * (phi + std::f64::consts::PI / 3.0).cos(),
+            -t * (phi - std::f64::consts::PI / 3.0).cos(),
+        ]
+    } else {
+        // One real root
+        let sqrt_d = d.sqrt();
+        vec![(sqrt_d - q).cbrt() - (sqrt_d + q).cbrt()]
+    };
+
+    for root in &mut roots {
+        *root -= a / 3.0;
+    }
+
+    roots
+}
+
+// Roots are sorted in increasing order
+pub fn solve_quartic(c: [f64; 5]) -> Vec<f64> {
+    // Normal form x^4 + Ax^3 + Bx^2 + Cx + D = 0
+    let a = c[3] / c[4];
+    let b = c[2] / c[4];
+    let cc = c[1] / c[4];
+    let d = c[0] / c[4];
+
+    // Substitute x = y - A/4 to eliminate the cubic term: y^4 + py^2 + qy + r = 0
+    let sq_a = a * a;
+    let p = -3.0 / 8.0 * sq_a + b;
+    let q = sq_a * a / 8.0 - a * b / 2.0 + cc;
+    let r = -3.0 / 256.0 * sq_a * sq_a + sq_a * b / 16.0 - a * cc / 4.0 + d;
+
+    let mut roots = if is_zero(r) {
+        // No absolute term: y(y^3 + py + q) = 0
+        let mut roots = solve_cubic([q, p, 0.0, 1.0]);
+        roots.push(0.0);
+        roots
+    } else {
+        // Take one root of the resolvent cubic to split into two quadratics
+        let z = solve_cubic([r * p / 2.0 - q * q / 8.0, -r, -p / 2.0, 1.0])[0];
+
+        let u = z * z - r;
+        let v = 2.0 * z - p;
+        let u = if is_zero(u) {
+            0.0
+        } else if u > 0.0 {
+            u.sqrt()
+        } else {
+            return vec![];
+        };
+        let v = if is_zero(v) {
+            0.0
+        } else if v > 0.0 {
+            v.sqrt()
+        } else {
+            return vec![];
+        };
+
+        let sign = if q < 0.0 { -1.0 } else { 1.0 };
+        let mut roots = solve_quadratic([z - u, sign * v, 1.0]);
+        roots.extend(solve_quadratic([z + u, -sign * v, 1.0]));
+        roots
+    };
+
+    // Resubstitute, then polish with a couple of Newton steps on the original polynomial
+    let eval = |x: f64| (((c[4] * x + c[3]) * x + c[2]) * x + c[1]) * x + c[0];
+    let derivative = |x: f64| ((4.0 * c[4] * x + 3.0 * c[3]) * x + 2.0 * c[2]) * x + c[1];
+    for root in &mut roots {
+        *root -= a / 4.0;
+        for _ in 0..2 {
+            let slope = derivative(*root);
+            if slope.abs() > EPSILON {
+                *root -= eval(*root) / slope;
+            }
+        }
+    }
+
+    roots.sort_by(|a, b| a.total_cmp(b));
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_roots(roots: Vec<f64>, expected: &[f64]) {
+        assert_eq!(roots.len(), expected.len(), "roots {:?}", roots);
+        for (root, expected) in roots.iter().zip(expected) {
+            assert!((root - expected).abs() < 1e-6, "roots {:?}", roots);
+        }
+    }
+
+    #[test]
+    fn quadratic() {
+        // (x - 1)(x + 2)
+        assert_roots(solve_quadratic([-2.0, 1.0, 1.0]), &[-2.0, 1.0]);
+        // x^2 + 1
+        assert_roots(solve_quadratic([1.0, 0.0, 1.0]), &[]);
+    }
+
+    #[test]
+    fn cubic() {
+        // (x - 1)(x - 2)(x - 3)
+        let mut roots = solve_cubic([-6.0, 11.0, -6.0, 1.0]);
+        roots.sort_by(|a, b| a.total_cmp(b));
+        assert_roots(roots, &[1.0, 2.0, 3.0]);
+        // (x - 2)(x^2 + 1)
+        assert_roots(solve_cubic([-2.0, 1.0, -2.0, 1.0]), &[2.0]);
+    }
+
+    #[test]
+    fn quartic() {
+        // (x - 1)(x - 2)(x + 3)(x - 0.5)
+        assert_roots(
+            solve_quartic([-3.0, 9.5, -7.0, -0.5, 1.0]),
+            &[-3.0, 0.5, 1.0, 2.0],
+        );
+        // (x^2 + 1)(x^2 + 2)
+        assert_roots(solve_quartic([2.0, 0.0, 3.0, 0.0, 1.0]), &[]);
+        // (x - 1)(x + 1)(x^2 + 1)
+        assert_roots(solve_quartic([-1.0, 0.0, 0.0, 0.0, 1.0]), &[-1.0, 1.0]);
+    }
+}
@@ -3,6 +3,8 @@ use crate::math::Vec3;
 pub struct Ray {
     pub origin: Vec3,
     pub direction: Vec3,
+    // When the ray was sent, between the camera's shutter open and close times
+    pub time: f32,
 }
 
 impl Ray {
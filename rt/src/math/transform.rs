@@ -0,0 +1,269 @@
+use std::ops::Mul;
+
+use crate::math::Vec3;
+
+// Row major, m[row][col]
+type Matrix = [[f32; 4]; 4];
+
+const IDENTITY: Matrix = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut m = [[0.0; 4]; 4];
+    for (row, m_row) in m.iter_mut().enumerate() {
+        for (col, value) in m_row.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[row][k] * b[k][col]).sum();
+        }
+    }
+
+    m
+}
+
+// Inverse of an affine matrix, i.e. one with a bottom row of 0, 0, 0, 1
+fn affine_inverse(m: &Matrix) -> Matrix {
+    // Inverse of the upper 3x3 is the transposed cofactor matrix over the determinant
+    let column = |col: usize| Vec3::new(m[0][col], m[1][col], m[2][col]);
+    let (a, b, c) = (column(0), column(1), column(2));
+    let rows = [b.cross(&c), c.cross(&a), a.cross(&b)];
+    let inv_det = 1.0 / a.dot(&rows[0]);
+
+    let mut inverse = IDENTITY;
+    for (i, row) in rows.iter().enumerate() {
+        let row = inv_det * row;
+        inverse[i][0] = row.x;
+        inverse[i][1] = row.y;
+        inverse[i][2] = row.z;
+    }
+
+    // Then undo the translation
+    let translation = Vec3::new(m[0][3], m[1][3], m[2][3]);
+    for row in inverse.iter_mut().take(3) {
+        row[3] = -(row[0] * translation.x + row[1] * translation.y + row[2] * translation.z);
+    }
+
+    inverse
+}
+
+// An affine transform. The inverse is kept alongside so rays can be taken
+// into object space without inverting per ray
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    matrix: Matrix,
+    inverse: Matrix,
+}
+
+impl Transform {
+    pub fn identity() -> Transform {
+        Transform {
+            matrix: IDENTITY,
+            inverse: IDENTITY,
+        }
+    }
+
+    pub fn translate(offset: Vec3) -> Transform {
+        let mut matrix = IDENTITY;
+        let mut inverse = IDENTITY;
+        for i in 0..3 {
+            matrix[i][3] = offset[i];
+            inverse[i][3] = -offset[i];
+        }
+
+        Transform { matrix, inverse }
+    }
+
+    // Scale factors along each axis, they must not be 0
+    pub fn scale(factors: Vec3) -> Transform {
+        let mut matrix = IDENTITY;
+        let mut inverse = IDENTITY;
+        for i in 0..3 {
+            matrix[i][i] = factors[i];
+            inverse[i][i] = 1.0 / factors[i];
+        }
+
+        Transform { matrix, inverse }
+    }
+
+    // Counter clockwise rotation around axis when looking down it
+    pub fn rotate(axis: Vec3, degrees: f32) -> Transform {
+        let a = axis.make_unit();
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let k = 1.0 - cos;
+
+        // Rodrigues' rotation formula
+        let matrix = [
+            [
+                cos + a.x * a.x * k,
+                a.x * a.y * k - a.z * sin,
+                a.x * a.z * k + a.y * sin,
+                0.0,
+            ],
+            [
+                a.y * a.x * k + a.z * sin,
+                cos + a.y * a.y * k,
+                a.y * a.z * k - a.x * sin,
+                0.0,
+            ],
+            [
+                a.z * a.x * k - a.y * sin,
+                a.z * a.y * k + a.x * sin,
+                cos + a.z * a.z * k,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+
+        // Rotations are orthogonal so the inverse is the transpose
+        let mut inverse = IDENTITY;
+        for (row, inverse_row) in inverse.iter_mut().enumerate().take(3) {
+            for (col, value) in inverse_row.iter_mut().enumerate().take(3) {
+                *value = matrix[col][row];
+            }
+        }
+
+        Transform { matrix, inverse }
+    }
+
+    // From a column major matrix, e.g. as stored in glTF. It must be affine
+    pub fn from_column_major(columns: [[f32; 4]; 4]) -> Transform {
+        let mut matrix = IDENTITY;
+        for (col, column) in columns.iter().enumerate() {
+            for (row, value) in column.iter().enumerate() {
+                matrix[row][col] = *value;
+            }
+        }
+
+        Transform {
+            matrix,
+            inverse: affine_inverse(&matrix),
+        }
+    }
+
+    pub fn inverse(&self) -> Transform {
+        Transform {
+            matrix: self.inverse,
+            inverse: self.matrix,
+        }
+    }
+
+    pub fn transform_vector(&self, v: &Vec3) -> Vec3 {
+        let m = &self.matrix;
+        Vec3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+
+    pub fn transform_point(&self, p: &Vec3) -> Vec3 {
+        let m = &self.matrix;
+        self.transform_vector(p) + Vec3::new(m[0][3], m[1][3], m[2][3])
+    }
+
+    // Normals are transformed by the inverse transpose so they stay perpendicular
+    // to the surface. The result isn't unit length
+    pub fn transform_normal(&self, n: &Vec3) -> Vec3 {
+        let m = &self.inverse;
+        Vec3::new(
+            m[0][0] * n.x + m[1][0] * n.y + m[2][0] * n.z,
+            m[0][1] * n.x + m[1][1] * n.y + m[2][1] * n.z,
+            m[0][2] * n.x + m[1][2] * n.y + m[2][2] * n.z,
+        )
+    }
+
+    // Determinant of the upper 3x3, negative if the transform mirrors
+    pub fn determinant(&self) -> f32 {
+        let column = |col: usize| {
+            Vec3::new(
+                self.matrix[0][col],
+                self.matrix[1][col],
+                self.matrix[2][col],
+            )
+        };
+        column(0).dot(&column(1).cross(&column(2)))
+    }
+}
+
+// a * b applies b first, then a
+impl Mul for Transform {
+    type Output = Transform;
+
+    fn mul(self, other: Transform) -> Transform {
+        Transform {
+            matrix: multiply(&self.matrix, &other.matrix),
+            inverse: multiply(&other.inverse, &self.inverse),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::assert_near;
+
+    #[test]
+    fn transform_point() {
+        let p = Vec3::new(1.0, 2.0, 3.0);
+
+        let translate = Transform::translate(Vec3::new(1.0, -1.0, 0.5));
+        assert_near(translate.transform_point(&p), Vec3::new(2.0, 1.0, 3.5));
+        // Vectors aren't translated
+        assert_near(translate.transform_vector(&p), p);
+
+        let scale = Transform::scale(Vec3::new(2.0, 3.0, -1.0));
+        assert_near(scale.transform_point(&p), Vec3::new(2.0, 6.0, -3.0));
+
+        // Quarter turn around y takes x to -z
+        let rotate = Transform::rotate(Vec3::new(0.0, 1.0, 0.0), 90.0);
+        assert_near(
+            rotate.transform_point(&Vec3::new(1.0, 0.0, 0.0)),
+            Vec3::new(0.0, 0.0, -1.0),
+        );
+
+        // Scale first, then translate
+        let combined = translate * scale;
+        assert_near(combined.transform_point(&p), Vec3::new(3.0, 5.0, -2.5));
+    }
+
+    #[test]
+    fn inverse() {
+        let transform = Transform::translate(Vec3::new(1.0, 2.0, 3.0))
+            * Transform::rotate(Vec3::new(1.0, 1.0, 0.0), 30.0)
+            * Transform::scale(Vec3::new(2.0, 0.5, 1.0));
+        let p = Vec3::new(-1.0, 4.0, 2.0);
+        assert_near(
+            transform
+                .inverse()
+                .transform_point(&transform.transform_point(&p)),
+            p,
+        );
+
+        // Computed inverse matches the composed one
+        let mut columns = [[0.0; 4]; 4];
+        for (col, column) in columns.iter_mut().enumerate() {
+            for (row, value) in column.iter_mut().enumerate() {
+                *value = transform.matrix[row][col];
+            }
+        }
+        let from_columns = Transform::from_column_major(columns);
+        assert_near(
+            from_columns.inverse().transform_point(&p),
+            transform.inverse().transform_point(&p),
+        );
+    }
+
+    #[test]
+    fn transform_normal() {
+        // Squashing a 45 degree slope along x makes it steeper
+        let scale = Transform::scale(Vec3::new(0.5, 1.0, 1.0));
+        let normal = scale
+            .transform_normal(&Vec3::new(1.0, 1.0, 0.0))
+            .make_unit();
+        assert_near(normal, Vec3::new(2.0, 1.0, 0.0).make_unit());
+
+        assert!(Transform::scale(Vec3::new(-1.0, 1.0, 1.0)).determinant() < 0.0);
+    }
+}